@@ -2,12 +2,15 @@
 pragma solidity ^0.8.19;
 
 contract Guard {
-    mapping(address => bool) allowlist;
+    mapping(address => bool) public allowlist;
     address public immutable owner;
 
+    event AllowlistUpdated(address indexed account, bool allowed);
+
     constructor() {
         owner = msg.sender;
         allowlist[msg.sender] = true;
+        emit AllowlistUpdated(msg.sender, true);
     }
 
     modifier guard() {
@@ -19,4 +22,28 @@ contract Guard {
         require(msg.sender == owner, "Guard: not owner");
         _;
     }
+
+    /***
+     * @notice Let accounts snipe, buy and sell through the contract
+     * @dev Only callable by the owner
+     */
+    function addToAllowlist(address[] calldata accounts) external onlyowner {
+        for (uint256 i; i < accounts.length; i++) {
+            allowlist[accounts[i]] = true;
+            emit AllowlistUpdated(accounts[i], true);
+        }
+    }
+
+    /***
+     * @notice Stop accounts from sniping, buying and selling
+     * @dev Only callable by the owner
+     */
+    function removeFromAllowlist(
+        address[] calldata accounts
+    ) external onlyowner {
+        for (uint256 i; i < accounts.length; i++) {
+            allowlist[accounts[i]] = false;
+            emit AllowlistUpdated(accounts[i], false);
+        }
+    }
 }
@@ -12,8 +12,6 @@ contract Sniper is Guard {
 
     constructor() {
         ft = FriendtechSharesV1(0xCF205808Ed36593aa40a44F10c7f7C2F67d4A4d4);
-        address EOA = address(0);
-        allowlist[EOA] = true;
     }
 
     /***
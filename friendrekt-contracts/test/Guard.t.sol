@@ -0,0 +1,81 @@
+// SPDX-License-Identifier: UNLICENSED
+pragma solidity ^0.8.13;
+
+import {Test} from "forge-std/Test.sol";
+import {Sniper} from "../src/Sniper.sol";
+
+contract GuardTest is Test {
+    event AllowlistUpdated(address indexed account, bool allowed);
+
+    Sniper sniper;
+    address hot = address(0xB0B);
+    address stranger = address(0xBAD);
+
+    function setUp() public {
+        sniper = new Sniper();
+    }
+
+    function accounts(address account) internal pure returns (address[] memory list) {
+        list = new address[](1);
+        list[0] = account;
+    }
+
+    function testDeployerIsOwnerAndAllowlisted() public {
+        assertEq(sniper.owner(), address(this));
+        assertTrue(sniper.allowlist(address(this)));
+    }
+
+    function testConstructorEmitsAllowlistUpdated() public {
+        vm.expectEmit(true, false, false, true);
+        emit AllowlistUpdated(address(this), true);
+        new Sniper();
+    }
+
+    function testZeroAddressIsNotAllowlisted() public {
+        assertFalse(sniper.allowlist(address(0)));
+    }
+
+    function testOwnerAddsAndRemoves() public {
+        vm.expectEmit(true, false, false, true);
+        emit AllowlistUpdated(hot, true);
+        sniper.addToAllowlist(accounts(hot));
+        assertTrue(sniper.allowlist(hot));
+
+        vm.expectEmit(true, false, false, true);
+        emit AllowlistUpdated(hot, false);
+        sniper.removeFromAllowlist(accounts(hot));
+        assertFalse(sniper.allowlist(hot));
+    }
+
+    function testOnlyOwnerAdds() public {
+        vm.prank(stranger);
+        vm.expectRevert("Guard: not owner");
+        sniper.addToAllowlist(accounts(stranger));
+        assertFalse(sniper.allowlist(stranger));
+    }
+
+    function testOnlyOwnerRemoves() public {
+        sniper.addToAllowlist(accounts(hot));
+
+        // not even an allowlisted account
+        vm.prank(hot);
+        vm.expectRevert("Guard: not owner");
+        sniper.removeFromAllowlist(accounts(hot));
+        assertTrue(sniper.allowlist(hot));
+    }
+
+    function testUnlistedCallerReverts() public {
+        vm.prank(stranger);
+        vm.expectRevert("Guard: not allowlisted");
+        sniper.sellShares(address(1), 1);
+    }
+
+    function testRemovedCallerReverts() public {
+        sniper.addToAllowlist(accounts(hot));
+        sniper.removeFromAllowlist(accounts(hot));
+
+        vm.prank(hot);
+        vm.expectRevert("Guard: not allowlisted");
+        sniper.sellShares(address(1), 1);
+    }
+}
@@ -1,11 +1,131 @@
-//use ethers_solc::{Project, ProjectPathsConfig};
 use ethers_contract_abigen::MultiAbigen;
+use ethers_core::{abi::Abi, utils::hex};
+use serde_json::Value;
+use std::{env, fmt::Write, fs, path::Path};
 
+const CONTRACTS: &str = "../friendrekt-contracts";
+// where `forge build` (or the `solc` feature) leaves the compiled Sniper
+const SNIPER_ARTIFACT: &str = "../friendrekt-contracts/out/Sniper.sol/Sniper.json";
+// the friend.tech ABI isn't ours to compile, it only lives here
+const ABI: &str = "abi";
+const BINDINGS: &str = "src/bindings";
+
+// Set to fail the build when the contracts haven't been compiled, rather
+// than skip checking the ABI and leave `deploy` without a Sniper to deploy.
+// CI always does.
+const REQUIRE: &str = "FRIENDREKT_REQUIRE_ARTIFACTS";
+
+// abi/ and src/bindings are only ever checked here, `cargo xtask bindings`
+// updates them.
 fn main() {
-    // configure the project with all its paths, solc, cache etc.
-    println!("Building ethers contract");
-    let gen = MultiAbigen::from_json_files("./abi").unwrap();
-    let bindings = gen.build().unwrap();
-    bindings.write_to_module("src/bindings", false).unwrap();
-    println!("cargo:rerun-if-changed=abi/");
+    println!("cargo:rerun-if-changed={}", ABI);
+    println!("cargo:rerun-if-changed={}", BINDINGS);
+    println!("cargo:rerun-if-env-changed={}", REQUIRE);
+    println!("cargo:rerun-if-env-changed=CI");
+    let require = env::var_os(REQUIRE).is_some() || env::var_os("CI").is_some();
+
+    #[cfg(feature = "solc")]
+    compile();
+    // also picks up `forge build` writing out/
+    #[cfg(not(feature = "solc"))]
+    println!("cargo:rerun-if-changed={}", CONTRACTS);
+
+    let artifact = read_artifact(Path::new(SNIPER_ARTIFACT));
+    match &artifact {
+        Some(artifact) => check_abi(&Path::new(ABI).join("sniper.json"), &artifact["abi"]),
+        None if require => panic!(
+            "{} doesn't exist, build the contracts with `forge build` or the solc feature",
+            SNIPER_ARTIFACT
+        ),
+        None => println!(
+            "cargo:warning={} doesn't exist, so abi/sniper.json is unchecked and deploy has no Sniper to deploy. \
+             Build the contracts with `forge build` or the solc feature.",
+            SNIPER_ARTIFACT
+        ),
+    }
+    write_bytecode(artifact.as_ref());
+
+    // the committed bindings have to be what abigen makes of the ABIs
+    let bindings = MultiAbigen::from_json_files(ABI).unwrap().build().unwrap();
+    if let Err(e) = bindings.ensure_consistent_module(BINDINGS, false) {
+        panic!(
+            "{} is out of date with {}/, run `cargo xtask bindings` to regenerate it: {}",
+            BINDINGS, ABI, e
+        );
+    }
+}
+
+// Compile friendrekt-contracts into out/ like `forge build` would, with the
+// solc on PATH. Rebuilds only when the sources change, not the artifacts it
+// writes.
+#[cfg(feature = "solc")]
+fn compile() {
+    use ethers_solc::{utils::source_files, Project, ProjectPathsConfig};
+
+    let paths = ProjectPathsConfig::dapptools(CONTRACTS).unwrap();
+    let project = Project::builder().paths(paths).build().unwrap();
+    project.rerun_if_sources_changed();
+    // only src/, the forge tests in test/ need forge-std
+    let output = project
+        .compile_files(source_files(&project.paths.sources))
+        .unwrap_or_else(|e| panic!("failed to compile {}: {}", CONTRACTS, e));
+    if output.has_compiler_errors() {
+        panic!("failed to compile {}:\n{}", CONTRACTS, output);
+    }
+}
+
+fn read_artifact(path: &Path) -> Option<Value> {
+    let json = fs::read_to_string(path).ok()?;
+    Some(serde_json::from_str(&json).unwrap_or_else(|e| panic!("unreadable {:?}: {}", path, e)))
+}
+
+// The ABI in `path` has to be the compiled contract's, or the bindings would
+// encode calls it doesn't have.
+fn check_abi(path: &Path, compiled: &Value) {
+    let abi = |value: &Value| -> Abi {
+        serde_json::from_value(value.clone())
+            .unwrap_or_else(|e| panic!("invalid ABI for {:?}: {}", path, e))
+    };
+    let committed: Value = serde_json::from_str(&fs::read_to_string(path).unwrap()).unwrap();
+    if abi(&committed) != abi(compiled) {
+        panic!(
+            "{:?} doesn't match the ABI compiled from {}, run `cargo xtask bindings` to take the compiled one",
+            path, CONTRACTS
+        );
+    }
+}
+
+// Bundle the creation and runtime code for src/bytecode.rs, or None when the
+// contracts haven't been compiled.
+fn write_bytecode(artifact: Option<&Value>) {
+    let mut out = String::new();
+    match artifact {
+        Some(artifact) => {
+            let code = |field: &str| {
+                let object = artifact[field]["object"].as_str().unwrap_or_default();
+                if object.is_empty() || hex::decode(object).is_err() {
+                    panic!("{} has no {} ready to deploy", SNIPER_ARTIFACT, field);
+                }
+                object.to_string()
+            };
+            let mut immutables = String::new();
+            let references = artifact["deployedBytecode"]["immutableReferences"].as_object();
+            for ranges in references.into_iter().flat_map(|r| r.values()) {
+                for range in ranges.as_array().into_iter().flatten() {
+                    write!(immutables, "({}, {}), ", range["start"], range["length"]).unwrap();
+                }
+            }
+            writeln!(
+                out,
+                "pub const SNIPER: Option<Artifact> = Some(Artifact {{ bytecode: {:?}, deployed_bytecode: {:?}, immutables: &[{}] }});",
+                code("bytecode"),
+                code("deployedBytecode"),
+                immutables
+            )
+            .unwrap();
+        }
+        None => writeln!(out, "pub const SNIPER: Option<Artifact> = None;").unwrap(),
+    }
+    let path = Path::new(&env::var("OUT_DIR").unwrap()).join("bytecode.rs");
+    fs::write(path, out).unwrap();
 }
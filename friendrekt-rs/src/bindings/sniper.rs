@@ -17,6 +17,58 @@ pub mod sniper {
                 inputs: ::std::vec![],
             }),
             functions: ::core::convert::From::from([
+                (
+                    ::std::borrow::ToOwned::to_owned("addToAllowlist"),
+                    ::std::vec![
+                        ::ethers::core::abi::ethabi::Function {
+                            name: ::std::borrow::ToOwned::to_owned("addToAllowlist"),
+                            inputs: ::std::vec![
+                                ::ethers::core::abi::ethabi::Param {
+                                    name: ::std::borrow::ToOwned::to_owned("accounts"),
+                                    kind: ::ethers::core::abi::ethabi::ParamType::Array(
+                                        ::std::boxed::Box::new(
+                                            ::ethers::core::abi::ethabi::ParamType::Address,
+                                        ),
+                                    ),
+                                    internal_type: ::core::option::Option::Some(
+                                        ::std::borrow::ToOwned::to_owned("address[]"),
+                                    ),
+                                },
+                            ],
+                            outputs: ::std::vec![],
+                            constant: ::core::option::Option::None,
+                            state_mutability: ::ethers::core::abi::ethabi::StateMutability::NonPayable,
+                        },
+                    ],
+                ),
+                (
+                    ::std::borrow::ToOwned::to_owned("allowlist"),
+                    ::std::vec![
+                        ::ethers::core::abi::ethabi::Function {
+                            name: ::std::borrow::ToOwned::to_owned("allowlist"),
+                            inputs: ::std::vec![
+                                ::ethers::core::abi::ethabi::Param {
+                                    name: ::std::string::String::new(),
+                                    kind: ::ethers::core::abi::ethabi::ParamType::Address,
+                                    internal_type: ::core::option::Option::Some(
+                                        ::std::borrow::ToOwned::to_owned("address"),
+                                    ),
+                                },
+                            ],
+                            outputs: ::std::vec![
+                                ::ethers::core::abi::ethabi::Param {
+                                    name: ::std::string::String::new(),
+                                    kind: ::ethers::core::abi::ethabi::ParamType::Bool,
+                                    internal_type: ::core::option::Option::Some(
+                                        ::std::borrow::ToOwned::to_owned("bool"),
+                                    ),
+                                },
+                            ],
+                            constant: ::core::option::Option::None,
+                            state_mutability: ::ethers::core::abi::ethabi::StateMutability::View,
+                        },
+                    ],
+                ),
                 (
                     ::std::borrow::ToOwned::to_owned("buyShares"),
                     ::std::vec![
@@ -170,6 +222,32 @@ pub mod sniper {
                         },
                     ],
                 ),
+                (
+                    ::std::borrow::ToOwned::to_owned("removeFromAllowlist"),
+                    ::std::vec![
+                        ::ethers::core::abi::ethabi::Function {
+                            name: ::std::borrow::ToOwned::to_owned(
+                                "removeFromAllowlist",
+                            ),
+                            inputs: ::std::vec![
+                                ::ethers::core::abi::ethabi::Param {
+                                    name: ::std::borrow::ToOwned::to_owned("accounts"),
+                                    kind: ::ethers::core::abi::ethabi::ParamType::Array(
+                                        ::std::boxed::Box::new(
+                                            ::ethers::core::abi::ethabi::ParamType::Address,
+                                        ),
+                                    ),
+                                    internal_type: ::core::option::Option::Some(
+                                        ::std::borrow::ToOwned::to_owned("address[]"),
+                                    ),
+                                },
+                            ],
+                            outputs: ::std::vec![],
+                            constant: ::core::option::Option::None,
+                            state_mutability: ::ethers::core::abi::ethabi::StateMutability::NonPayable,
+                        },
+                    ],
+                ),
                 (
                     ::std::borrow::ToOwned::to_owned("returnETH"),
                     ::std::vec![
@@ -232,7 +310,29 @@ pub mod sniper {
                     ],
                 ),
             ]),
-            events: ::std::collections::BTreeMap::new(),
+            events: ::core::convert::From::from([
+                (
+                    ::std::borrow::ToOwned::to_owned("AllowlistUpdated"),
+                    ::std::vec![
+                        ::ethers::core::abi::ethabi::Event {
+                            name: ::std::borrow::ToOwned::to_owned("AllowlistUpdated"),
+                            inputs: ::std::vec![
+                                ::ethers::core::abi::ethabi::EventParam {
+                                    name: ::std::borrow::ToOwned::to_owned("account"),
+                                    kind: ::ethers::core::abi::ethabi::ParamType::Address,
+                                    indexed: true,
+                                },
+                                ::ethers::core::abi::ethabi::EventParam {
+                                    name: ::std::borrow::ToOwned::to_owned("allowed"),
+                                    kind: ::ethers::core::abi::ethabi::ParamType::Bool,
+                                    indexed: false,
+                                },
+                            ],
+                            anonymous: false,
+                        },
+                    ],
+                ),
+            ]),
             errors: ::std::collections::BTreeMap::new(),
             receive: true,
             fallback: false,
@@ -279,6 +379,24 @@ pub mod sniper {
                 ),
             )
         }
+        ///Calls the contract's `addToAllowlist` (0x5207c273) function
+        pub fn add_to_allowlist(
+            &self,
+            accounts: ::std::vec::Vec<::ethers::core::types::Address>,
+        ) -> ::ethers::contract::builders::ContractCall<M, ()> {
+            self.0
+                .method_hash([82, 7, 194, 115], accounts)
+                .expect("method not found (this should never happen)")
+        }
+        ///Calls the contract's `allowlist` (0xa7cd52cb) function
+        pub fn allowlist(
+            &self,
+            p0: ::ethers::core::types::Address,
+        ) -> ::ethers::contract::builders::ContractCall<M, bool> {
+            self.0
+                .method_hash([167, 205, 82, 203], p0)
+                .expect("method not found (this should never happen)")
+        }
         ///Calls the contract's `buyShares` (0x6945b123) function
         pub fn buy_shares(
             &self,
@@ -333,6 +451,15 @@ pub mod sniper {
                 .method_hash([141, 165, 203, 91], ())
                 .expect("method not found (this should never happen)")
         }
+        ///Calls the contract's `removeFromAllowlist` (0x104b6cb7) function
+        pub fn remove_from_allowlist(
+            &self,
+            accounts: ::std::vec::Vec<::ethers::core::types::Address>,
+        ) -> ::ethers::contract::builders::ContractCall<M, ()> {
+            self.0
+                .method_hash([16, 75, 108, 183], accounts)
+                .expect("method not found (this should never happen)")
+        }
         ///Calls the contract's `returnETH` (0x2aa918cb) function
         pub fn return_eth(&self) -> ::ethers::contract::builders::ContractCall<M, ()> {
             self.0
@@ -358,6 +485,26 @@ pub mod sniper {
                 .method_hash([220, 159, 102, 208], ft)
                 .expect("method not found (this should never happen)")
         }
+        ///Gets the contract's `AllowlistUpdated` event
+        pub fn allowlist_updated_filter(
+            &self,
+        ) -> ::ethers::contract::builders::Event<
+            ::std::sync::Arc<M>,
+            M,
+            AllowlistUpdatedFilter,
+        > {
+            self.0.event()
+        }
+        /// Returns an `Event` builder for all the events of this contract.
+        pub fn events(
+            &self,
+        ) -> ::ethers::contract::builders::Event<
+            ::std::sync::Arc<M>,
+            M,
+            AllowlistUpdatedFilter,
+        > {
+            self.0.event_with_filter(::core::default::Default::default())
+        }
     }
     impl<M: ::ethers::providers::Middleware> From<::ethers::contract::Contract<M>>
     for sniper<M> {
@@ -365,6 +512,50 @@ pub mod sniper {
             Self::new(contract.address(), contract.client())
         }
     }
+    #[derive(
+        Clone,
+        ::ethers::contract::EthEvent,
+        ::ethers::contract::EthDisplay,
+        Default,
+        Debug,
+        PartialEq,
+        Eq,
+        Hash
+    )]
+    #[ethevent(name = "AllowlistUpdated", abi = "AllowlistUpdated(address,bool)")]
+    pub struct AllowlistUpdatedFilter {
+        #[ethevent(indexed)]
+        pub account: ::ethers::core::types::Address,
+        pub allowed: bool,
+    }
+    ///Container type for all input parameters for the `addToAllowlist` function with signature `addToAllowlist(address[])` and selector `0x5207c273`
+    #[derive(
+        Clone,
+        ::ethers::contract::EthCall,
+        ::ethers::contract::EthDisplay,
+        Default,
+        Debug,
+        PartialEq,
+        Eq,
+        Hash
+    )]
+    #[ethcall(name = "addToAllowlist", abi = "addToAllowlist(address[])")]
+    pub struct AddToAllowlistCall {
+        pub accounts: ::std::vec::Vec<::ethers::core::types::Address>,
+    }
+    ///Container type for all input parameters for the `allowlist` function with signature `allowlist(address)` and selector `0xa7cd52cb`
+    #[derive(
+        Clone,
+        ::ethers::contract::EthCall,
+        ::ethers::contract::EthDisplay,
+        Default,
+        Debug,
+        PartialEq,
+        Eq,
+        Hash
+    )]
+    #[ethcall(name = "allowlist", abi = "allowlist(address)")]
+    pub struct AllowlistCall(pub ::ethers::core::types::Address);
     ///Container type for all input parameters for the `buyShares` function with signature `buyShares(address,uint256)` and selector `0x6945b123`
     #[derive(
         Clone,
@@ -444,6 +635,21 @@ pub mod sniper {
     )]
     #[ethcall(name = "owner", abi = "owner()")]
     pub struct OwnerCall;
+    ///Container type for all input parameters for the `removeFromAllowlist` function with signature `removeFromAllowlist(address[])` and selector `0x104b6cb7`
+    #[derive(
+        Clone,
+        ::ethers::contract::EthCall,
+        ::ethers::contract::EthDisplay,
+        Default,
+        Debug,
+        PartialEq,
+        Eq,
+        Hash
+    )]
+    #[ethcall(name = "removeFromAllowlist", abi = "removeFromAllowlist(address[])")]
+    pub struct RemoveFromAllowlistCall {
+        pub accounts: ::std::vec::Vec<::ethers::core::types::Address>,
+    }
     ///Container type for all input parameters for the `returnETH` function with signature `returnETH()` and selector `0x2aa918cb`
     #[derive(
         Clone,
@@ -491,11 +697,14 @@ pub mod sniper {
     ///Container type for all of the contract's call
     #[derive(Clone, ::ethers::contract::EthAbiType, Debug, PartialEq, Eq, Hash)]
     pub enum sniperCalls {
+        AddToAllowlist(AddToAllowlistCall),
+        Allowlist(AllowlistCall),
         BuyShares(BuySharesCall),
         DoSnipeManyShares(DoSnipeManySharesCall),
         Execute(ExecuteCall),
         Ft(FtCall),
         Owner(OwnerCall),
+        RemoveFromAllowlist(RemoveFromAllowlistCall),
         ReturnETH(ReturnETHCall),
         SellShares(SellSharesCall),
         SetFt(SetFtCall),
@@ -505,38 +714,59 @@ pub mod sniper {
             data: impl AsRef<[u8]>,
         ) -> ::core::result::Result<Self, ::ethers::core::abi::AbiError> {
             let data = data.as_ref();
-            if let Ok(decoded)
-                = <BuySharesCall as ::ethers::core::abi::AbiDecode>::decode(data) {
+            if let Ok(decoded) = <AddToAllowlistCall as ::ethers::core::abi::AbiDecode>::decode(
+                data,
+            ) {
+                return Ok(Self::AddToAllowlist(decoded));
+            }
+            if let Ok(decoded) = <AllowlistCall as ::ethers::core::abi::AbiDecode>::decode(
+                data,
+            ) {
+                return Ok(Self::Allowlist(decoded));
+            }
+            if let Ok(decoded) = <BuySharesCall as ::ethers::core::abi::AbiDecode>::decode(
+                data,
+            ) {
                 return Ok(Self::BuyShares(decoded));
             }
-            if let Ok(decoded)
-                = <DoSnipeManySharesCall as ::ethers::core::abi::AbiDecode>::decode(
-                    data,
-                ) {
+            if let Ok(decoded) = <DoSnipeManySharesCall as ::ethers::core::abi::AbiDecode>::decode(
+                data,
+            ) {
                 return Ok(Self::DoSnipeManyShares(decoded));
             }
-            if let Ok(decoded)
-                = <ExecuteCall as ::ethers::core::abi::AbiDecode>::decode(data) {
+            if let Ok(decoded) = <ExecuteCall as ::ethers::core::abi::AbiDecode>::decode(
+                data,
+            ) {
                 return Ok(Self::Execute(decoded));
             }
-            if let Ok(decoded)
-                = <FtCall as ::ethers::core::abi::AbiDecode>::decode(data) {
+            if let Ok(decoded) = <FtCall as ::ethers::core::abi::AbiDecode>::decode(
+                data,
+            ) {
                 return Ok(Self::Ft(decoded));
             }
-            if let Ok(decoded)
-                = <OwnerCall as ::ethers::core::abi::AbiDecode>::decode(data) {
+            if let Ok(decoded) = <OwnerCall as ::ethers::core::abi::AbiDecode>::decode(
+                data,
+            ) {
                 return Ok(Self::Owner(decoded));
             }
-            if let Ok(decoded)
-                = <ReturnETHCall as ::ethers::core::abi::AbiDecode>::decode(data) {
+            if let Ok(decoded) = <RemoveFromAllowlistCall as ::ethers::core::abi::AbiDecode>::decode(
+                data,
+            ) {
+                return Ok(Self::RemoveFromAllowlist(decoded));
+            }
+            if let Ok(decoded) = <ReturnETHCall as ::ethers::core::abi::AbiDecode>::decode(
+                data,
+            ) {
                 return Ok(Self::ReturnETH(decoded));
             }
-            if let Ok(decoded)
-                = <SellSharesCall as ::ethers::core::abi::AbiDecode>::decode(data) {
+            if let Ok(decoded) = <SellSharesCall as ::ethers::core::abi::AbiDecode>::decode(
+                data,
+            ) {
                 return Ok(Self::SellShares(decoded));
             }
-            if let Ok(decoded)
-                = <SetFtCall as ::ethers::core::abi::AbiDecode>::decode(data) {
+            if let Ok(decoded) = <SetFtCall as ::ethers::core::abi::AbiDecode>::decode(
+                data,
+            ) {
                 return Ok(Self::SetFt(decoded));
             }
             Err(::ethers::core::abi::Error::InvalidData.into())
@@ -545,6 +775,12 @@ pub mod sniper {
     impl ::ethers::core::abi::AbiEncode for sniperCalls {
         fn encode(self) -> Vec<u8> {
             match self {
+                Self::AddToAllowlist(element) => {
+                    ::ethers::core::abi::AbiEncode::encode(element)
+                }
+                Self::Allowlist(element) => {
+                    ::ethers::core::abi::AbiEncode::encode(element)
+                }
                 Self::BuyShares(element) => {
                     ::ethers::core::abi::AbiEncode::encode(element)
                 }
@@ -554,6 +790,9 @@ pub mod sniper {
                 Self::Execute(element) => ::ethers::core::abi::AbiEncode::encode(element),
                 Self::Ft(element) => ::ethers::core::abi::AbiEncode::encode(element),
                 Self::Owner(element) => ::ethers::core::abi::AbiEncode::encode(element),
+                Self::RemoveFromAllowlist(element) => {
+                    ::ethers::core::abi::AbiEncode::encode(element)
+                }
                 Self::ReturnETH(element) => {
                     ::ethers::core::abi::AbiEncode::encode(element)
                 }
@@ -567,17 +806,32 @@ pub mod sniper {
     impl ::core::fmt::Display for sniperCalls {
         fn fmt(&self, f: &mut ::core::fmt::Formatter<'_>) -> ::core::fmt::Result {
             match self {
+                Self::AddToAllowlist(element) => ::core::fmt::Display::fmt(element, f),
+                Self::Allowlist(element) => ::core::fmt::Display::fmt(element, f),
                 Self::BuyShares(element) => ::core::fmt::Display::fmt(element, f),
                 Self::DoSnipeManyShares(element) => ::core::fmt::Display::fmt(element, f),
                 Self::Execute(element) => ::core::fmt::Display::fmt(element, f),
                 Self::Ft(element) => ::core::fmt::Display::fmt(element, f),
                 Self::Owner(element) => ::core::fmt::Display::fmt(element, f),
+                Self::RemoveFromAllowlist(element) => {
+                    ::core::fmt::Display::fmt(element, f)
+                }
                 Self::ReturnETH(element) => ::core::fmt::Display::fmt(element, f),
                 Self::SellShares(element) => ::core::fmt::Display::fmt(element, f),
                 Self::SetFt(element) => ::core::fmt::Display::fmt(element, f),
             }
         }
     }
+    impl ::core::convert::From<AddToAllowlistCall> for sniperCalls {
+        fn from(value: AddToAllowlistCall) -> Self {
+            Self::AddToAllowlist(value)
+        }
+    }
+    impl ::core::convert::From<AllowlistCall> for sniperCalls {
+        fn from(value: AllowlistCall) -> Self {
+            Self::Allowlist(value)
+        }
+    }
     impl ::core::convert::From<BuySharesCall> for sniperCalls {
         fn from(value: BuySharesCall) -> Self {
             Self::BuyShares(value)
@@ -603,6 +857,11 @@ pub mod sniper {
             Self::Owner(value)
         }
     }
+    impl ::core::convert::From<RemoveFromAllowlistCall> for sniperCalls {
+        fn from(value: RemoveFromAllowlistCall) -> Self {
+            Self::RemoveFromAllowlist(value)
+        }
+    }
     impl ::core::convert::From<ReturnETHCall> for sniperCalls {
         fn from(value: ReturnETHCall) -> Self {
             Self::ReturnETH(value)
@@ -618,6 +877,18 @@ pub mod sniper {
             Self::SetFt(value)
         }
     }
+    ///Container type for all return fields from the `allowlist` function with signature `allowlist(address)` and selector `0xa7cd52cb`
+    #[derive(
+        Clone,
+        ::ethers::contract::EthAbiType,
+        ::ethers::contract::EthAbiCodec,
+        Default,
+        Debug,
+        PartialEq,
+        Eq,
+        Hash
+    )]
+    pub struct AllowlistReturn(pub bool);
     ///Container type for all return fields from the `ft` function with signature `ft()` and selector `0xbb1631cf`
     #[derive(
         Clone,
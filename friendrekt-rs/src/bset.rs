@@ -1,40 +1,283 @@
-use std::collections::{HashSet, VecDeque};
+use crate::metrics::METRICS;
+use std::{
+    collections::{hash_map::RandomState, BTreeMap, HashMap},
+    hash::{BuildHasher, Hash},
+    sync::{Mutex, MutexGuard},
+    time::{Duration, Instant},
+};
 
-// A bounded set is made by combining a hash set with a VecDeque.
-pub struct FIFOCache<T> {
-    set: HashSet<T>,
-    queue: VecDeque<T>,
-    limit: usize,
+// Caches are split into up to this many shards, each behind its own lock, so
+// tasks working on different keys rarely wait on each other.
+const SHARDS: usize = 16;
+
+struct Slot<V> {
+    value: V,
+    expires: Option<Instant>,
+    // position in `Shard::order`
+    used: u64,
+}
+
+struct Shard<K, V> {
+    name: &'static str,
+    slots: HashMap<K, Slot<V>>,
+    // keys by when they were last used, least recently used first
+    order: BTreeMap<u64, K>,
+    tick: u64,
+    capacity: usize,
 }
 
-impl<T: Clone + Eq + std::hash::Hash> FIFOCache<T> {
-    pub fn new(limit: usize) -> Self {
-        FIFOCache {
-            set: HashSet::new(),
-            queue: VecDeque::new(),
-            limit,
+impl<K: Clone + Eq + Hash, V> Shard<K, V> {
+    fn evicted(&self, reason: &str) {
+        METRICS
+            .cache_evictions
+            .with_label_values(&[self.name, reason])
+            .inc();
+    }
+
+    // The slot for `key` unless it has expired, in which case it's dropped.
+    fn live(&mut self, key: &K) -> Option<&mut Slot<V>> {
+        let expired = self
+            .slots
+            .get(key)?
+            .expires
+            .is_some_and(|expires| expires <= Instant::now());
+        if expired {
+            self.remove(key);
+            self.evicted("expired");
+            return None;
         }
+        self.slots.get_mut(key)
     }
 
-    pub fn insert(&mut self, value: T) {
-        // Check if the value is already in the set
-        if self.set.contains(&value) {
-            return;
+    fn touch(&mut self, key: &K) {
+        self.tick += 1;
+        if let Some(slot) = self.slots.get_mut(key) {
+            self.order.remove(&slot.used);
+            slot.used = self.tick;
+            self.order.insert(self.tick, key.clone());
         }
+    }
 
-        // Check if the cache is full and remove the oldest element if necessary
-        if self.queue.len() == self.limit {
-            if let Some(removed_value) = self.queue.pop_front() {
-                self.set.remove(&removed_value);
+    fn insert(&mut self, key: K, value: V, expires: Option<Instant>) {
+        if self.remove(&key).is_none() && self.slots.len() >= self.capacity {
+            if let Some((_, oldest)) = self.order.pop_first() {
+                self.slots.remove(&oldest);
+                METRICS.cache_entries.with_label_values(&[self.name]).dec();
+                self.evicted("capacity");
             }
         }
 
-        // Insert the new value into both the set and the queue
-        self.set.insert(value.clone());
-        self.queue.push_back(value);
+        self.tick += 1;
+        self.order.insert(self.tick, key.clone());
+        self.slots.insert(
+            key,
+            Slot {
+                value,
+                expires,
+                used: self.tick,
+            },
+        );
+        METRICS.cache_entries.with_label_values(&[self.name]).inc();
+    }
+
+    fn remove(&mut self, key: &K) -> Option<V> {
+        let slot = self.slots.remove(key)?;
+        self.order.remove(&slot.used);
+        METRICS.cache_entries.with_label_values(&[self.name]).dec();
+        Some(slot.value)
+    }
+}
+
+// A bounded map shared by many tasks. Once full, inserting evicts the least
+// recently used entry of the shard the key falls in. Entries can expire,
+// after the cache's default TTL or one given when inserting them.
+//
+// The shard locks are std mutexes: they're never held across an await, so
+// they're cheaper than tokio's and fine to take from async code.
+pub struct Cache<K, V> {
+    shards: Vec<Mutex<Shard<K, V>>>,
+    hasher: RandomState,
+    ttl: Option<Duration>,
+}
+
+impl<K: Clone + Eq + Hash, V> Cache<K, V> {
+    // `name` labels the cache's metrics.
+    pub fn new(name: &'static str, capacity: usize, ttl: Option<Duration>) -> Self {
+        let capacity = capacity.max(1);
+        let shards = capacity.min(SHARDS);
+        METRICS
+            .cache_capacity
+            .with_label_values(&[name])
+            .set(capacity as i64);
+
+        Cache {
+            shards: (0..shards)
+                .map(|_| {
+                    Mutex::new(Shard {
+                        name,
+                        slots: HashMap::new(),
+                        order: BTreeMap::new(),
+                        tick: 0,
+                        capacity: capacity.div_ceil(shards),
+                    })
+                })
+                .collect(),
+            hasher: RandomState::new(),
+            ttl,
+        }
+    }
+
+    fn shard(&self, key: &K) -> MutexGuard<'_, Shard<K, V>> {
+        let i = self.hasher.hash_one(key) as usize % self.shards.len();
+        // a panic while holding the lock can't leave a shard half updated
+        // in a way that matters for a cache
+        self.shards[i].lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    pub fn get(&self, key: &K) -> Option<V>
+    where
+        V: Clone,
+    {
+        let mut shard = self.shard(key);
+        let value = shard.live(key)?.value.clone();
+        shard.touch(key);
+        Some(value)
+    }
+
+    pub fn contains(&self, key: &K) -> bool {
+        self.shard(key).live(key).is_some()
+    }
+
+    // Insert or replace, expiring after the cache's TTL.
+    pub fn insert(&self, key: K, value: V) {
+        let expires = self.ttl.map(|ttl| Instant::now() + ttl);
+        self.shard(&key).insert(key, value, expires);
+    }
+
+    // Insert or replace, expiring after `ttl` whatever the cache's TTL.
+    pub fn insert_with_ttl(&self, key: K, value: V, ttl: Duration) {
+        self.shard(&key)
+            .insert(key, value, Some(Instant::now() + ttl));
+    }
+
+    // Insert unless there already is a live entry for `key`. Returns whether
+    // it was inserted.
+    pub fn insert_new(&self, key: K, value: V) -> bool {
+        let mut shard = self.shard(&key);
+        if shard.live(&key).is_some() {
+            return false;
+        }
+        let expires = self.ttl.map(|ttl| Instant::now() + ttl);
+        shard.insert(key, value, expires);
+        true
+    }
+
+    pub fn remove(&self, key: &K) -> Option<V> {
+        self.shard(key).remove(key)
+    }
+
+    // Entries held, counting expired ones that haven't been dropped yet.
+    pub fn size(&self) -> usize {
+        self.shards
+            .iter()
+            .map(|shard| shard.lock().unwrap_or_else(|e| e.into_inner()).slots.len())
+            .sum()
+    }
+}
+
+// The last `capacity` values seen, e.g. tx hashes we've already handled.
+pub struct SeenSet<T>(Cache<T, ()>);
+
+impl<T: Clone + Eq + Hash> SeenSet<T> {
+    pub fn new(name: &'static str, capacity: usize) -> Self {
+        SeenSet(Cache::new(name, capacity, None))
+    }
+
+    // Whether this is the first time we see `value`, remembering it if so.
+    pub fn first_seen(&self, value: T) -> bool {
+        self.0.insert_new(value, ())
+    }
+
+    // Forget `value`, so it counts as new when seen again.
+    pub fn forget(&self, value: &T) {
+        self.0.remove(value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // everything in one shard, so which entry is least recently used is
+    // known whatever the keys hash to
+    fn single_shard(capacity: usize, ttl: Option<Duration>) -> Cache<u64, u64> {
+        let mut cache = Cache::new("test", 1, ttl);
+        cache.shards[0].get_mut().unwrap().capacity = capacity;
+        cache
+    }
+
+    #[test]
+    fn full_cache_evicts_least_recently_used() {
+        let cache = single_shard(2, None);
+        cache.insert(1, 10);
+        cache.insert(2, 20);
+        // 2 is now the least recently used
+        assert_eq!(cache.get(&1), Some(10));
+        cache.insert(3, 30);
+
+        assert_eq!(cache.get(&2), None);
+        assert_eq!(cache.get(&1), Some(10));
+        assert_eq!(cache.get(&3), Some(30));
+        assert_eq!(cache.size(), 2);
+    }
+
+    #[test]
+    fn replacing_doesnt_evict() {
+        let cache = single_shard(2, None);
+        cache.insert(1, 10);
+        cache.insert(2, 20);
+        cache.insert(1, 11);
+
+        assert_eq!(cache.get(&1), Some(11));
+        assert_eq!(cache.get(&2), Some(20));
+        assert_eq!(cache.size(), 2);
+    }
+
+    #[test]
+    fn expired_entries_are_gone() {
+        let cache = single_shard(4, Some(Duration::from_secs(3600)));
+        cache.insert(1, 10);
+        cache.insert_with_ttl(2, 20, Duration::ZERO);
+
+        assert!(cache.contains(&1));
+        assert!(!cache.contains(&2));
+        assert_eq!(cache.get(&2), None);
+        // dropped once found expired
+        assert_eq!(cache.size(), 1);
+
+        cache.insert_with_ttl(3, 30, Duration::ZERO);
+        assert!(cache.insert_new(3, 31));
+        assert!(!cache.insert_new(3, 32));
+        assert_eq!(cache.get(&3), Some(31));
+    }
+
+    #[test]
+    fn capacity_is_spread_over_shards() {
+        let cache = Cache::<u64, ()>::new("test", 1000, None);
+        assert_eq!(cache.shards.len(), SHARDS);
+        for i in 0..10_000 {
+            cache.insert(i, ());
+        }
+        // each shard rounds its share up
+        assert!(cache.size() <= 1000 + SHARDS);
     }
 
-    pub fn contains(&self, value: &T) -> bool {
-        self.set.contains(value)
+    #[test]
+    fn seen_set_remembers_until_forgotten() {
+        let seen = SeenSet::new("test", 16);
+        assert!(seen.first_seen(1));
+        assert!(!seen.first_seen(1));
+        seen.forget(&1);
+        assert!(seen.first_seen(1));
     }
 }
@@ -0,0 +1,74 @@
+use ethers::{types::Bytes, utils::hex};
+
+// A contract as compiled from friendrekt-contracts.
+pub struct Artifact {
+    // creation code, what gets deployed
+    bytecode: &'static str,
+    // runtime code, with the ranges each deployment writes its immutables to
+    deployed_bytecode: &'static str,
+    immutables: &'static [(usize, usize)],
+}
+
+// build.rs bundles the Sniper from its forge artifact when the contracts have
+// been built, with `forge build` or the `solc` feature. Otherwise it's None.
+include!(concat!(env!("OUT_DIR"), "/bytecode.rs"));
+
+impl Artifact {
+    // checked by build.rs
+    pub fn bytecode(&self) -> Bytes {
+        hex::decode(self.bytecode).unwrap().into()
+    }
+
+    // Whether `code`, as read from the chain, is this contract's runtime code.
+    // Immutables differ between deployments so they aren't compared.
+    pub fn matches(&self, code: &[u8]) -> bool {
+        let mut expected = hex::decode(self.deployed_bytecode).unwrap();
+        let mut code = code.to_vec();
+        if code.len() != expected.len() {
+            return false;
+        }
+        for &(start, length) in self.immutables {
+            expected[start..start + length].fill(0);
+            code[start..start + length].fill(0);
+        }
+        code == expected
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bindings::sniper::{sniper::sniper, SNIPER_ABI};
+    use ethers::{prelude::*, utils::Anvil};
+    use std::{sync::Arc, time::Duration};
+
+    // Deploys the bundled Sniper like `friendrekt deploy` does, and reads it
+    // back like `friendrekt status` does.
+    #[tokio::test]
+    #[ignore = "needs anvil and the compiled contracts"]
+    async fn deployed_sniper_matches_the_bundled_bytecode() {
+        let artifact = SNIPER.expect("build the contracts with `forge build` first");
+        let anvil = Anvil::new().spawn();
+        let wallet: LocalWallet = anvil.keys()[0].clone().into();
+        let deployer = wallet.address();
+        let provider = Provider::<Http>::try_from(anvil.endpoint())
+            .unwrap()
+            .interval(Duration::from_millis(10));
+        let client = Arc::new(SignerMiddleware::new(
+            provider,
+            wallet.with_chain_id(anvil.chain_id()),
+        ));
+
+        let factory = ContractFactory::new(SNIPER_ABI.clone(), artifact.bytecode(), client.clone());
+        let contract = factory.deploy(()).unwrap().send().await.unwrap();
+        let code = client.get_code(contract.address(), None).await.unwrap();
+        // the owner is an immutable, so this also checks they're skipped
+        assert!(artifact.matches(&code));
+        assert!(!artifact.matches(&code[1..]));
+
+        let sniper = sniper::new(contract.address(), client);
+        assert_eq!(sniper.owner().call().await.unwrap(), deployer);
+        assert!(sniper.allowlist(deployer).call().await.unwrap());
+        assert!(!sniper.allowlist(Address::zero()).call().await.unwrap());
+    }
+}
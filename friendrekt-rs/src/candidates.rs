@@ -0,0 +1,448 @@
+use crate::{
+    bindings::{shares::shares::shares, sniper::sniper::sniper},
+    cli::Client,
+    config::Config,
+    decisions::{Decision, Decisions},
+    fasthttp::FastHttp,
+    filters::Filters,
+    keys::KeyError,
+    latency::{Latency, Stage, Timeline},
+    math,
+    metrics::{Outcome, METRICS},
+    notify::{notify, Event},
+    pipeline::Kind,
+    prod_kosetto::{Kosetto, Lookup, Profiles},
+    risk::{RiskManager, RiskRejection},
+    signers::SignerPool,
+    snipes::Snipes,
+    strategy::Strategy,
+    supervisor::{Supervisor, TaskError},
+    watchlist::Watchlist,
+};
+use ethers::{prelude::*, types::transaction::eip2930::AccessList, utils::hex};
+use std::{fmt, str::FromStr, sync::Arc};
+use tracing::{debug, info, warn};
+
+// Everything deciding on a candidate tx needs, shared by the block loop's
+// workers. Each buyShares or relay tx ends up as one decision in the audit
+// log, however far it got.
+pub struct Candidates {
+    pub client: Arc<Client>,
+    pub profiles: Arc<Profiles>,
+    pub watchlist: Arc<Watchlist>,
+    pub kosetto: Arc<Kosetto>,
+    pub strategy: Arc<Strategy>,
+    pub filters: Option<Arc<Filters>>,
+    pub risk: Option<Arc<RiskManager>>,
+    pub decisions: Arc<Decisions>,
+    pub latency: Arc<Latency>,
+    pub snipes: Arc<Snipes>,
+    pub signers: Arc<SignerPool>,
+    // follows the receipts of the snipes sent
+    pub receipts: Arc<Supervisor>,
+    pub config: Arc<Config>,
+    pub friendtech: Arc<shares<Client>>,
+    pub sniper: Arc<sniper<Client>>,
+    pub fasthttp: Arc<FastHttp>,
+    pub chain_id: u64,
+    // shares to buy, and the supply limit of watched subjects whose entry
+    // doesn't have one
+    pub amount: U256,
+    pub watch_supply_limit: u64,
+}
+
+// Skipped at `timeline`, for `reason`.
+fn skip(candidate: Decision, timeline: &Timeline, reason: impl fmt::Display) -> Decision {
+    candidate
+        .outcome("skip", timeline.elapsed_ms())
+        .reason(reason)
+}
+
+impl Candidates {
+    // Decide on a buyShares or relay tx and record the decision. Returns an
+    // address worth looking up once the block is done, if any.
+    pub async fn handle(
+        &self,
+        tx: Transaction,
+        kind: Kind,
+        mut timeline: Timeline,
+    ) -> Result<Option<Address>, TaskError> {
+        if kind == Kind::Relay {
+            let (decision, deposit) = self.relay(&tx, &timeline).await;
+            self.decisions.record(decision).await;
+            return Ok(deposit);
+        }
+
+        // read while we decide, only the record waits for it
+        let friendtech = self.friendtech.clone();
+        let subject = tx.from;
+        let supply = tokio::spawn(async move {
+            match friendtech.shares_supply(subject).call().await {
+                Ok(supply) => Some(supply.low_u64()),
+                Err(e) => {
+                    debug!(?subject, "Failed to get supply: {}", e);
+                    None
+                }
+            }
+        });
+        let (decision, result) = match self.buy_shares(&tx, &mut timeline).await {
+            Ok(decision) => (decision, Ok(None)),
+            Err((decision, e)) => (decision, Err(e)),
+        };
+        let supply = supply.await.ok().flatten();
+        self.decisions.record(decision.supply(supply)).await;
+        result
+    }
+
+    // Snipe the sender of a buyShares tx, if they pass. Failing to sign for
+    // good is fatal, along with the decision to record first.
+    async fn buy_shares(
+        &self,
+        tx: &Transaction,
+        timeline: &mut Timeline,
+    ) -> Result<Decision, (Decision, TaskError)> {
+        let candidate = Decision::new(tx);
+        let config = &self.config;
+
+        // tx that bought shares
+        let reason = if tx.input.len() != 68 {
+            Some("malformed buyShares call")
+        } else if tx.to.is_none() {
+            Some("no recipient")
+        } else if tx.transaction_type.is_some_and(|tt| tt != U64::from(2)) {
+            Some("not an EIP-1559 tx")
+        } else if tx.value != U256::zero() && tx.to != Some(self.friendtech.address()) {
+            Some("sends ETH to a contract other than friend.tech")
+        } else {
+            None
+        };
+        if let Some(reason) = reason {
+            debug!(decision = "skip", reason, "Not sniping");
+            return Ok(skip(candidate, timeline, reason));
+        }
+        // our fees are based on its EIP-1559 ones, which a tx without a type
+        // may not have
+        let tx_fees = match tx.max_fee_per_gas.zip(tx.max_priority_fee_per_gas) {
+            Some(fees) => fees,
+            None => {
+                debug!(
+                    decision = "skip",
+                    reason = "no EIP-1559 fees",
+                    "Not sniping"
+                );
+                return Ok(skip(candidate, timeline, "no EIP-1559 fees"));
+            }
+        };
+
+        let mut watched = self.watchlist.get(&tx.from).await;
+        // an address cached as having no account may have just signed up, so
+        // that counts as a miss
+        let cached = self.profiles.get(&tx.from).flatten();
+        let source = match (&cached, &watched) {
+            (Some(_), _) => "cache",
+            (None, Some(_)) => "watchlist",
+            (None, None) => "kosetto",
+        };
+        let candidate = candidate.source(source);
+        let found = match (cached, &watched) {
+            (Some(info), _) => {
+                // found twitter info cache
+                METRICS.cache.with_label_values(&["hit"]).inc();
+                Ok(info)
+            }
+            (None, Some(entry)) => {
+                // watched address, no need to ask Kosetto
+                METRICS.cache.with_label_values(&["watchlist"]).inc();
+                Ok(entry.info())
+            }
+            (None, None) => {
+                METRICS.cache.with_label_values(&["miss"]).inc();
+                match self.kosetto.lookup_into(&self.profiles, tx.from).await {
+                    Lookup::Found(live_info) => Ok(live_info),
+                    // just resolved on the watchlist, which has it for the
+                    // next tx
+                    Lookup::Watched(info) => Ok(info),
+                    Lookup::NotRegistered => Err("no friend.tech account"),
+                    Lookup::Failed => Err("profile lookup failed"),
+                }
+            }
+        };
+        let info = match found {
+            Ok(info) => info,
+            Err(reason) => {
+                debug!(
+                    decision = "skip",
+                    reason,
+                    latency_ms = timeline.elapsed_ms(),
+                    "Not sniping"
+                );
+                return Ok(skip(candidate, timeline, reason));
+            }
+        };
+
+        timeline.mark(Stage::Lookup);
+        let candidate = candidate.profile(&info);
+        if watched.is_none() {
+            // the handle may have been added to the watchlist after we cached
+            // this user
+            watched = self
+                .watchlist
+                .observe(tx.from, &info.twitter_username, &info.twitter_user_id)
+                .await;
+        }
+
+        let (amount, supply_limit) = match &watched {
+            Some(entry) => match entry.limits(self.amount, self.watch_supply_limit) {
+                Some(limits) => limits,
+                None => {
+                    info!(
+                        decision = "skip",
+                        reason = "doesn't fit watchlist max_eth",
+                        handle = %entry.label(),
+                        latency_ms = timeline.elapsed_ms(),
+                        "Not sniping"
+                    );
+                    return Ok(skip(candidate, timeline, "doesn't fit watchlist max_eth"));
+                }
+            },
+            None => (
+                self.amount,
+                self.strategy.supply_limit(info.followers).await,
+            ),
+        };
+
+        if supply_limit == 0 {
+            debug!(
+                decision = "skip",
+                reason = "not enough followers",
+                handle = %info.twitter_username,
+                followers = info.followers,
+                latency_ms = timeline.elapsed_ms(),
+                "Not sniping"
+            );
+            return Ok(skip(candidate, timeline, "not enough followers"));
+        }
+        let candidate = candidate.quote(amount, supply_limit);
+
+        if let Some(filters) = &self.filters {
+            if let Err(reason) = filters.check(tx.from, &info).await {
+                info!(
+                    decision = "skip",
+                    %reason,
+                    handle = %info.twitter_username,
+                    followers = info.followers,
+                    latency_ms = timeline.elapsed_ms(),
+                    "Not sniping, rejected by filters"
+                );
+                return Ok(skip(candidate, timeline, reason));
+            }
+        }
+
+        let (max_fee, prio_fee) = config.gas.fees(tx_fees.0, tx_fees.1);
+        let gas = U256::from(config.gas.gas_limit) * max_fee;
+
+        let mut reservation = None;
+        if let Some(risk) = &self.risk {
+            // worst case, the Sniper buys right at the limit. The snipe buys
+            // shares of the sender, whatever the tx itself bought.
+            let cost = math::get_price_after_fee(U256::from(supply_limit), amount);
+            match risk.reserve(tx.from, cost, gas).await {
+                Ok(reserved) => reservation = Some(reserved),
+                Err(reason) => {
+                    // the kill switch is the operator's own doing
+                    if !matches!(reason, RiskRejection::KillSwitch) {
+                        notify(Event::RiskLimit {
+                            subject: tx.from,
+                            reason: reason.to_string(),
+                        });
+                    }
+                    warn!(
+                        decision = "skip",
+                        %reason,
+                        subject = ?tx.from,
+                        handle = %info.twitter_username,
+                        followers = info.followers,
+                        supply_limit,
+                        latency_ms = timeline.elapsed_ms(),
+                        "Not sniping, over risk limits"
+                    );
+                    return Ok(skip(candidate, timeline, reason));
+                }
+            }
+        }
+
+        timeline.mark(Stage::Decide);
+
+        // a dry run only checks the limits, dropping the reservation gives
+        // back what it counted
+        if config.features.dry_run {
+            info!(
+                decision = "dry_run",
+                subject = ?tx.from,
+                handle = %info.twitter_username,
+                followers = info.followers,
+                amount = %amount,
+                supply_limit,
+                latency_ms = timeline.elapsed_ms(),
+                "Would snipe"
+            );
+            return Ok(candidate.outcome("dry_run", timeline.elapsed_ms()));
+        }
+
+        let binding = self
+            .sniper
+            .do_snipe_many_shares(vec![tx.from], vec![amount], vec![U256::from(supply_limit)])
+            .calldata()
+            .unwrap();
+
+        let signer = match self.signers.acquire(self.client.as_ref(), gas).await {
+            Ok(signer) => signer,
+            Err(e) => {
+                warn!("No signer for the snipe: {}", e);
+                return Ok(candidate
+                    .outcome("error", timeline.elapsed_ms())
+                    .reason(format!("no signer: {}", e)));
+            }
+        };
+        let txn = Eip1559TransactionRequest {
+            to: Some(NameOrAddress::Address(self.sniper.address())),
+            from: Some(signer.address()),
+            nonce: Some(signer.nonce),
+            gas: Some(U256::from(config.gas.gas_limit)),
+            value: None,
+            data: Some(binding),
+            chain_id: Some(U64::from(self.chain_id)),
+            max_priority_fee_per_gas: Some(prio_fee),
+            max_fee_per_gas: Some(max_fee),
+            access_list: AccessList::default(),
+        }
+        .into();
+
+        let sig = match signer.sign(&txn).await {
+            Ok(sig) => sig,
+            // a remote signer may just be out for a moment
+            Err(KeyError::Remote(e)) => {
+                warn!("Failed to sign snipe: {}", e);
+                signer.failed().await;
+                return Ok(candidate
+                    .outcome("error", timeline.elapsed_ms())
+                    .reason(format!("failed to sign: {}", e)));
+            }
+            // without a working signer there's no point carrying on
+            Err(e) => {
+                let decision = candidate
+                    .outcome("error", timeline.elapsed_ms())
+                    .reason(format!("failed to sign: {}", e));
+                let e = TaskError::Fatal(format!("failed to sign snipe: {}", e));
+                return Err((decision, e));
+            }
+        };
+        timeline.mark(Stage::Sign);
+
+        let raw = txn.rlp_signed(&sig);
+        METRICS.snipes.with_label_values(&["attempted"]).inc();
+        let hash = self
+            .fasthttp
+            .send_request(format!("0x{}", hex::encode(raw)))
+            .await;
+        timeline.mark(Stage::Send);
+        self.latency.record(timeline).await;
+        let snipe = match hash.as_deref().map(H256::from_str) {
+            Some(Ok(snipe)) => snipe,
+            _ => {
+                METRICS.snipes.with_label_values(&["failed"]).inc();
+                signer.failed().await;
+                warn!(
+                    subject = ?tx.from,
+                    handle = %info.twitter_username,
+                    response = ?hash,
+                    latency_ms = timeline.elapsed_ms(),
+                    "Sequencer didn't take the snipe"
+                );
+                return Ok(candidate
+                    .outcome("error", timeline.elapsed_ms())
+                    .reason("sequencer didn't take the snipe"));
+            }
+        };
+
+        METRICS.snipes.with_label_values(&["sent"]).inc();
+        if let Some(reservation) = reservation {
+            reservation.commit();
+        }
+        notify(Event::SnipeSent {
+            subject: tx.from,
+            handle: info.twitter_username.clone(),
+            hash: snipe,
+        });
+        let client = self.client.clone();
+        let snipes = self.snipes.clone();
+        self.receipts.spawn("receipt", async move {
+            let outcome = snipes.follow(client.as_ref(), snipe).await;
+            if let Outcome::Dropped = outcome {
+                signer.failed().await;
+            }
+            Ok(())
+        });
+
+        info!(
+            decision = "snipe",
+            subject = ?tx.from,
+            handle = %info.twitter_username,
+            followers = info.followers,
+            amount = %amount,
+            supply_limit,
+            snipe = ?snipe,
+            latency_ms = timeline.elapsed_ms(),
+            fetch_ms = timeline.ms(Stage::Fetch),
+            classify_ms = timeline.ms(Stage::Classify),
+            lookup_ms = timeline.ms(Stage::Lookup),
+            decide_ms = timeline.ms(Stage::Decide),
+            sign_ms = timeline.ms(Stage::Sign),
+            send_ms = timeline.ms(Stage::Send),
+            "Sent snipe"
+        );
+        Ok(candidate
+            .outcome("snipe", timeline.elapsed_ms())
+            .snipe(hash))
+    }
+
+    // A message relayed from L1. Whoever its deposit is for may be about to
+    // sign up, so they're looked up ahead of time.
+    async fn relay(&self, tx: &Transaction, timeline: &Timeline) -> (Decision, Option<Address>) {
+        // From my testing, I haven't seen any relay_txn_sig txns come through.
+        // Could be a bug in my code, but I suspect it's just not used anymore.
+        let candidate = Decision::new(tx);
+        let event = match self.client.get_transaction_receipt(tx.hash).await {
+            Ok(Some(event)) => event,
+            Ok(None) => return (skip(candidate, timeline, "no receipt"), None),
+            Err(e) => {
+                warn!("Failed to get receipt: {}", e);
+                let decision = candidate
+                    .outcome("error", timeline.elapsed_ms())
+                    .reason(format!("failed to get receipt: {}", e));
+                return (decision, None);
+            }
+        };
+        let deposit =
+            H256::from_str("0xb0444523268717a02698be47d0803aa7468c00acbed2f8bd93a0459cde61dd89")
+                .unwrap();
+        let address = event
+            .logs
+            .iter()
+            .find(|e| e.topics.first() == Some(&deposit))
+            .and_then(|e| e.data.get(12..32))
+            .map(Address::from_slice);
+        match address {
+            Some(address) => {
+                debug!(
+                    ?address,
+                    "Relayed deposit, looking it up once the block is done"
+                );
+                let mut decision = candidate.outcome("prewarm", timeline.elapsed_ms());
+                decision.subject = address;
+                (decision, Some(address))
+            }
+            None => (skip(candidate, timeline, "no deposit"), None),
+        }
+    }
+}
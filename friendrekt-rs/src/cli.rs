@@ -0,0 +1,705 @@
+use crate::{
+    bindings::{
+        shares::shares::shares,
+        sniper::{sniper::sniper, SNIPER_ABI},
+    },
+    bytecode,
+    config::Config,
+    decisions,
+    keys::{self, Key, Keystores},
+    ledger::{self, Ledger, LotMethod},
+    math,
+    portfolio::{Portfolio, TradeScanner},
+    rpc::Failover,
+};
+use clap::{Parser, Subcommand};
+use ethers::{prelude::*, utils::parse_ether};
+use std::{
+    io::{self, BufRead, Write},
+    net::SocketAddr,
+    path::PathBuf,
+    sync::Arc,
+    time::Duration,
+};
+use tracing::info;
+
+pub type Client = SignerMiddleware<Provider<Failover>, Key>;
+type CliResult = Result<(), Box<dyn std::error::Error>>;
+
+// Command line flags. Anything set here (or through the matching env var)
+// wins over the config file.
+#[derive(Parser, Debug)]
+#[command(name = "friendrekt", version, about = "friend.tech share sniper")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// Path to the TOML config file [default: friendrekt.toml if it exists]
+    #[arg(short, long, env = "FRIENDREKT_CONFIG", global = true)]
+    pub config: Option<PathBuf>,
+
+    /// Websocket RPC endpoint for Base
+    #[arg(long, env = "BASE_WSS_URL", global = true)]
+    pub wss_url: Option<String>,
+
+    /// Private key of the operator wallet
+    #[arg(long, env = "PRIVATE_KEY", hide_env_values = true, global = true)]
+    pub private_key: Option<String>,
+
+    /// Private keys of the hot wallets snipes are sent from, comma separated
+    #[arg(
+        long,
+        env = "HOT_PRIVATE_KEYS",
+        hide_env_values = true,
+        value_delimiter = ',',
+        global = true
+    )]
+    pub hot_keys: Option<Vec<String>>,
+
+    /// friend.tech shares contract
+    #[arg(long, env = "FT_ADDRESS", global = true)]
+    pub ft_address: Option<Address>,
+
+    /// Deployed Sniper contract
+    #[arg(long, env = "SNIPER_ADDRESS", global = true)]
+    pub sniper_address: Option<Address>,
+
+    /// Endpoint snipes are broadcast to
+    #[arg(long, env = "SEQUENCER_URL", global = true)]
+    pub sequencer_url: Option<String>,
+
+    /// Base URL of the follower count sidecar (friendrekt-py)
+    #[arg(long, env = "FOLLOWERS_URL", global = true)]
+    pub followers_url: Option<String>,
+
+    /// Shares to buy per snipe
+    #[arg(long, env = "SNIPE_AMOUNT", global = true)]
+    pub amount: Option<u64>,
+
+    /// Bearer token for the admin API
+    #[arg(long, env = "ADMIN_TOKEN", hide_env_values = true, global = true)]
+    pub admin_token: Option<String>,
+
+    /// Log snipes (or print the preview of a command) instead of sending
+    #[arg(long, global = true)]
+    pub dry_run: bool,
+
+    /// Send without asking for confirmation
+    #[arg(short, long, global = true)]
+    pub yes: bool,
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Run the sniping bot (the default)
+    Run,
+    /// Buy shares of a subject through the Sniper contract
+    Buy { subject: Address, amount: u64 },
+    /// Sell shares of a subject held by the Sniper contract
+    Sell { subject: Address, amount: u64 },
+    /// Withdraw all ETH from the Sniper contract to the owner
+    Withdraw,
+    /// Point the Sniper contract at a different friend.tech contract
+    SetFt { address: Address },
+    /// Deploy a new Sniper contract, set it up and write its address to the config
+    Deploy {
+        /// ETH to send it [default: contracts.deploy_fund_eth]
+        #[arg(long)]
+        fund_eth: Option<f64>,
+    },
+    /// Manage the wallets allowed to snipe through the Sniper contract
+    Allowlist {
+        #[command(subcommand)]
+        action: AllowlistAction,
+    },
+    /// Show the operator wallet and Sniper contract
+    Status,
+    /// Show the shares held by the Sniper contract and their value
+    Portfolio {
+        /// Also write the positions to this CSV file
+        #[arg(long)]
+        csv: Option<PathBuf>,
+        /// Keep refreshing every this many seconds
+        #[arg(long)]
+        watch: Option<u64>,
+    },
+    /// Build the realised PnL ledger from the Sniper's trades and reconcile it
+    Ledger {
+        /// How sells are matched to buys [default: portfolio.lot_method]
+        #[arg(long, value_enum)]
+        method: Option<LotMethod>,
+        /// Write the open lots to this CSV file
+        #[arg(long)]
+        lots_csv: Option<PathBuf>,
+        /// Write the realised disposals to this CSV file
+        #[arg(long)]
+        disposals_csv: Option<PathBuf>,
+    },
+    /// Search the decision audit log, most recent last
+    Decisions {
+        /// Only decisions about this subject
+        #[arg(long)]
+        subject: Option<Address>,
+        /// From this time on: unix seconds or a duration ago, e.g. 12h or 7d
+        #[arg(long, value_parser = decisions::parse_time)]
+        since: Option<u64>,
+        /// Up to this time, same format as --since
+        #[arg(long, value_parser = decisions::parse_time)]
+        until: Option<u64>,
+        /// Only snipe, skip or dry_run decisions
+        #[arg(long)]
+        decision: Option<String>,
+        /// Show at most this many
+        #[arg(long, default_value_t = 100)]
+        limit: usize,
+        /// Print JSON lines instead of a summary
+        #[arg(long)]
+        json: bool,
+    },
+    /// Send a test message through the configured notifiers
+    Notify {
+        #[arg(default_value = "friendrekt test notification")]
+        message: String,
+    },
+    /// Serve recorded flashblocks on a local websocket, for flashblocks.url
+    ReplayFlashblocks {
+        /// One JSON payload per line, as written to flashblocks.record_path
+        file: PathBuf,
+        #[arg(long, default_value = "127.0.0.1:9186")]
+        listen: SocketAddr,
+        /// Time between payloads
+        #[arg(long, default_value_t = 200)]
+        interval_ms: u64,
+    },
+    /// Price buying or selling shares of a subject
+    Quote {
+        subject: Address,
+        #[arg(default_value_t = 1)]
+        amount: u64,
+    },
+}
+
+impl Command {
+    // Whether it runs without the chain, and so without a node, a signer or
+    // the Sniper.
+    pub fn is_local(&self) -> bool {
+        matches!(
+            self,
+            Command::Decisions { .. } | Command::Notify { .. } | Command::ReplayFlashblocks { .. }
+        )
+    }
+}
+
+// Without addresses, these act on the wallets snipes are sent from: the hot
+// wallets, or the operator if there are none.
+#[derive(Subcommand, Debug, Clone)]
+pub enum AllowlistAction {
+    /// Allow wallets to snipe, buy and sell
+    Add { addresses: Vec<Address> },
+    /// Stop wallets from sniping, buying and selling
+    Remove { addresses: Vec<Address> },
+    /// Show whether wallets are allowlisted
+    Check { addresses: Vec<Address> },
+}
+
+// Connect to the websocket RPC with the configured signer. Hot wallets are
+// loaded later with the same `keystores`, so passphrases are asked for once.
+pub async fn connect(
+    config: &Config,
+    keystores: &mut Keystores,
+) -> Result<Arc<Client>, Box<dyn std::error::Error>> {
+    let rpc = Failover::connect(&config.rpc).await?;
+    rpc.monitor(Duration::from_secs(config.rpc.health_secs));
+    let provider = Provider::new(rpc);
+    let cid = provider.get_chainid().await?.as_u64();
+    let signer = keys::operator(&config.signer, keystores)
+        .await?
+        .with_chain_id(cid);
+
+    let provider = Arc::new(SignerMiddleware::new(provider, signer));
+    info!(address = ?provider.address(), chain_id = cid, "Connected");
+    Ok(provider)
+}
+
+// Run a one-off command against the Sniper contract.
+pub async fn execute(command: Command, config: &Config, yes: bool) -> CliResult {
+    let mut keystores = Keystores::new(&config.signer);
+    let client = connect(config, &mut keystores).await?;
+    let confirm = Confirm {
+        dry_run: config.features.dry_run,
+        yes,
+    };
+    // the only command that works without a Sniper
+    if let Command::Deploy { fund_eth } = command {
+        return deploy(client, &mut keystores, config, fund_eth, &confirm).await;
+    }
+    let sniper = sniper::new(config.sniper(), client.clone());
+    let ft = shares::new(config.contracts.friendtech, client.clone());
+
+    match command {
+        Command::Run
+        | Command::Notify { .. }
+        | Command::Decisions { .. }
+        | Command::ReplayFlashblocks { .. } => {
+            unreachable!("handled by main")
+        }
+        Command::Deploy { .. } => unreachable!("handled above"),
+        Command::Buy { subject, amount } => {
+            let supply = ft.shares_supply(subject).call().await?;
+            let price = math::get_price(supply, U256::from(amount));
+            let cost = math::get_price_after_fee(supply, U256::from(amount));
+            let balance = client.get_balance(sniper.address(), None).await?;
+
+            println!(
+                "Buy {} shares of {:?} at supply {}",
+                amount, subject, supply
+            );
+            println!(
+                "Price: {} ETH ({} ETH after fees)",
+                math::wei_to_eth(price),
+                math::wei_to_eth(cost)
+            );
+            println!("Sniper balance: {} ETH", math::wei_to_eth(balance));
+            if balance < cost {
+                println!("Warning: the Sniper contract can't afford this buy");
+            }
+
+            if confirm.ask()? {
+                send(sniper.buy_shares(subject, U256::from(amount))).await?;
+            }
+        }
+        Command::Sell { subject, amount } => {
+            let supply = ft.shares_supply(subject).call().await?;
+            let held = ft.shares_balance(subject, sniper.address()).call().await?;
+            let amount = U256::from(amount);
+            if amount > held || amount >= supply {
+                return Err(format!(
+                    "can't sell {} shares, the Sniper holds {} of {}",
+                    amount, held, supply
+                )
+                .into());
+            }
+
+            let price = math::get_price(supply - amount, amount);
+            let proceeds = math::get_sell_price_after_fee(supply - amount, amount);
+
+            println!(
+                "Sell {} of {} shares of {:?} at supply {}",
+                amount, held, subject, supply
+            );
+            println!(
+                "Price: {} ETH ({} ETH after fees)",
+                math::wei_to_eth(price),
+                math::wei_to_eth(proceeds)
+            );
+
+            if confirm.ask()? {
+                send(sniper.sell_shares(subject, amount)).await?;
+            }
+        }
+        Command::Withdraw => {
+            let balance = client.get_balance(sniper.address(), None).await?;
+            let owner = sniper.owner().call().await?;
+
+            println!(
+                "Withdraw {} ETH from {:?} to {:?}",
+                math::wei_to_eth(balance),
+                sniper.address(),
+                owner
+            );
+            if owner != client.address() {
+                println!(
+                    "Warning: {:?} is not the owner, this will revert",
+                    client.address()
+                );
+            }
+
+            if confirm.ask()? {
+                send(sniper.return_eth()).await?;
+            }
+        }
+        Command::SetFt { address } => {
+            let current = sniper.ft().call().await?;
+
+            println!(
+                "Set friend.tech contract from {:?} to {:?}",
+                current, address
+            );
+            if confirm.ask()? {
+                send(sniper.set_ft(address)).await?;
+            }
+        }
+        Command::Allowlist { action } => {
+            let (allowed, addresses) = match &action {
+                AllowlistAction::Add { addresses } => (true, addresses),
+                AllowlistAction::Remove { addresses } => (false, addresses),
+                AllowlistAction::Check { addresses } => (false, addresses),
+            };
+            let addresses = if addresses.is_empty() {
+                keys::hot(&config.signer, client.signer(), &mut keystores)
+                    .await?
+                    .iter()
+                    .map(|key| key.address())
+                    .collect()
+            } else {
+                addresses.clone()
+            };
+
+            let mut changes = Vec::new();
+            println!("Sniper: {:?}", sniper.address());
+            for address in addresses {
+                let listed = sniper.allowlist(address).call().await?;
+                println!(
+                    "  {:?}: {}",
+                    address,
+                    if listed {
+                        "allowlisted"
+                    } else {
+                        "not allowlisted"
+                    }
+                );
+                if listed != allowed {
+                    changes.push(address);
+                }
+            }
+            if let AllowlistAction::Check { .. } = action {
+                return Ok(());
+            }
+            if changes.is_empty() {
+                println!("Nothing to change");
+                return Ok(());
+            }
+
+            if allowed {
+                println!("Allowlist {} wallets", changes.len());
+            } else {
+                println!("Remove {} wallets from the allowlist", changes.len());
+            }
+            let owner = sniper.owner().call().await?;
+            if owner != client.address() {
+                println!(
+                    "Warning: {:?} is not the owner, this will revert",
+                    client.address()
+                );
+            }
+            if confirm.ask()? {
+                if allowed {
+                    send(sniper.add_to_allowlist(changes)).await?;
+                } else {
+                    send(sniper.remove_from_allowlist(changes)).await?;
+                }
+            }
+        }
+        Command::Status => {
+            let operator = client.address();
+            println!("Operator: {:?}", operator);
+            println!(
+                "  balance: {} ETH",
+                math::wei_to_eth(client.get_balance(operator, None).await?)
+            );
+            let hot = keys::hot(&config.signer, client.signer(), &mut keystores).await?;
+            if hot.iter().any(|key| key.address() != operator) {
+                println!("Hot wallets:");
+                for key in hot {
+                    println!(
+                        "  {:?}: {} ETH",
+                        key.address(),
+                        math::wei_to_eth(client.get_balance(key.address(), None).await?)
+                    );
+                }
+            }
+            println!("Sniper: {:?}", sniper.address());
+            println!(
+                "  balance: {} ETH",
+                math::wei_to_eth(client.get_balance(sniper.address(), None).await?)
+            );
+            if let Some(artifact) = bytecode::SNIPER {
+                let code = client.get_code(sniper.address(), None).await?;
+                println!(
+                    "  code: {}",
+                    if code.is_empty() {
+                        "none, nothing is deployed there"
+                    } else if artifact.matches(&code) {
+                        "matches this build's Sniper"
+                    } else {
+                        "differs from this build's Sniper"
+                    }
+                );
+            }
+            println!("  owner: {:?}", sniper.owner().call().await?);
+            println!("  friend.tech: {:?}", sniper.ft().call().await?);
+        }
+        Command::Portfolio { csv, watch } => {
+            let mut scanner = TradeScanner::new(
+                ft.address(),
+                sniper.address(),
+                config.portfolio.start_block,
+                config.portfolio.scan_chunk,
+            );
+            if config.portfolio.start_block == 0 {
+                println!("portfolio.start_block is not set, scanning from genesis");
+            }
+
+            loop {
+                let found = scanner.scan(client.as_ref()).await?;
+                if found > 0 {
+                    println!("Found {} new trades by the Sniper", found);
+                }
+
+                let portfolio = Portfolio::load(&ft, sniper.address(), &scanner.trades).await?;
+                portfolio.print();
+                if let Some(path) = &csv {
+                    portfolio.write_csv(path)?;
+                    println!("Wrote {:?}", path);
+                }
+
+                match watch {
+                    Some(secs) => tokio::time::sleep(Duration::from_secs(secs)).await,
+                    None => break,
+                }
+            }
+        }
+        Command::Ledger {
+            method,
+            lots_csv,
+            disposals_csv,
+        } => {
+            let mut scanner = TradeScanner::new(
+                ft.address(),
+                sniper.address(),
+                config.portfolio.start_block,
+                config.portfolio.scan_chunk,
+            );
+            scanner.scan(client.as_ref()).await?;
+            let gas = ledger::gas_costs(client.as_ref(), &scanner.trades).await?;
+            println!(
+                "Found {} trades by the Sniper in {} transactions",
+                scanner.trades.len(),
+                gas.len()
+            );
+
+            let method = method.unwrap_or(config.portfolio.lot_method);
+            let mut ledger = Ledger::build(method, &scanner.trades, &gas);
+            ledger
+                .reconcile(&ft, sniper.address(), config.portfolio.start_block)
+                .await?;
+            ledger.print();
+
+            if let Some(path) = &lots_csv {
+                ledger.write_lots_csv(path)?;
+                println!("Wrote {:?}", path);
+            }
+            if let Some(path) = &disposals_csv {
+                ledger.write_disposals_csv(path)?;
+                println!("Wrote {:?}", path);
+            }
+        }
+        Command::Quote { subject, amount } => {
+            let supply = ft.shares_supply(subject).call().await?;
+            let held = ft.shares_balance(subject, sniper.address()).call().await?;
+            let amount = U256::from(amount);
+
+            println!("{:?}: supply {}, Sniper holds {}", subject, supply, held);
+            println!(
+                "Buy {}: {} ETH ({} ETH after fees)",
+                amount,
+                math::wei_to_eth(math::get_price(supply, amount)),
+                math::wei_to_eth(math::get_price_after_fee(supply, amount))
+            );
+            if amount < supply {
+                println!(
+                    "Sell {}: {} ETH ({} ETH after fees)",
+                    amount,
+                    math::wei_to_eth(math::get_price(supply - amount, amount)),
+                    math::wei_to_eth(math::get_sell_price_after_fee(supply - amount, amount))
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
+// Deploy a Sniper owned by the operator, point it at friend.tech, fund it and
+// allowlist the hot wallets, then check that all of it took. The address is
+// written to the config file as soon as the contract is deployed, so it isn't
+// lost if a later step fails.
+async fn deploy(
+    client: Arc<Client>,
+    keystores: &mut Keystores,
+    config: &Config,
+    fund_eth: Option<f64>,
+    confirm: &Confirm,
+) -> CliResult {
+    let artifact = bytecode::SNIPER.ok_or(
+        "this build has no Sniper bytecode, build friendrekt-contracts with forge (or \
+         build with --features solc) and rebuild",
+    )?;
+    let fund_eth = fund_eth.unwrap_or(config.contracts.deploy_fund_eth);
+    let fund = match parse_ether(fund_eth) {
+        Ok(fund) if fund_eth.is_finite() && fund_eth >= 0.0 => fund,
+        _ => return Err(format!("{} is not a valid ETH amount", fund_eth).into()),
+    };
+    let friendtech = config.contracts.friendtech;
+    let operator = client.address();
+    // the operator is allowlisted by the constructor
+    let hot: Vec<Address> = keys::hot(&config.signer, client.signer(), keystores)
+        .await?
+        .iter()
+        .map(|key| key.address())
+        .filter(|address| *address != operator)
+        .collect();
+
+    println!("Deploy a Sniper owned by {:?}", operator);
+    println!("  friend.tech: {:?}", friendtech);
+    println!("  funded with: {} ETH", fund_eth);
+    for wallet in &hot {
+        println!("  allowlist: {:?}", wallet);
+    }
+    if let Some(current) = config.contracts.sniper {
+        println!("It replaces {:?} in the config", current);
+    }
+    let balance = client.get_balance(operator, None).await?;
+    println!("Operator balance: {} ETH", math::wei_to_eth(balance));
+    if balance < fund {
+        println!("Warning: the operator can't afford to fund it");
+    }
+    if !confirm.ask()? {
+        return Ok(());
+    }
+
+    let factory = ContractFactory::new(SNIPER_ABI.clone(), artifact.bytecode(), client.clone());
+    let (contract, receipt) = factory.deploy(())?.send_with_receipt().await?;
+    // a failed deployment still has an address, with no code at it
+    if receipt.status != Some(U64::one()) {
+        return Err(format!(
+            "deploying the Sniper reverted in block {:?}: https://basescan.org/tx/{:?}",
+            receipt.block_number.unwrap_or_default(),
+            receipt.transaction_hash
+        )
+        .into());
+    }
+    let sniper = sniper::new(contract.address(), client.clone());
+    println!(
+        "Deployed {:?} in block {:?}: https://basescan.org/tx/{:?}",
+        sniper.address(),
+        receipt.block_number.unwrap_or_default(),
+        receipt.transaction_hash
+    );
+    let path = config.write_sniper(sniper.address())?;
+    println!("Wrote it to {:?}", path);
+    if std::env::var_os("SNIPER_ADDRESS").is_some() {
+        println!("Warning: SNIPER_ADDRESS is set and wins over the config file, update it too");
+    }
+
+    // The Sniper is in the config whatever fails from here on, the checks
+    // below show what's left to set up. The constructor points it at the
+    // friend.tech on Base.
+    if sniper.ft().call().await? != friendtech {
+        if let Err(e) = send(sniper.set_ft(friendtech)).await {
+            println!("Failed to point it at friend.tech: {}", e);
+        }
+    }
+    if !fund.is_zero() {
+        let pending = client
+            .send_transaction(TransactionRequest::pay(sniper.address(), fund), None)
+            .await?;
+        if let Err(e) = confirmed(pending).await {
+            println!("Failed to fund it: {}", e);
+        }
+    }
+    if !hot.is_empty() {
+        if let Err(e) = send(sniper.add_to_allowlist(hot.clone())).await {
+            println!("Failed to allowlist the hot wallets: {}", e);
+        }
+    }
+
+    let code = client.get_code(sniper.address(), None).await?;
+    let mut allowlisted = true;
+    for wallet in hot.iter().chain([&operator]) {
+        allowlisted &= sniper.allowlist(*wallet).call().await?;
+    }
+    let checks = [
+        ("runs this build's Sniper", artifact.matches(&code)),
+        (
+            "owned by the operator",
+            sniper.owner().call().await? == operator,
+        ),
+        (
+            "points at friend.tech",
+            sniper.ft().call().await? == friendtech,
+        ),
+        (
+            "funded",
+            client.get_balance(sniper.address(), None).await? >= fund,
+        ),
+        ("wallets allowlisted", allowlisted),
+    ];
+    let mut failed = 0;
+    for (check, ok) in &checks {
+        println!("  {}: {}", check, if *ok { "ok" } else { "FAILED" });
+        if !ok {
+            failed += 1;
+        }
+    }
+    if failed > 0 {
+        return Err(format!(
+            "{} of {} checks of the new Sniper failed, finish setting it up with set-ft, allowlist add or a transfer",
+            failed,
+            checks.len()
+        )
+        .into());
+    }
+    Ok(())
+}
+
+struct Confirm {
+    dry_run: bool,
+    yes: bool,
+}
+
+impl Confirm {
+    // Whether to go ahead and send, asking on stdin unless told not to.
+    fn ask(&self) -> io::Result<bool> {
+        if self.dry_run {
+            println!("Dry run, not sending");
+            return Ok(false);
+        }
+        if self.yes {
+            return Ok(true);
+        }
+
+        print!("Send? [y/N] ");
+        io::stdout().flush()?;
+        let mut answer = String::new();
+        io::stdin().lock().read_line(&mut answer)?;
+        let answer = answer.trim().to_lowercase();
+        Ok(answer == "y" || answer == "yes")
+    }
+}
+
+async fn send<D: ethers::abi::Detokenize>(call: ContractCall<Client, D>) -> CliResult {
+    confirmed(call.send().await?).await
+}
+
+// Wait for a sent tx, failing unless it was mined and succeeded.
+async fn confirmed<P: JsonRpcClient>(pending: PendingTransaction<'_, P>) -> CliResult {
+    let hash = pending.tx_hash();
+    println!("Sent: https://basescan.org/tx/{:?}", hash);
+    match pending.await? {
+        Some(receipt) if receipt.status == Some(U64::one()) => {
+            println!(
+                "Confirmed in block {:?}",
+                receipt.block_number.unwrap_or_default()
+            );
+            Ok(())
+        }
+        Some(receipt) => Err(format!(
+            "{:?} reverted in block {:?}",
+            hash,
+            receipt.block_number.unwrap_or_default()
+        )
+        .into()),
+        None => Err(format!("{:?} was dropped without being mined", hash).into()),
+    }
+}
@@ -0,0 +1,1132 @@
+use crate::{
+    cli::{Cli, Command},
+    keys,
+    ledger::LotMethod,
+    signers::{Refill, Selection},
+};
+use ethers::{
+    signers::LocalWallet,
+    types::{Address, U256},
+    utils::{parse_ether, parse_units, to_checksum},
+};
+use serde::{Deserialize, Serialize, Serializer};
+use std::{fmt, net::SocketAddr, path::PathBuf};
+
+const DEFAULT_CONFIG_PATH: &str = "friendrekt.toml";
+const FRIENDTECH: &str = "0xCF205808Ed36593aa40a44F10c7f7C2F67d4A4d4";
+// Far past any real supply, a share there costs over 600k ETH.
+pub const MAX_SUPPLY_LIMIT: u64 = 100_000;
+
+#[derive(Debug)]
+pub enum ConfigError {
+    Read(PathBuf, std::io::Error),
+    Parse(PathBuf, toml::de::Error),
+    Missing(&'static str, &'static str),
+    Invalid(&'static str, String),
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Read(path, e) => write!(f, "failed to read {:?}: {}", path, e),
+            ConfigError::Parse(path, e) => write!(f, "failed to parse {:?}: {}", path, e),
+            ConfigError::Missing(key, hint) => {
+                write!(
+                    f,
+                    "{} is not set (use {} or set it in the config file)",
+                    key, hint
+                )
+            }
+            ConfigError::Invalid(key, reason) => write!(f, "{} is invalid: {}", key, reason),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RpcConfig {
+    // shown as hosts only, RPC URLs tend to carry API keys
+    #[serde(serialize_with = "host")]
+    pub wss_url: Option<String>,
+    // tried in order when wss_url is down or behind
+    #[serde(serialize_with = "hosts")]
+    pub fallback_wss_urls: Vec<String>,
+    // used for requests and for fetching missed blocks, never subscribed on
+    #[serde(serialize_with = "hosts")]
+    pub http_urls: Vec<String>,
+    pub sequencer_url: String,
+    // reconnect attempts on a dropped websocket before failing over
+    pub reconnects: usize,
+    pub health_secs: u64,
+    // blocks an endpoint can be behind the best one before it's ranked down
+    pub max_head_lag: u64,
+    // resubscribe when no block arrives for this long
+    pub stall_secs: u64,
+    pub max_backfill_blocks: u64,
+    // recent blocks remembered to notice a reorg replacing them
+    pub reorg_depth: usize,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FlashblocksConfig {
+    // the sequencer's flashblocks websocket, uncompressed JSON payloads
+    #[serde(serialize_with = "host")]
+    pub url: Option<String>,
+    // reconnect when nothing arrives for this long
+    pub stall_secs: u64,
+    // append every payload received here, for replay-flashblocks
+    pub record_path: Option<PathBuf>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ContractsConfig {
+    pub friendtech: Address,
+    pub sniper: Option<Address>,
+    // sent to a new Sniper by `friendrekt deploy`
+    pub deploy_fund_eth: f64,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SignerConfig {
+    // the operator: owns the Sniper, runs the CLI and is the treasury hot
+    // wallets are refilled from. One of a plain text key, an encrypted
+    // keystore or an account of the remote signer.
+    #[serde(skip_serializing)]
+    pub private_key: Option<String>,
+    pub keystore: Option<PathBuf>,
+    pub remote: Option<Address>,
+    // snipes are sent from these, from any of the three; none means from
+    // the operator
+    #[serde(skip_serializing)]
+    pub hot_keys: Vec<String>,
+    pub hot_keystores: Vec<PathBuf>,
+    pub hot_remote: Vec<Address>,
+    // the keystores' passphrase, asked for on the terminal otherwise
+    pub password_file: Option<PathBuf>,
+    // JSON-RPC endpoint of a signing daemon, see keys.rs
+    #[serde(serialize_with = "host")]
+    pub remote_url: Option<String>,
+    pub remote_timeout_secs: u64,
+    pub selection: Selection,
+    pub balance_secs: u64,
+    // top a hot wallet up to refill_to_eth once it drops below this
+    pub refill_below_eth: Option<f64>,
+    pub refill_to_eth: Option<f64>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LookupConfig {
+    pub kosetto_url: String,
+    pub followers_url: String,
+    pub timeout_secs: u64,
+}
+
+// Subjects with more than `followers_above` followers get `supply_limit`.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct Tier {
+    pub followers_above: u64,
+    pub supply_limit: u64,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct StrategyConfig {
+    pub amount: u64,
+    pub tiers: Vec<Tier>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct GasConfig {
+    pub gas_limit: u64,
+    // priority fee paid relative to the tx we're following, in percent
+    pub priority_fee_percent: u64,
+    pub max_fee_cap_gwei: Option<f64>,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PrewarmConfig {
+    pub workers: usize,
+    pub queue_size: usize,
+    pub negative_ttl_secs: u64,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PipelineConfig {
+    // buyShares and relay txs worked on at once, across blocks
+    pub workers: usize,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+    // Twitter info kept for this many addresses
+    pub profiles: usize,
+    // looked up again after this long, follower counts change
+    pub profile_ttl_secs: u64,
+    // tx hashes remembered so a block delivered twice isn't handled twice
+    pub seen_txs: usize,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct WatchlistConfig {
+    pub path: PathBuf,
+    pub reload_secs: u64,
+    pub default_supply_limit: u64,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FiltersConfig {
+    pub blocklist_path: PathBuf,
+    pub reload_secs: u64,
+    pub max_subject_dumps: usize,
+    pub backfill_blocks: u64,
+}
+
+// Caps on what the sniping loop spends, ETH amounts. Unset limits don't apply.
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RiskConfig {
+    pub max_eth_per_subject: Option<f64>,
+    pub max_eth_per_hour: Option<f64>,
+    pub max_eth_per_day: Option<f64>,
+    pub max_open_positions: Option<usize>,
+    pub max_gas_eth: Option<f64>,
+    pub gas_window_secs: u64,
+    // balance the Sniper contract never snipes below
+    pub min_balance_eth: f64,
+    // no new snipes while this file exists
+    pub kill_file: PathBuf,
+    pub refresh_secs: u64,
+}
+
+// The limits of RiskConfig on their own, so they can be changed at runtime.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RiskLimits {
+    pub max_eth_per_subject: Option<f64>,
+    pub max_eth_per_hour: Option<f64>,
+    pub max_eth_per_day: Option<f64>,
+    pub max_open_positions: Option<usize>,
+    pub max_gas_eth: Option<f64>,
+    pub min_balance_eth: f64,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SupervisorConfig {
+    // restarts in a row before a failing task takes the bot down
+    pub max_restarts: u32,
+    // how long shutdown waits for snipes in flight
+    pub drain_secs: u64,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PortfolioConfig {
+    // block to start looking for our trades from, i.e. the Sniper deployment
+    pub start_block: u64,
+    pub scan_chunk: u64,
+    // how sells are matched to buys in the ledger
+    pub lot_method: LotMethod,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct MetricsConfig {
+    // where /metrics is served, keep it off public interfaces
+    pub listen: SocketAddr,
+    pub balance_secs: u64,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct TelegramConfig {
+    #[serde(skip_serializing)]
+    pub bot_token: String,
+    pub chat_id: String,
+    #[serde(default = "default_telegram_api")]
+    pub api_url: String,
+}
+
+fn default_telegram_api() -> String {
+    "https://api.telegram.org".to_string()
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NotifyConfig {
+    // each gets {"text": message} POSTed to it
+    #[serde(serialize_with = "hosts")]
+    pub webhook_urls: Vec<String>,
+    pub telegram: Option<TelegramConfig>,
+    // program and arguments, run with the message on stdin
+    pub command: Vec<String>,
+    // events in between are rolled into one message
+    pub min_interval_secs: u64,
+    pub low_balance_eth: Option<f64>,
+    pub balance_secs: u64,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AdminConfig {
+    // keep it on localhost, or behind something that does TLS
+    pub listen: SocketAddr,
+    // sent as `Authorization: Bearer <token>`
+    #[serde(skip_serializing)]
+    pub token: Option<String>,
+    // decisions kept for GET /decisions
+    pub recent_decisions: usize,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuditConfig {
+    // every snipe decision is appended here as a line of JSON
+    pub path: PathBuf,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LatencyConfig {
+    // snipes the percentiles are computed over
+    pub window: usize,
+    pub report_secs: u64,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    #[default]
+    Text,
+    Pretty,
+    Json,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LogConfig {
+    pub format: LogFormat,
+    // tracing filter directives, e.g. "info" or "info,friendrekt::rpc=debug"
+    pub level: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct FeaturesConfig {
+    pub prewarm: bool,
+    pub watchlist: bool,
+    pub filters: bool,
+    pub risk: bool,
+    pub metrics: bool,
+    pub notify: bool,
+    pub admin: bool,
+    pub audit: bool,
+    pub flashblocks: bool,
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Default, Deserialize, Serialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    // the file this was loaded from, if any
+    #[serde(skip)]
+    pub path: Option<PathBuf>,
+    pub rpc: RpcConfig,
+    pub flashblocks: FlashblocksConfig,
+    pub contracts: ContractsConfig,
+    pub signer: SignerConfig,
+    pub lookup: LookupConfig,
+    pub strategy: StrategyConfig,
+    pub gas: GasConfig,
+    pub prewarm: PrewarmConfig,
+    pub pipeline: PipelineConfig,
+    pub cache: CacheConfig,
+    pub watchlist: WatchlistConfig,
+    pub filters: FiltersConfig,
+    pub risk: RiskConfig,
+    pub supervisor: SupervisorConfig,
+    pub portfolio: PortfolioConfig,
+    pub metrics: MetricsConfig,
+    pub latency: LatencyConfig,
+    pub notify: NotifyConfig,
+    pub admin: AdminConfig,
+    pub audit: AuditConfig,
+    pub log: LogConfig,
+    pub features: FeaturesConfig,
+}
+
+impl Default for RpcConfig {
+    fn default() -> Self {
+        RpcConfig {
+            wss_url: None,
+            fallback_wss_urls: Vec::new(),
+            http_urls: Vec::new(),
+            sequencer_url: "https://mainnet-sequencer.base.org/".to_string(),
+            reconnects: 5,
+            health_secs: 5,
+            max_head_lag: 3,
+            stall_secs: 30,
+            max_backfill_blocks: 50,
+            reorg_depth: 64,
+        }
+    }
+}
+
+impl RpcConfig {
+    // The websocket endpoints, primary first.
+    pub fn ws_urls(&self) -> impl Iterator<Item = &String> {
+        self.wss_url.iter().chain(self.fallback_wss_urls.iter())
+    }
+}
+
+impl Default for FlashblocksConfig {
+    fn default() -> Self {
+        FlashblocksConfig {
+            url: None,
+            stall_secs: 10,
+            record_path: None,
+        }
+    }
+}
+
+impl Default for ContractsConfig {
+    fn default() -> Self {
+        ContractsConfig {
+            friendtech: FRIENDTECH.parse().unwrap(),
+            sniper: None,
+            deploy_fund_eth: 0.0,
+        }
+    }
+}
+
+impl Default for SignerConfig {
+    fn default() -> Self {
+        SignerConfig {
+            private_key: None,
+            keystore: None,
+            remote: None,
+            hot_keys: Vec::new(),
+            hot_keystores: Vec::new(),
+            hot_remote: Vec::new(),
+            password_file: None,
+            remote_url: None,
+            remote_timeout_secs: 10,
+            selection: Selection::RoundRobin,
+            balance_secs: 30,
+            refill_below_eth: None,
+            refill_to_eth: None,
+        }
+    }
+}
+
+impl SignerConfig {
+    // Safe to call after load, validate checks the amounts.
+    pub fn refill(&self) -> Option<Refill> {
+        Some(Refill {
+            below: parse_ether(self.refill_below_eth?).unwrap(),
+            to: parse_ether(self.refill_to_eth?).unwrap(),
+        })
+    }
+}
+
+impl Default for LookupConfig {
+    fn default() -> Self {
+        LookupConfig {
+            kosetto_url: "https://prod-api.kosetto.com".to_string(),
+            followers_url: "http://127.0.0.1:8000".to_string(),
+            timeout_secs: 60,
+        }
+    }
+}
+
+impl Default for StrategyConfig {
+    fn default() -> Self {
+        // are we late? the supply_limit is the max shares
+        // we'd be willing to buy at each tier of followers
+        // this limit is checked in Sniper.sol
+        let tier = |followers_above, supply_limit| Tier {
+            followers_above,
+            supply_limit,
+        };
+        StrategyConfig {
+            amount: 5,
+            tiers: vec![
+                tier(1_000_000, 100),
+                tier(500_000, 60),
+                tier(250_000, 60),
+                tier(100_000, 40),
+                tier(20_000, 30),
+            ],
+        }
+    }
+}
+
+impl Default for GasConfig {
+    fn default() -> Self {
+        GasConfig {
+            gas_limit: 1_000_000,
+            priority_fee_percent: 100,
+            max_fee_cap_gwei: None,
+        }
+    }
+}
+
+impl Default for PrewarmConfig {
+    fn default() -> Self {
+        PrewarmConfig {
+            workers: 4,
+            queue_size: 256,
+            negative_ttl_secs: 600,
+        }
+    }
+}
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        PipelineConfig { workers: 64 }
+    }
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig {
+            profiles: 100_000,
+            profile_ttl_secs: 3600,
+            seen_txs: 10_000,
+        }
+    }
+}
+
+impl Default for WatchlistConfig {
+    fn default() -> Self {
+        WatchlistConfig {
+            path: PathBuf::from("watchlist.json"),
+            reload_secs: 5,
+            default_supply_limit: 100,
+        }
+    }
+}
+
+impl Default for FiltersConfig {
+    fn default() -> Self {
+        FiltersConfig {
+            blocklist_path: PathBuf::from("blocklist.json"),
+            reload_secs: 5,
+            max_subject_dumps: 0,
+            backfill_blocks: 1800,
+        }
+    }
+}
+
+impl Default for RiskConfig {
+    fn default() -> Self {
+        RiskConfig {
+            max_eth_per_subject: None,
+            max_eth_per_hour: None,
+            max_eth_per_day: None,
+            max_open_positions: None,
+            max_gas_eth: None,
+            gas_window_secs: 60 * 60,
+            min_balance_eth: 0.0,
+            kill_file: PathBuf::from("friendrekt.kill"),
+            refresh_secs: 10,
+        }
+    }
+}
+
+impl Default for SupervisorConfig {
+    fn default() -> Self {
+        SupervisorConfig {
+            max_restarts: 10,
+            drain_secs: 30,
+        }
+    }
+}
+
+impl Default for PortfolioConfig {
+    fn default() -> Self {
+        PortfolioConfig {
+            start_block: 0,
+            scan_chunk: 2000,
+            lot_method: LotMethod::Fifo,
+        }
+    }
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        MetricsConfig {
+            listen: SocketAddr::from(([127, 0, 0, 1], 9184)),
+            balance_secs: 30,
+        }
+    }
+}
+
+impl Default for NotifyConfig {
+    fn default() -> Self {
+        NotifyConfig {
+            webhook_urls: Vec::new(),
+            telegram: None,
+            command: Vec::new(),
+            min_interval_secs: 30,
+            low_balance_eth: None,
+            balance_secs: 60,
+        }
+    }
+}
+
+impl Default for AdminConfig {
+    fn default() -> Self {
+        AdminConfig {
+            listen: SocketAddr::from(([127, 0, 0, 1], 9185)),
+            token: None,
+            recent_decisions: 200,
+        }
+    }
+}
+
+impl Default for AuditConfig {
+    fn default() -> Self {
+        AuditConfig {
+            path: PathBuf::from("decisions.jsonl"),
+        }
+    }
+}
+
+impl Default for LatencyConfig {
+    fn default() -> Self {
+        LatencyConfig {
+            window: 1000,
+            report_secs: 300,
+        }
+    }
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig {
+            format: LogFormat::Text,
+            level: "info".to_string(),
+        }
+    }
+}
+
+impl Default for FeaturesConfig {
+    fn default() -> Self {
+        FeaturesConfig {
+            prewarm: true,
+            watchlist: true,
+            filters: true,
+            risk: true,
+            metrics: true,
+            notify: true,
+            admin: false,
+            audit: true,
+            flashblocks: false,
+            dry_run: false,
+        }
+    }
+}
+
+impl Default for RiskLimits {
+    fn default() -> Self {
+        RiskConfig::default().limits()
+    }
+}
+
+// Supply limit for a subject with this many followers, 0 if we shouldn't buy.
+pub fn supply_limit(tiers: &[Tier], followers: u64) -> u64 {
+    tiers
+        .iter()
+        .find(|t| followers > t.followers_above)
+        .map(|t| t.supply_limit)
+        .unwrap_or(0)
+}
+
+// Check tiers and put the highest first so the first match wins.
+pub fn check_tiers(tiers: &mut [Tier]) -> Result<(), ConfigError> {
+    if tiers
+        .iter()
+        .any(|t| !(1..=MAX_SUPPLY_LIMIT).contains(&t.supply_limit))
+    {
+        return Err(ConfigError::Invalid(
+            "strategy.tiers",
+            format!("supply_limit must be from 1 to {}", MAX_SUPPLY_LIMIT),
+        ));
+    }
+    tiers.sort_by_key(|t| std::cmp::Reverse(t.followers_above));
+    Ok(())
+}
+
+impl RiskConfig {
+    pub fn limits(&self) -> RiskLimits {
+        RiskLimits {
+            max_eth_per_subject: self.max_eth_per_subject,
+            max_eth_per_hour: self.max_eth_per_hour,
+            max_eth_per_day: self.max_eth_per_day,
+            max_open_positions: self.max_open_positions,
+            max_gas_eth: self.max_gas_eth,
+            min_balance_eth: self.min_balance_eth,
+        }
+    }
+}
+
+impl RiskLimits {
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        let amounts = [
+            ("risk.max_eth_per_subject", self.max_eth_per_subject),
+            ("risk.max_eth_per_hour", self.max_eth_per_hour),
+            ("risk.max_eth_per_day", self.max_eth_per_day),
+            ("risk.max_gas_eth", self.max_gas_eth),
+            ("risk.min_balance_eth", Some(self.min_balance_eth)),
+        ];
+        for (key, eth) in amounts {
+            if let Some(eth) = eth {
+                if !eth.is_finite() || eth < 0.0 || parse_ether(eth).is_err() {
+                    return Err(ConfigError::Invalid(
+                        key,
+                        format!("{} is not a valid ETH amount", eth),
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl GasConfig {
+    // Fees for our snipe, based on those of the tx we're following.
+    pub fn fees(&self, max_fee: U256, prio_fee: U256) -> (U256, U256) {
+        let prio_fee = prio_fee * U256::from(self.priority_fee_percent) / U256::from(100);
+        let mut max_fee = max_fee.max(prio_fee);
+        if let Some(cap) = self.max_fee_cap_gwei {
+            // validated on load
+            let cap: U256 = parse_units(cap, "gwei").unwrap().into();
+            max_fee = max_fee.min(cap);
+        }
+
+        (max_fee, prio_fee.min(max_fee))
+    }
+}
+
+impl Config {
+    // Load the config file and apply the command line on top of it.
+    pub fn load(cli: &Cli) -> Result<Self, ConfigError> {
+        let path = cli.config.clone().or_else(|| {
+            let default = PathBuf::from(DEFAULT_CONFIG_PATH);
+            default.exists().then_some(default)
+        });
+
+        let mut config = match &path {
+            Some(path) => {
+                let data = std::fs::read_to_string(path)
+                    .map_err(|e| ConfigError::Read(path.clone(), e))?;
+                toml::from_str(&data).map_err(|e| ConfigError::Parse(path.clone(), e))?
+            }
+            None => Config::default(),
+        };
+        config.path = path;
+
+        if let Some(url) = &cli.wss_url {
+            config.rpc.wss_url = Some(url.clone());
+        }
+        if let Some(key) = &cli.private_key {
+            config.signer.private_key = Some(key.clone());
+        }
+        if let Some(keys) = &cli.hot_keys {
+            config.signer.hot_keys = keys.clone();
+        }
+        if let Some(address) = cli.ft_address {
+            config.contracts.friendtech = address;
+        }
+        if let Some(address) = cli.sniper_address {
+            config.contracts.sniper = Some(address);
+        }
+        if let Some(url) = &cli.sequencer_url {
+            config.rpc.sequencer_url = url.clone();
+        }
+        if let Some(url) = &cli.followers_url {
+            config.lookup.followers_url = url.clone();
+        }
+        if let Some(amount) = cli.amount {
+            config.strategy.amount = amount;
+        }
+        if let Some(token) = &cli.admin_token {
+            config.admin.token = Some(token.clone());
+        }
+        if cli.dry_run {
+            config.features.dry_run = true;
+        }
+
+        config.validate(cli.command.as_ref().unwrap_or(&Command::Run))?;
+        Ok(config)
+    }
+
+    // Only what `command` uses, so e.g. searching the audit log works
+    // without a node or a signer.
+    fn validate(&mut self, command: &Command) -> Result<(), ConfigError> {
+        if let Err(e) = tracing_subscriber::EnvFilter::try_new(&self.log.level) {
+            return Err(ConfigError::Invalid("log.level", e.to_string()));
+        }
+
+        for url in &self.notify.webhook_urls {
+            check_url("notify.webhook_urls", url)?;
+        }
+        if let Some(telegram) = &self.notify.telegram {
+            check_url("notify.telegram.api_url", &telegram.api_url)?;
+        }
+        if let Some(eth) = self.notify.low_balance_eth {
+            if !eth.is_finite() || eth < 0.0 || parse_ether(eth).is_err() {
+                return Err(ConfigError::Invalid(
+                    "notify.low_balance_eth",
+                    format!("{} is not a valid ETH amount", eth),
+                ));
+            }
+        }
+        if self.notify.balance_secs == 0 {
+            return Err(ConfigError::Invalid(
+                "notify.balance_secs",
+                "must be at least 1".to_string(),
+            ));
+        }
+
+        if command.is_local() {
+            return Ok(());
+        }
+        // deploying makes the Sniper rather than needing one
+        let deploying = matches!(command, Command::Deploy { .. });
+
+        let wss_url = self.rpc.wss_url.as_ref().ok_or(ConfigError::Missing(
+            "rpc.wss_url",
+            "--wss-url or BASE_WSS_URL",
+        ))?;
+        check_url("rpc.wss_url", wss_url)?;
+        if !wss_url.starts_with("ws://") && !wss_url.starts_with("wss://") {
+            return Err(ConfigError::Invalid(
+                "rpc.wss_url",
+                "expected a ws:// or wss:// URL".to_string(),
+            ));
+        }
+        for url in &self.rpc.fallback_wss_urls {
+            check_url("rpc.fallback_wss_urls", url)?;
+            if !url.starts_with("ws://") && !url.starts_with("wss://") {
+                return Err(ConfigError::Invalid(
+                    "rpc.fallback_wss_urls",
+                    format!("{} is not a ws:// or wss:// URL", url),
+                ));
+            }
+        }
+        for url in &self.rpc.http_urls {
+            check_url("rpc.http_urls", url)?;
+            if !url.starts_with("http://") && !url.starts_with("https://") {
+                return Err(ConfigError::Invalid(
+                    "rpc.http_urls",
+                    format!("{} is not an http:// or https:// URL", url),
+                ));
+            }
+        }
+        if self.rpc.health_secs == 0 || self.rpc.stall_secs == 0 {
+            return Err(ConfigError::Invalid(
+                "rpc",
+                "health_secs and stall_secs must be at least 1".to_string(),
+            ));
+        }
+        if self.rpc.reorg_depth == 0 {
+            return Err(ConfigError::Invalid(
+                "rpc.reorg_depth",
+                "must be at least 1".to_string(),
+            ));
+        }
+
+        if self.features.flashblocks {
+            let url = self.flashblocks.url.as_ref().ok_or(ConfigError::Invalid(
+                "flashblocks.url",
+                "must be set when features.flashblocks is on".to_string(),
+            ))?;
+            check_url("flashblocks.url", url)?;
+            if !url.starts_with("ws://") && !url.starts_with("wss://") {
+                return Err(ConfigError::Invalid(
+                    "flashblocks.url",
+                    "expected a ws:// or wss:// URL".to_string(),
+                ));
+            }
+        }
+        if self.flashblocks.stall_secs == 0 {
+            return Err(ConfigError::Invalid(
+                "flashblocks.stall_secs",
+                "must be at least 1".to_string(),
+            ));
+        }
+
+        check_url("rpc.sequencer_url", &self.rpc.sequencer_url)?;
+        check_url("lookup.kosetto_url", &self.lookup.kosetto_url)?;
+        check_url("lookup.followers_url", &self.lookup.followers_url)?;
+
+        let operators = [
+            self.signer.private_key.is_some(),
+            self.signer.keystore.is_some(),
+            self.signer.remote.is_some(),
+        ]
+        .into_iter()
+        .filter(|set| *set)
+        .count();
+        match operators {
+            0 => {
+                return Err(ConfigError::Missing(
+                    "signer.private_key",
+                    "--private-key or PRIVATE_KEY, or signer.keystore or signer.remote",
+                ))
+            }
+            1 => {}
+            _ => {
+                return Err(ConfigError::Invalid(
+                    "signer",
+                    "set only one of private_key, keystore and remote".to_string(),
+                ))
+            }
+        }
+        if let Some(key) = &self.signer.private_key {
+            if key.parse::<LocalWallet>().is_err() {
+                return Err(ConfigError::Invalid(
+                    "signer.private_key",
+                    "not a valid hex private key".to_string(),
+                ));
+            }
+        }
+        if self
+            .signer
+            .hot_keys
+            .iter()
+            .any(|key| key.parse::<LocalWallet>().is_err())
+        {
+            return Err(ConfigError::Invalid(
+                "signer.hot_keys",
+                "not all valid hex private keys".to_string(),
+            ));
+        }
+        if let Some(path) = keys::missing_keystore(&self.signer) {
+            return Err(ConfigError::Invalid(
+                "signer.keystore",
+                format!("{:?} doesn't exist", path),
+            ));
+        }
+        if self.signer.remote.is_some() || !self.signer.hot_remote.is_empty() {
+            let url = self.signer.remote_url.as_ref().ok_or(ConfigError::Invalid(
+                "signer.remote_url",
+                "must be set to sign with remote or hot_remote".to_string(),
+            ))?;
+            check_url("signer.remote_url", url)?;
+        }
+        if self.signer.remote_timeout_secs == 0 {
+            return Err(ConfigError::Invalid(
+                "signer.remote_timeout_secs",
+                "must be at least 1".to_string(),
+            ));
+        }
+        if self.signer.balance_secs == 0 {
+            return Err(ConfigError::Invalid(
+                "signer.balance_secs",
+                "must be at least 1".to_string(),
+            ));
+        }
+        for (key, eth) in [
+            ("signer.refill_below_eth", self.signer.refill_below_eth),
+            ("signer.refill_to_eth", self.signer.refill_to_eth),
+        ] {
+            if let Some(eth) = eth {
+                if !eth.is_finite() || eth < 0.0 || parse_ether(eth).is_err() {
+                    return Err(ConfigError::Invalid(
+                        key,
+                        format!("{} is not a valid ETH amount", eth),
+                    ));
+                }
+            }
+        }
+        match (self.signer.refill_below_eth, self.signer.refill_to_eth) {
+            (Some(below), Some(to)) if to <= below => {
+                return Err(ConfigError::Invalid(
+                    "signer.refill_to_eth",
+                    "must be more than refill_below_eth".to_string(),
+                ))
+            }
+            (Some(_), None) | (None, Some(_)) => {
+                return Err(ConfigError::Invalid(
+                    "signer",
+                    "refill_below_eth and refill_to_eth go together".to_string(),
+                ))
+            }
+            _ => {}
+        }
+
+        let eth = self.contracts.deploy_fund_eth;
+        if !eth.is_finite() || eth < 0.0 || parse_ether(eth).is_err() {
+            return Err(ConfigError::Invalid(
+                "contracts.deploy_fund_eth",
+                format!("{} is not a valid ETH amount", eth),
+            ));
+        }
+        if self.contracts.sniper.is_none() && !deploying {
+            return Err(ConfigError::Missing(
+                "contracts.sniper",
+                "--sniper-address or SNIPER_ADDRESS",
+            ));
+        }
+
+        if self.strategy.amount == 0 {
+            return Err(ConfigError::Invalid(
+                "strategy.amount",
+                "must be at least 1".to_string(),
+            ));
+        }
+        check_tiers(&mut self.strategy.tiers)?;
+        if !(1..=MAX_SUPPLY_LIMIT).contains(&self.watchlist.default_supply_limit) {
+            return Err(ConfigError::Invalid(
+                "watchlist.default_supply_limit",
+                format!("must be from 1 to {}", MAX_SUPPLY_LIMIT),
+            ));
+        }
+
+        if self.gas.gas_limit == 0 {
+            return Err(ConfigError::Invalid(
+                "gas.gas_limit",
+                "must be greater than 0".to_string(),
+            ));
+        }
+        if let Some(cap) = self.gas.max_fee_cap_gwei {
+            if !cap.is_finite() || cap <= 0.0 || parse_units(cap, "gwei").is_err() {
+                return Err(ConfigError::Invalid(
+                    "gas.max_fee_cap_gwei",
+                    format!("{} is not a positive gwei amount", cap),
+                ));
+            }
+        }
+
+        if self.prewarm.workers == 0 || self.prewarm.queue_size == 0 {
+            return Err(ConfigError::Invalid(
+                "prewarm",
+                "workers and queue_size must be at least 1".to_string(),
+            ));
+        }
+        self.risk.limits().validate()?;
+        if self.risk.gas_window_secs == 0 || self.risk.refresh_secs == 0 {
+            return Err(ConfigError::Invalid(
+                "risk",
+                "gas_window_secs and refresh_secs must be at least 1".to_string(),
+            ));
+        }
+
+        if self.metrics.balance_secs == 0 {
+            return Err(ConfigError::Invalid(
+                "metrics.balance_secs",
+                "must be at least 1".to_string(),
+            ));
+        }
+
+        if self.latency.window == 0 || self.latency.report_secs == 0 {
+            return Err(ConfigError::Invalid(
+                "latency",
+                "window and report_secs must be at least 1".to_string(),
+            ));
+        }
+
+        for (key, size) in [
+            ("pipeline.workers", self.pipeline.workers),
+            ("cache.profiles", self.cache.profiles),
+            ("cache.seen_txs", self.cache.seen_txs),
+        ] {
+            if size == 0 {
+                return Err(ConfigError::Invalid(key, "must be at least 1".to_string()));
+            }
+        }
+        if self.admin.recent_decisions == 0 {
+            return Err(ConfigError::Invalid(
+                "admin.recent_decisions",
+                "must be at least 1".to_string(),
+            ));
+        }
+        if self.features.admin && self.admin.token.is_none() {
+            return Err(ConfigError::Missing(
+                "admin.token",
+                "--admin-token or ADMIN_TOKEN",
+            ));
+        }
+
+        if self.portfolio.scan_chunk == 0 {
+            return Err(ConfigError::Invalid(
+                "portfolio.scan_chunk",
+                "must be at least 1".to_string(),
+            ));
+        }
+        if self.watchlist.reload_secs == 0 || self.filters.reload_secs == 0 {
+            return Err(ConfigError::Invalid(
+                "reload_secs",
+                "must be at least 1".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    pub fn sniper(&self) -> Address {
+        self.contracts.sniper.unwrap()
+    }
+
+    // Point contracts.sniper in the config file at `address`, leaving the
+    // rest of the file, comments and all, as it was. Without a config file,
+    // one is made at the default path. Returns the file written.
+    pub fn write_sniper(&self, address: Address) -> Result<PathBuf, Box<dyn std::error::Error>> {
+        let path = self
+            .path
+            .clone()
+            .unwrap_or_else(|| PathBuf::from(DEFAULT_CONFIG_PATH));
+        let mut doc = match std::fs::read_to_string(&path) {
+            Ok(data) => data.parse::<toml_edit::DocumentMut>()?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Default::default(),
+            Err(e) => return Err(ConfigError::Read(path, e).into()),
+        };
+        if !doc.contains_key("contracts") {
+            doc["contracts"] = toml_edit::table();
+        }
+        doc["contracts"]["sniper"] = toml_edit::value(to_checksum(&address, None));
+        std::fs::write(&path, doc.to_string())
+            .map_err(|e| format!("failed to write {:?}: {}", path, e))?;
+        Ok(path)
+    }
+}
+
+fn url_host(url: &str) -> String {
+    reqwest::Url::parse(url)
+        .ok()
+        .and_then(|u| u.host_str().map(str::to_string))
+        .unwrap_or_else(|| "?".to_string())
+}
+
+fn host<S: Serializer>(url: &Option<String>, serializer: S) -> Result<S::Ok, S::Error> {
+    match url {
+        Some(url) => serializer.serialize_some(&url_host(url)),
+        None => serializer.serialize_none(),
+    }
+}
+
+fn hosts<S: Serializer>(urls: &[String], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(urls.iter().map(|url| url_host(url)))
+}
+
+fn check_url(key: &'static str, url: &str) -> Result<(), ConfigError> {
+    reqwest::Url::parse(url)
+        .map(|_| ())
+        .map_err(|e| ConfigError::Invalid(key, format!("{}: {}", url, e)))
+}
@@ -0,0 +1,568 @@
+use crate::{math, prod_kosetto::TwitterInfo};
+use ethers::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::VecDeque,
+    fmt,
+    fs::File,
+    io::{BufRead, BufReader},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex as SyncMutex},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tokio::{
+    fs::OpenOptions,
+    io::{AsyncWriteExt, BufWriter},
+    sync::{mpsc, Mutex},
+    task::JoinHandle,
+};
+use tracing::{error, info};
+
+// Decisions waiting to be written. Once it's full, recording waits for the
+// disk rather than piling up in memory.
+const LOG_QUEUE: usize = 1024;
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_secs()
+}
+
+// What we did about a buyShares or relay tx, and why. One per candidate, written to
+// the audit log as a line of JSON.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+pub struct Decision {
+    // unix seconds
+    pub at: u64,
+    pub block: Option<u64>,
+    #[serde(default)]
+    pub block_hash: Option<H256>,
+    pub tx: H256,
+    pub from: Address,
+    pub subject: Address,
+    pub handle: Option<String>,
+    pub followers: Option<u64>,
+    // the subject's share supply around when we decided, if it could be read
+    #[serde(default)]
+    pub supply: Option<u64>,
+    // where the profile came from: cache, watchlist or kosetto
+    pub source: Option<String>,
+    // snipe, skip, dry_run, error or prewarm
+    pub decision: String,
+    // why we skipped or failed, e.g. the filter or risk limit that rejected it
+    pub reason: Option<String>,
+    pub amount: Option<u64>,
+    pub supply_limit: Option<u64>,
+    // most the snipe could cost, buying right at the supply limit
+    pub quote_eth: Option<f64>,
+    pub snipe: Option<String>,
+    pub latency_ms: u64,
+    // the tx's block was reorged out after we decided
+    #[serde(default)]
+    pub orphaned: bool,
+}
+
+impl Decision {
+    pub fn new(tx: &Transaction) -> Self {
+        Decision {
+            at: now(),
+            block: tx.block_number.map(|n| n.as_u64()),
+            block_hash: tx.block_hash,
+            tx: tx.hash,
+            from: tx.from,
+            // a snipe buys shares of whoever sent the trade
+            subject: tx.from,
+            handle: None,
+            followers: None,
+            supply: None,
+            source: None,
+            decision: String::new(),
+            reason: None,
+            amount: None,
+            supply_limit: None,
+            quote_eth: None,
+            snipe: None,
+            latency_ms: 0,
+            orphaned: false,
+        }
+    }
+
+    pub fn source(mut self, source: &str) -> Self {
+        self.source = Some(source.to_string());
+        self
+    }
+
+    pub fn profile(mut self, info: &TwitterInfo) -> Self {
+        self.handle = Some(info.twitter_username.clone());
+        self.followers = Some(info.followers);
+        self
+    }
+
+    pub fn supply(mut self, supply: Option<u64>) -> Self {
+        self.supply = supply;
+        self
+    }
+
+    pub fn quote(mut self, amount: U256, supply_limit: u64) -> Self {
+        self.amount = Some(amount.low_u64());
+        self.supply_limit = Some(supply_limit);
+        self.quote_eth = Some(math::wei_to_eth(math::get_price_after_fee(
+            U256::from(supply_limit),
+            amount,
+        )));
+        self
+    }
+
+    pub fn outcome(mut self, decision: &str, latency_ms: u64) -> Self {
+        self.at = now();
+        self.decision = decision.to_string();
+        self.latency_ms = latency_ms;
+        self
+    }
+
+    pub fn reason(mut self, reason: impl fmt::Display) -> Self {
+        self.reason = Some(reason.to_string());
+        self
+    }
+
+    pub fn snipe(mut self, hash: Option<String>) -> Self {
+        self.snipe = hash;
+        self
+    }
+}
+
+impl fmt::Display for Decision {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}  {:<7} {:?} {}",
+            utc(self.at),
+            self.decision,
+            self.subject,
+            self.handle.as_deref().unwrap_or("-")
+        )?;
+        if let Some(followers) = self.followers {
+            write!(f, " ({} followers)", followers)?;
+        }
+        if let Some(supply) = self.supply {
+            write!(f, " at supply {}", supply)?;
+        }
+        if let (Some(amount), Some(limit), Some(quote)) =
+            (self.amount, self.supply_limit, self.quote_eth)
+        {
+            write!(f, " {} up to supply {}, <= {:.6} ETH", amount, limit, quote)?;
+        }
+        if let Some(reason) = &self.reason {
+            write!(f, ": {}", reason)?;
+        }
+        if let Some(snipe) = &self.snipe {
+            write!(f, " -> {}", snipe)?;
+        }
+        write!(f, " [tx {:?}, {}ms]", self.tx, self.latency_ms)?;
+        if self.orphaned {
+            write!(f, " (block reorged out)")?;
+        }
+        Ok(())
+    }
+}
+
+// The last `capacity` decisions for the admin API, and every one of them
+// appended to the audit log if there is one.
+pub struct Decisions {
+    recent: Mutex<VecDeque<Decision>>,
+    capacity: usize,
+    // taken by close, which then waits for the writer
+    log: SyncMutex<Option<mpsc::Sender<Decision>>>,
+    writer: Mutex<Option<JoinHandle<()>>>,
+}
+
+impl Decisions {
+    pub fn new(capacity: usize, log: Option<PathBuf>) -> Arc<Self> {
+        let (log, writer) = match log {
+            Some(path) => {
+                let (log, rx) = mpsc::channel(LOG_QUEUE);
+                // the hot path only queues, writing happens here
+                (Some(log), Some(tokio::spawn(write_log(path, rx))))
+            }
+            None => (None, None),
+        };
+
+        Arc::new(Decisions {
+            recent: Mutex::new(VecDeque::with_capacity(capacity)),
+            capacity,
+            log: SyncMutex::new(log),
+            writer: Mutex::new(writer),
+        })
+    }
+
+    async fn log(&self, decision: Decision) {
+        let log = self.log.lock().unwrap_or_else(|e| e.into_inner()).clone();
+        if let Some(log) = log {
+            let _ = log.send(decision).await;
+        }
+    }
+
+    // Stop logging and wait for what's queued to be written, on shutdown.
+    // Decisions recorded after this are only kept in memory.
+    pub async fn close(&self) {
+        self.log.lock().unwrap_or_else(|e| e.into_inner()).take();
+        if let Some(writer) = self.writer.lock().await.take() {
+            let _ = writer.await;
+        }
+    }
+
+    pub async fn record(&self, decision: Decision) {
+        self.log(decision.clone()).await;
+
+        let mut recent = self.recent.lock().await;
+        if recent.len() == self.capacity {
+            recent.pop_front();
+        }
+        recent.push_back(decision);
+    }
+
+    // Mark the decisions on txs in `blocks`, which a reorg just took out, as
+    // orphaned and append that to the audit log. Only the last `capacity`
+    // decisions are looked at, far more than a reorg on Base can reach.
+    // Returns the decisions marked.
+    pub async fn retract(&self, blocks: &[H256]) -> Vec<Decision> {
+        let mut retracted = Vec::new();
+        for decision in self.recent.lock().await.iter_mut() {
+            if !decision.orphaned && decision.block_hash.is_some_and(|b| blocks.contains(&b)) {
+                decision.orphaned = true;
+                retracted.push(decision.clone());
+            }
+        }
+
+        for decision in &retracted {
+            self.log(decision.clone()).await;
+        }
+        retracted
+    }
+
+    // Newest first.
+    pub async fn recent(&self, limit: usize) -> Vec<Decision> {
+        self.recent
+            .lock()
+            .await
+            .iter()
+            .rev()
+            .take(limit)
+            .cloned()
+            .collect()
+    }
+}
+
+async fn write_log(path: PathBuf, mut rx: mpsc::Receiver<Decision>) {
+    let file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(&path)
+        .await;
+    let mut file = match file {
+        Ok(file) => BufWriter::new(file),
+        Err(e) => {
+            error!(
+                ?path,
+                "Failed to open audit log, decisions won't be kept: {}", e
+            );
+            return;
+        }
+    };
+    info!(?path, "Writing decisions to the audit log");
+
+    while let Some(decision) = rx.recv().await {
+        let line = match serde_json::to_string(&decision) {
+            Ok(line) => line,
+            Err(e) => {
+                error!("Failed to serialize decision: {}", e);
+                continue;
+            }
+        };
+        if let Err(e) = file.write_all(format!("{}\n", line).as_bytes()).await {
+            error!(?path, "Failed to write to audit log: {}", e);
+        }
+        // a burst goes out in one write, and nothing waits in the buffer for
+        // the next decision
+        if rx.is_empty() {
+            if let Err(e) = file.flush().await {
+                error!(?path, "Failed to write to audit log: {}", e);
+            }
+        }
+    }
+}
+
+// Which decisions to read back from the audit log.
+pub struct Query {
+    // matches the buyShares subject or the sender
+    pub subject: Option<Address>,
+    pub since: Option<u64>,
+    pub until: Option<u64>,
+    pub decision: Option<String>,
+    // the most recent this many
+    pub limit: usize,
+}
+
+impl Query {
+    fn matches(&self, d: &Decision) -> bool {
+        self.subject.is_none_or(|s| d.subject == s || d.from == s)
+            && self.since.is_none_or(|since| d.at >= since)
+            && self.until.is_none_or(|until| d.at <= until)
+            && self
+                .decision
+                .as_ref()
+                .is_none_or(|decision| d.decision.eq_ignore_ascii_case(decision))
+    }
+}
+
+// Read the decisions matching `query` from the audit log, oldest first.
+pub fn query(path: &Path, query: &Query) -> Result<Vec<Decision>, Box<dyn std::error::Error>> {
+    let file = File::open(path).map_err(|e| format!("failed to open {:?}: {}", path, e))?;
+    let mut found = VecDeque::new();
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let decision: Decision = match serde_json::from_str(&line) {
+            Ok(decision) => decision,
+            Err(e) => {
+                // e.g. a line cut short by a crash, the rest is still good
+                eprintln!("Skipping line {} of {:?}: {}", i + 1, path, e);
+                continue;
+            }
+        };
+        if query.matches(&decision) {
+            // a retraction replaces the decision it retracts
+            if decision.orphaned {
+                found.retain(|d: &Decision| {
+                    d.tx != decision.tx || d.block_hash != decision.block_hash
+                });
+            }
+            if found.len() == query.limit {
+                found.pop_front();
+            }
+            found.push_back(decision);
+        }
+    }
+
+    Ok(found.into())
+}
+
+// Unix seconds, or a duration ago such as 30m, 12h or 7d.
+pub fn parse_time(s: &str) -> Result<u64, String> {
+    if let Ok(secs) = s.parse() {
+        return Ok(secs);
+    }
+
+    let unit = match s.chars().last() {
+        Some('s') => 1,
+        Some('m') => 60,
+        Some('h') => 3600,
+        Some('d') => 86400,
+        _ => {
+            return Err(format!(
+                "{} is neither unix seconds nor like 30m, 12h, 7d",
+                s
+            ))
+        }
+    };
+    let n: u64 = s[..s.len() - 1]
+        .parse()
+        .map_err(|_| format!("{} is neither unix seconds nor like 30m, 12h, 7d", s))?;
+    Ok(now().saturating_sub(n.saturating_mul(unit)))
+}
+
+// YYYY-MM-DD HH:MM:SS in UTC.
+fn utc(secs: u64) -> String {
+    let days = (secs / 86400) as i64;
+    let rem = secs % 86400;
+
+    // civil from days, http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02}",
+        year,
+        month,
+        day,
+        rem / 3600,
+        rem % 3600 / 60,
+        rem % 60
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decision(n: u64, from: Address, outcome: &str) -> Decision {
+        let tx = Transaction {
+            hash: H256::from_low_u64_be(n),
+            from,
+            block_number: Some(n.into()),
+            block_hash: Some(H256::from_low_u64_be(1000 + n)),
+            ..Default::default()
+        };
+        let mut decision = Decision::new(&tx).outcome(outcome, 1);
+        decision.at = n;
+        decision
+    }
+
+    fn everything() -> Query {
+        Query {
+            subject: None,
+            since: None,
+            until: None,
+            decision: None,
+            limit: 100,
+        }
+    }
+
+    // `lines` written as an audit log, and read back with `query`
+    fn read(name: &str, lines: &[String], query: &Query) -> Vec<u64> {
+        let path = std::env::temp_dir().join(format!(
+            "friendrekt-decisions-{}-{}.jsonl",
+            std::process::id(),
+            name
+        ));
+        std::fs::write(&path, lines.join("\n")).unwrap();
+        let found = super::query(&path, query).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        found.iter().map(|d| d.at).collect()
+    }
+
+    fn lines(decisions: &[Decision]) -> Vec<String> {
+        decisions
+            .iter()
+            .map(|d| serde_json::to_string(d).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn query_filters() {
+        let a = Address::repeat_byte(1);
+        let b = Address::repeat_byte(2);
+        let log = lines(&[
+            decision(1, a, "skip"),
+            decision(2, b, "snipe"),
+            decision(3, a, "snipe"),
+            decision(4, b, "dry_run"),
+        ]);
+
+        assert_eq!(read("all", &log, &everything()), [1, 2, 3, 4]);
+        let by_subject = Query {
+            subject: Some(a),
+            ..everything()
+        };
+        assert_eq!(read("subject", &log, &by_subject), [1, 3]);
+        let by_decision = Query {
+            decision: Some("SNIPE".to_string()),
+            ..everything()
+        };
+        assert_eq!(read("decision", &log, &by_decision), [2, 3]);
+        let by_time = Query {
+            since: Some(2),
+            until: Some(3),
+            ..everything()
+        };
+        assert_eq!(read("time", &log, &by_time), [2, 3]);
+        let latest = Query {
+            limit: 2,
+            ..everything()
+        };
+        assert_eq!(read("limit", &log, &latest), [3, 4]);
+    }
+
+    #[test]
+    fn query_skips_unreadable_lines() {
+        let a = Address::repeat_byte(1);
+        let mut log = lines(&[decision(1, a, "skip"), decision(2, a, "skip")]);
+        log.insert(1, "{\"at\": 5, \"tx\"".to_string());
+        log.insert(1, String::new());
+
+        assert_eq!(read("unreadable", &log, &everything()), [1, 2]);
+    }
+
+    #[test]
+    fn retraction_replaces_the_decision() {
+        let a = Address::repeat_byte(1);
+        let mut retracted = decision(1, a, "snipe");
+        retracted.orphaned = true;
+        retracted.at = 5;
+        let log = lines(&[decision(1, a, "snipe"), decision(2, a, "skip"), retracted]);
+
+        assert_eq!(read("retraction", &log, &everything()), [2, 5]);
+    }
+
+    #[tokio::test]
+    async fn retract_marks_decisions_in_orphaned_blocks() {
+        let a = Address::repeat_byte(1);
+        let decisions = Decisions::new(10, None);
+        for n in 1..=3 {
+            decisions.record(decision(n, a, "skip")).await;
+        }
+
+        let orphaned = [H256::from_low_u64_be(1002), H256::from_low_u64_be(1003)];
+        let retracted = decisions.retract(&orphaned).await;
+        assert_eq!(
+            retracted.iter().map(|d| d.tx).collect::<Vec<_>>(),
+            [H256::from_low_u64_be(2), H256::from_low_u64_be(3)]
+        );
+        // only once
+        assert!(decisions.retract(&orphaned).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn close_writes_everything_queued() {
+        let a = Address::repeat_byte(1);
+        let path = std::env::temp_dir().join(format!(
+            "friendrekt-decisions-{}-close.jsonl",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&path);
+        let decisions = Decisions::new(10, Some(path.clone()));
+        // more than the queue holds, so some wait on the writer
+        let n = LOG_QUEUE as u64 + 100;
+        for i in 1..=n {
+            decisions.record(decision(i, a, "skip")).await;
+        }
+        decisions.close().await;
+        // only kept in memory from here on
+        decisions.record(decision(n + 1, a, "skip")).await;
+
+        let mut query = everything();
+        query.limit = usize::MAX;
+        let found = super::query(&path, &query).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(found.len() as u64, n);
+        assert_eq!(found.last().unwrap().at, n);
+    }
+
+    #[test]
+    fn parse_time_takes_seconds_or_durations() {
+        assert_eq!(parse_time("1700000000"), Ok(1_700_000_000));
+        let ago = now() - parse_time("30m").unwrap();
+        assert!((1800..1805).contains(&ago));
+        let ago = now() - parse_time("7d").unwrap();
+        assert!((7 * 86400..7 * 86400 + 5).contains(&ago));
+        // not before the epoch
+        assert_eq!(parse_time("999999999999999999d"), Ok(0));
+    }
+
+    #[test]
+    fn parse_time_rejects_anything_else() {
+        for s in ["", "m", "30w", "-5m", "1.5h", "tomorrow", "5é"] {
+            assert!(parse_time(s).is_err(), "{:?}", s);
+        }
+    }
+}
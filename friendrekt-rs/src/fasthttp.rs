@@ -1,8 +1,13 @@
+use crate::metrics::METRICS;
 use serde::{Deserialize, Serialize};
+use std::time::Instant;
+use tracing::warn;
 
 pub struct FastHttp {
     pub rpc: String,
     pub client: reqwest::Client,
+    // host only, for metrics
+    endpoint: String,
 }
 
 #[derive(Serialize, Debug)]
@@ -13,12 +18,14 @@ struct Params {
     id: i32,
 }
 
+#[allow(dead_code)]
 #[derive(Deserialize, Debug)]
 struct JSONError {
     code: i32,
     message: String,
 }
 
+#[allow(dead_code)]
 #[derive(Deserialize, Debug)]
 struct Ret {
     jsonrpc: String,
@@ -29,12 +36,29 @@ struct Ret {
 
 impl FastHttp {
     pub fn new(rpc: String) -> Self {
+        let endpoint = reqwest::Url::parse(&rpc)
+            .ok()
+            .and_then(|u| u.host_str().map(str::to_string))
+            .unwrap_or_else(|| "?".to_string());
         FastHttp {
             client: reqwest::Client::new(),
             rpc,
+            endpoint,
         }
     }
+
     pub async fn send_request(&self, request: String) -> Option<String> {
+        let started = Instant::now();
+        let hash = self.send(request).await;
+        let result = if hash.is_some() { "ok" } else { "failed" };
+        METRICS
+            .broadcast
+            .with_label_values(&[&self.endpoint, result])
+            .observe(started.elapsed().as_secs_f64());
+        hash
+    }
+
+    async fn send(&self, request: String) -> Option<String> {
         let request_params = Params {
             jsonrpc: "2.0".to_string(),
             method: "eth_sendRawTransaction".to_string(),
@@ -52,14 +76,14 @@ impl FastHttp {
                 let data: Result<Ret, _> = res.json().await;
                 match data {
                     Ok(data) => {
-                        if data.result.is_some() {
-                            Some(data.result.unwrap())
-                        } else if data.error.is_some() {
-                            println!("Error: {:?}", data.error.unwrap());
-                            return None;
+                        if let Some(result) = data.result {
+                            Some(result)
+                        } else if let Some(error) = data.error {
+                            warn!(?error, "Sequencer rejected the transaction");
+                            None
                         } else {
-                            println!("Unknown error");
-                            return None;
+                            warn!("Sequencer returned neither a result nor an error");
+                            None
                         }
                     }
                     Err(_) => None,
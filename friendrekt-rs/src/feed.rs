@@ -0,0 +1,363 @@
+use crate::{
+    metrics::METRICS,
+    rpc::Failover,
+    supervisor::{Shutdown, TaskError},
+};
+use ethers::prelude::*;
+use std::{
+    collections::VecDeque,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::{mpsc, Mutex};
+use tracing::{info, warn};
+
+// A block as handed to the bot, with when we first heard of it and when we
+// had its transactions.
+pub struct NewBlock {
+    pub block: Block<Transaction>,
+    // the header notification, for backfilled blocks the one that revealed the gap
+    pub header_at: Instant,
+    pub fetched_at: Instant,
+    // blocks handed out before that this one's branch replaced, newest
+    // first; only set on the first block of the new branch
+    pub orphaned: Vec<BlockRef>,
+    // set when it's not a block but a flashblock: its index within the
+    // block, and only the txs it added
+    pub flashblock: Option<u64>,
+}
+
+// A block handed out. Once a reorg takes it out of the chain its
+// transactions may or may not show up again in the blocks that replaced it.
+pub struct BlockRef {
+    pub number: u64,
+    pub hash: H256,
+    pub txs: Vec<H256>,
+}
+
+fn block_number(block: &Block<Transaction>) -> Result<u64, TaskError> {
+    block
+        .number
+        .map(|n| n.as_u64())
+        .ok_or(TaskError::Retry("block without a number".to_string()))
+}
+
+// The last `depth` blocks handed out, oldest first.
+struct Chain {
+    blocks: VecDeque<BlockRef>,
+    depth: usize,
+}
+
+// Where a new branch, walked back to its oldest block so far, meets the chain.
+enum Link {
+    // on top of the chain once these blocks, newest first, are taken off
+    Joins(Vec<BlockRef>),
+    // not yet, the oldest block's parent is needed too
+    Parent,
+}
+
+impl Chain {
+    fn new(depth: usize) -> Self {
+        Chain {
+            blocks: VecDeque::with_capacity(depth + 1),
+            depth,
+        }
+    }
+
+    // Whether a block announced as `number` and `hash` is nothing new:
+    // already handed out, or older than anything we track.
+    fn knows(&self, number: u64, hash: H256) -> bool {
+        self.blocks.iter().any(|b| b.hash == hash)
+            || self
+                .blocks
+                .front()
+                .is_some_and(|first| number < first.number)
+    }
+
+    // `oldest` is the oldest block of a branch of `fetched` blocks leading up
+    // to the one announced as `number`.
+    fn link(
+        &mut self,
+        oldest: &Block<Transaction>,
+        number: u64,
+        fetched: usize,
+        max_backfill: u64,
+    ) -> Result<Link, TaskError> {
+        if let Some(i) = self
+            .blocks
+            .iter()
+            .position(|b| b.hash == oldest.parent_hash)
+        {
+            return Ok(Link::Joins(self.blocks.drain(i + 1..).rev().collect()));
+        }
+        let (first, last) = match (self.blocks.front(), self.blocks.back()) {
+            (Some(first), Some(last)) => (first.number, last.number),
+            // the first block since we started
+            _ => return Ok(Link::Joins(Vec::new())),
+        };
+
+        let n = block_number(oldest)?;
+        if n <= first {
+            warn!(
+                depth = last + 1 - n,
+                "Reorg deeper than the blocks we track, treating them all as orphaned"
+            );
+            return Ok(Link::Joins(self.blocks.drain(..).rev().collect()));
+        }
+        if n > last + 1 && fetched as u64 > max_backfill {
+            warn!(
+                missed = number - last - 1,
+                skipped = n - last - 1,
+                "Missed too many blocks, skipping the oldest"
+            );
+            return Ok(Link::Joins(Vec::new()));
+        }
+        Ok(Link::Parent)
+    }
+
+    fn push(&mut self, block: &Block<Transaction>) -> Result<(), TaskError> {
+        self.blocks.push_back(BlockRef {
+            number: block_number(block)?,
+            hash: block.hash.unwrap_or_default(),
+            txs: block.transactions.iter().map(|tx| tx.hash).collect(),
+        });
+        if self.blocks.len() > self.depth {
+            self.blocks.pop_front();
+        }
+        Ok(())
+    }
+}
+
+// New blocks, with their transactions, in order and without gaps. Subscribes
+// on the best websocket endpoint and moves to another one when it drops,
+// stalls or falls behind; blocks missed in between are fetched over HTTP.
+//
+// Every block is linked to the last ones by its parent hash, so when the
+// chain reorganises the blocks of the new branch are handed out again along
+// with the ones they replaced.
+pub struct BlockFeed {
+    rpc: Failover,
+    // HTTP first, for fetching the blocks themselves
+    fetch: Provider<Failover>,
+    blocks: mpsc::Sender<NewBlock>,
+    chain: Mutex<Chain>,
+    stall: Duration,
+    max_backfill: u64,
+}
+
+impl BlockFeed {
+    pub fn new(
+        rpc: Failover,
+        blocks: mpsc::Sender<NewBlock>,
+        depth: usize,
+        stall: Duration,
+        max_backfill: u64,
+    ) -> Arc<Self> {
+        Arc::new(BlockFeed {
+            fetch: Provider::new(rpc.http_first()),
+            rpc,
+            blocks,
+            chain: Mutex::new(Chain::new(depth)),
+            stall,
+            max_backfill,
+        })
+    }
+
+    // Follow new blocks until the subscription fails or we shut down. Meant
+    // to be supervised, every restart resubscribes.
+    pub async fn run(self: Arc<Self>, mut shutdown: Shutdown) -> Result<(), TaskError> {
+        let (label, ws) = self.rpc.best_ws().await.ok_or(TaskError::Retry(
+            "no websocket endpoint available".to_string(),
+        ))?;
+        let provider = Provider::new(ws);
+        let mut stream = provider.subscribe_blocks().await?;
+        info!(endpoint = %label, "Subscribed to new blocks");
+
+        loop {
+            let header = tokio::select! {
+                header = stream.next() => header,
+                _ = tokio::time::sleep(self.stall) => {
+                    self.rpc.report_ws(&label).await;
+                    return Err(TaskError::Retry(format!("no block from {} in {:?}", label, self.stall)));
+                }
+                _ = shutdown.wait() => return Ok(()),
+            };
+            let header_at = Instant::now();
+            let (number, hash) = match header.and_then(|h| Some((h.number?.as_u64(), h.hash?))) {
+                Some(header) => header,
+                None => {
+                    self.rpc.report_ws(&label).await;
+                    return Err(TaskError::Retry(format!("subscription on {} ended", label)));
+                }
+            };
+
+            self.push(number, hash, header_at).await?;
+        }
+    }
+
+    async fn push(&self, number: u64, hash: H256, header_at: Instant) -> Result<(), TaskError> {
+        let mut chain = self.chain.lock().await;
+        // e.g. after resubscribing, or from a lagging endpoint
+        if chain.knows(number, hash) {
+            return Ok(());
+        }
+
+        // Walk back from the new block until it links up with the chain
+        // we've handed out, fetching missed blocks and the rest of a new
+        // branch on the way. Nothing changes until every block is in.
+        let mut branch = vec![self.fetch_block(hash).await?];
+        let orphaned = loop {
+            let oldest = branch.last().unwrap();
+            match chain.link(oldest, number, branch.len(), self.max_backfill)? {
+                Link::Joins(orphaned) => break orphaned,
+                Link::Parent => {
+                    let parent = oldest.parent_hash;
+                    branch.push(self.fetch_block(parent).await?);
+                }
+            }
+        };
+
+        if let Some(newest) = orphaned.first() {
+            METRICS.reorgs.inc();
+            warn!(
+                depth = orphaned.len(),
+                from = orphaned.last().map(|b| b.number),
+                to = newest.number,
+                new_blocks = branch.len(),
+                "Chain reorganised, replaying the new branch"
+            );
+        } else if branch.len() > 1 {
+            info!(
+                from = number + 1 - branch.len() as u64,
+                to = number,
+                "Backfilling missed blocks"
+            );
+        }
+
+        let mut orphaned = orphaned;
+        while let Some(block) = branch.pop() {
+            chain.push(&block)?;
+            self.blocks
+                .send(NewBlock {
+                    block,
+                    header_at,
+                    fetched_at: Instant::now(),
+                    orphaned: std::mem::take(&mut orphaned),
+                    flashblock: None,
+                })
+                .await
+                .map_err(|_| TaskError::Fatal("block consumer is gone".to_string()))?;
+        }
+        Ok(())
+    }
+
+    async fn fetch_block(&self, hash: H256) -> Result<Block<Transaction>, TaskError> {
+        // not on the node we asked yet, resubscribe and try again
+        self.fetch
+            .get_block_with_txs(hash)
+            .await?
+            .ok_or(TaskError::Retry("block not found".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // block `number` of `branch`, whose parent is `parent_branch`'s block before it
+    fn block(number: u64, branch: u8, parent_branch: u8) -> Block<Transaction> {
+        let hash = |number: u64, branch: u8| H256::from_low_u64_be(number << 8 | branch as u64);
+        Block {
+            number: Some(number.into()),
+            hash: Some(hash(number, branch)),
+            parent_hash: hash(number - 1, parent_branch),
+            ..Default::default()
+        }
+    }
+
+    // blocks `from..=to` of branch 0
+    fn chain(depth: usize, from: u64, to: u64) -> Chain {
+        let mut chain = Chain::new(depth);
+        for number in from..=to {
+            chain.push(&block(number, 0, 0)).unwrap();
+        }
+        chain
+    }
+
+    fn joins(link: Link) -> Vec<u64> {
+        match link {
+            Link::Joins(orphaned) => orphaned.iter().map(|b| b.number).collect(),
+            Link::Parent => panic!("expected the branch to join the chain"),
+        }
+    }
+
+    fn numbers(chain: &Chain) -> Vec<u64> {
+        chain.blocks.iter().map(|b| b.number).collect()
+    }
+
+    #[test]
+    fn next_block_joins_on_top() {
+        let mut chain = Chain::new(3);
+        let first = block(10, 0, 0);
+        assert!(joins(chain.link(&first, 10, 1, 10).unwrap()).is_empty());
+        chain.push(&first).unwrap();
+
+        let next = block(11, 0, 0);
+        assert!(joins(chain.link(&next, 11, 1, 10).unwrap()).is_empty());
+        for number in 11..=13 {
+            chain.push(&block(number, 0, 0)).unwrap();
+        }
+        // only the last `depth` are kept
+        assert_eq!(numbers(&chain), [11, 12, 13]);
+    }
+
+    #[test]
+    fn knows_seen_and_older_blocks() {
+        let chain = chain(3, 10, 12);
+        let seen = block(11, 0, 0);
+        assert!(chain.knows(11, seen.hash.unwrap()));
+        assert!(chain.knows(9, H256::repeat_byte(9)));
+        assert!(!chain.knows(11, H256::repeat_byte(9)));
+        assert!(!chain.knows(13, H256::repeat_byte(9)));
+    }
+
+    #[test]
+    fn reorg_orphans_the_replaced_blocks() {
+        let mut chain = chain(10, 1, 4);
+        // branch 1 forks off after block 2 and is now ahead
+        let tip = block(5, 1, 1);
+        assert!(matches!(chain.link(&tip, 5, 1, 10).unwrap(), Link::Parent));
+        let middle = block(4, 1, 1);
+        assert!(matches!(
+            chain.link(&middle, 5, 2, 10).unwrap(),
+            Link::Parent
+        ));
+        let oldest = block(3, 1, 0);
+        assert_eq!(joins(chain.link(&oldest, 5, 3, 10).unwrap()), [4, 3]);
+        assert_eq!(numbers(&chain), [1, 2]);
+    }
+
+    #[test]
+    fn reorg_deeper_than_tracked_orphans_everything() {
+        let mut chain = chain(3, 5, 7);
+        let oldest = block(5, 1, 1);
+        assert_eq!(joins(chain.link(&oldest, 7, 3, 10).unwrap()), [7, 6, 5]);
+        assert!(chain.blocks.is_empty());
+    }
+
+    #[test]
+    fn gaps_are_backfilled_up_to_the_limit() {
+        let mut chain = chain(10, 1, 2);
+        let missed = block(6, 0, 0);
+        assert!(matches!(
+            chain.link(&missed, 6, 1, 2).unwrap(),
+            Link::Parent
+        ));
+        let older = block(5, 0, 0);
+        assert!(matches!(chain.link(&older, 6, 2, 2).unwrap(), Link::Parent));
+        let oldest = block(4, 0, 0);
+        assert!(joins(chain.link(&oldest, 6, 3, 2).unwrap()).is_empty());
+        // nothing orphaned, the chain carries on from the fetched blocks
+        assert_eq!(numbers(&chain), [1, 2]);
+    }
+}
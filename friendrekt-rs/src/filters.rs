@@ -0,0 +1,225 @@
+use crate::{indexer::TradeIndex, prod_kosetto::TwitterInfo};
+use ethers::types::Address;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use tokio::sync::RwLock;
+use tracing::{error, info};
+
+// Contents of the blocklist file.
+#[derive(Default, Deserialize, Serialize)]
+#[serde(default)]
+pub struct BlocklistFile {
+    pub addresses: Vec<Address>,
+    pub handles: Vec<String>,
+    pub twitter_user_ids: Vec<String>,
+    pub handle_patterns: Vec<String>,
+}
+
+#[derive(Default)]
+struct Blocklist {
+    addresses: Vec<Address>,
+    handles: Vec<String>,
+    twitter_user_ids: Vec<String>,
+    handle_patterns: Vec<Regex>,
+    modified: Option<SystemTime>,
+}
+
+// Why a subject was rejected.
+pub enum Rejection {
+    BlockedAddress,
+    BlockedHandle(String),
+    BlockedTwitterId(String),
+    HandlePattern(String),
+    Dumper(usize),
+}
+
+impl fmt::Display for Rejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Rejection::BlockedAddress => write!(f, "address is blocklisted"),
+            Rejection::BlockedHandle(h) => write!(f, "handle {} is blocklisted", h),
+            Rejection::BlockedTwitterId(id) => write!(f, "twitter id {} is blocklisted", id),
+            Rejection::HandlePattern(p) => write!(f, "handle matches pattern {}", p),
+            Rejection::Dumper(n) => write!(f, "dumped their own shares {} times", n),
+        }
+    }
+}
+
+// Checks run on a subject right before we snipe it.
+pub struct Filters {
+    path: PathBuf,
+    blocklist: RwLock<Blocklist>,
+    trades: Arc<TradeIndex>,
+    max_dumps: usize,
+}
+
+impl Filters {
+    pub async fn load(path: PathBuf, trades: Arc<TradeIndex>, max_dumps: usize) -> Arc<Self> {
+        let filters = Arc::new(Filters {
+            path,
+            blocklist: RwLock::new(Blocklist::default()),
+            trades,
+            max_dumps,
+        });
+        filters.reload().await;
+        filters
+    }
+
+    // Poll the blocklist file for changes every `interval`.
+    pub fn watch(self: &Arc<Self>, interval: Duration) {
+        let filters = self.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                filters.reload().await;
+            }
+        });
+    }
+
+    pub fn read_blocklist(&self) -> Result<BlocklistFile, Box<dyn std::error::Error>> {
+        match std::fs::read_to_string(&self.path) {
+            Ok(data) => Ok(serde_json::from_str(&data)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(BlocklistFile::default()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // Add everything in `add` to the blocklist file and load it straight away.
+    pub async fn block(&self, add: BlocklistFile) -> Result<(), Box<dyn std::error::Error>> {
+        for pattern in &add.handle_patterns {
+            Regex::new(&format!("(?i){}", pattern))?;
+        }
+
+        let mut file = self.read_blocklist()?;
+        fn extend<T: PartialEq>(list: &mut Vec<T>, add: Vec<T>) {
+            for item in add {
+                if !list.contains(&item) {
+                    list.push(item);
+                }
+            }
+        }
+        extend(&mut file.addresses, add.addresses);
+        extend(&mut file.handles, add.handles);
+        extend(&mut file.twitter_user_ids, add.twitter_user_ids);
+        extend(&mut file.handle_patterns, add.handle_patterns);
+        self.write(&file).await
+    }
+
+    // Remove everything in `remove` from the blocklist file.
+    pub async fn unblock(&self, remove: BlocklistFile) -> Result<(), Box<dyn std::error::Error>> {
+        let mut file = self.read_blocklist()?;
+        file.addresses.retain(|a| !remove.addresses.contains(a));
+        file.handles.retain(|h| {
+            !remove.handles.iter().any(|r| {
+                r.trim_start_matches('@')
+                    .eq_ignore_ascii_case(h.trim_start_matches('@'))
+            })
+        });
+        file.twitter_user_ids
+            .retain(|id| !remove.twitter_user_ids.contains(id));
+        file.handle_patterns
+            .retain(|p| !remove.handle_patterns.contains(p));
+        self.write(&file).await
+    }
+
+    async fn write(&self, file: &BlocklistFile) -> Result<(), Box<dyn std::error::Error>> {
+        std::fs::write(&self.path, serde_json::to_string_pretty(file)?)?;
+        info!(path = ?self.path, "Updated blocklist");
+        // don't wait for the next poll
+        self.blocklist.write().await.modified = None;
+        self.reload().await;
+        Ok(())
+    }
+
+    async fn reload(&self) {
+        let modified = std::fs::metadata(&self.path)
+            .and_then(|m| m.modified())
+            .ok();
+        if modified == self.blocklist.read().await.modified {
+            return;
+        }
+
+        let file = match std::fs::read_to_string(&self.path) {
+            Ok(data) => match serde_json::from_str::<BlocklistFile>(&data) {
+                Ok(file) => file,
+                Err(e) => {
+                    error!(path = ?self.path, "Failed to parse blocklist: {}", e);
+                    return;
+                }
+            },
+            Err(_) => BlocklistFile::default(),
+        };
+
+        let mut handle_patterns = Vec::new();
+        for pattern in &file.handle_patterns {
+            match Regex::new(&format!("(?i){}", pattern)) {
+                Ok(re) => handle_patterns.push(re),
+                Err(e) => {
+                    error!(pattern, "Bad handle pattern: {}", e);
+                    return;
+                }
+            }
+        }
+
+        info!(
+            path = ?self.path,
+            addresses = file.addresses.len(),
+            handles = file.handles.len(),
+            twitter_user_ids = file.twitter_user_ids.len(),
+            handle_patterns = handle_patterns.len(),
+            "Loaded blocklist"
+        );
+        *self.blocklist.write().await = Blocklist {
+            addresses: file.addresses,
+            handles: file.handles,
+            twitter_user_ids: file.twitter_user_ids,
+            handle_patterns,
+            modified,
+        };
+    }
+
+    pub async fn check(&self, subject: Address, info: &TwitterInfo) -> Result<(), Rejection> {
+        {
+            let blocklist = self.blocklist.read().await;
+            if blocklist.addresses.contains(&subject) {
+                return Err(Rejection::BlockedAddress);
+            }
+
+            let handle = &info.twitter_username;
+            if blocklist
+                .handles
+                .iter()
+                .any(|h| h.trim_start_matches('@').eq_ignore_ascii_case(handle))
+            {
+                return Err(Rejection::BlockedHandle(handle.clone()));
+            }
+
+            if blocklist.twitter_user_ids.contains(&info.twitter_user_id) {
+                return Err(Rejection::BlockedTwitterId(info.twitter_user_id.clone()));
+            }
+
+            if let Some(re) = blocklist
+                .handle_patterns
+                .iter()
+                .find(|re| re.is_match(handle))
+            {
+                return Err(Rejection::HandlePattern(
+                    re.as_str().trim_start_matches("(?i)").to_string(),
+                ));
+            }
+        }
+
+        let dumps = self.trades.dumps(&subject).await;
+        if dumps > self.max_dumps {
+            return Err(Rejection::Dumper(dumps));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,508 @@
+use crate::{
+    config::FlashblocksConfig,
+    feed::NewBlock,
+    metrics::METRICS,
+    notify::Dependency,
+    supervisor::{Shutdown, TaskError},
+};
+use ethers::{prelude::*, utils::rlp};
+use futures::{SinkExt, StreamExt};
+use serde::Deserialize;
+use std::{
+    io::BufRead,
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::{fs::OpenOptions, io::AsyncWriteExt, net::TcpListener, sync::mpsc};
+use tokio_tungstenite::{connect_async, tungstenite::Message};
+use tracing::{debug, info, warn};
+
+// One flashblock: the txs the sequencer added to the block it's building
+// since the last one. The first of a block also carries its header fields.
+#[derive(Deserialize)]
+struct Flashblock {
+    payload_id: String,
+    index: u64,
+    base: Option<Base>,
+    diff: Diff,
+    metadata: Option<Metadata>,
+}
+
+#[derive(Deserialize)]
+struct Base {
+    parent_hash: H256,
+    block_number: U64,
+    timestamp: U256,
+}
+
+#[derive(Deserialize)]
+struct Diff {
+    // signed and EIP-2718 encoded
+    transactions: Vec<Bytes>,
+}
+
+#[derive(Deserialize)]
+struct Metadata {
+    block_number: u64,
+}
+
+// Sub-block preconfirmations from the sequencer, a flashblock every ~200ms
+// instead of a block every 2s. Their txs go through the same pipeline as the
+// block feed's, and as the same seen-set is checked first, whichever of the
+// two brings a tx first gets it handled.
+pub struct Flashblocks {
+    url: String,
+    stall: Duration,
+    record: Option<PathBuf>,
+    blocks: mpsc::Sender<NewBlock>,
+    health: Dependency,
+}
+
+impl Flashblocks {
+    // `url` is validated on load.
+    pub fn new(config: &FlashblocksConfig, blocks: mpsc::Sender<NewBlock>) -> Arc<Self> {
+        Arc::new(Flashblocks {
+            url: config.url.clone().unwrap_or_default(),
+            stall: Duration::from_secs(config.stall_secs),
+            record: config.record_path.clone(),
+            blocks,
+            health: Dependency::new("Flashblocks"),
+        })
+    }
+
+    // Follow the stream until it fails or we shut down. Meant to be
+    // supervised, every restart reconnects.
+    pub async fn run(self: Arc<Self>, mut shutdown: Shutdown) -> Result<(), TaskError> {
+        let (mut ws, _) = match connect_async(&self.url).await {
+            Ok(ws) => ws,
+            Err(e) => {
+                self.health.down(&e);
+                return Err(e.into());
+            }
+        };
+        self.health.up();
+        info!("Subscribed to flashblocks");
+
+        let mut record = match &self.record {
+            Some(path) => Some(
+                OpenOptions::new()
+                    .create(true)
+                    .append(true)
+                    .open(path)
+                    .await
+                    .map_err(|e| TaskError::Fatal(format!("failed to open {:?}: {}", path, e)))?,
+            ),
+            None => None,
+        };
+        let mut assembler = Assembler::default();
+        let mut unreadable = false;
+
+        loop {
+            let message = tokio::select! {
+                message = ws.next() => message,
+                _ = tokio::time::sleep(self.stall) => {
+                    let error = format!("no flashblock in {:?}", self.stall);
+                    self.health.down(&error);
+                    return Err(TaskError::Retry(error));
+                }
+                _ = shutdown.wait() => return Ok(()),
+            };
+            let received_at = Instant::now();
+            let payload = match message {
+                Some(Ok(Message::Text(text))) => text.into_bytes(),
+                Some(Ok(Message::Binary(data))) => data,
+                Some(Ok(Message::Close(_))) | None => {
+                    self.health.down("stream closed");
+                    return Err(TaskError::Retry("flashblocks stream closed".to_string()));
+                }
+                // pings are answered for us
+                Some(Ok(_)) => continue,
+                Some(Err(e)) => {
+                    self.health.down(&e);
+                    return Err(e.into());
+                }
+            };
+
+            let flashblock: Flashblock = match serde_json::from_slice(&payload) {
+                Ok(flashblock) => flashblock,
+                Err(e) => {
+                    // once is enough, it'll be the same for every payload
+                    if !unreadable {
+                        warn!(
+                            "Unreadable flashblock, only uncompressed JSON payloads are supported: {}",
+                            e
+                        );
+                        unreadable = true;
+                    }
+                    continue;
+                }
+            };
+            if let Some(record) = &mut record {
+                record.write_all(&payload).await?;
+                record.write_all(b"\n").await?;
+                // written out before it's acted on, like a block would be
+                record.flush().await?;
+            }
+
+            let index = flashblock.index;
+            let block = match assembler.block(flashblock) {
+                Some(block) => block,
+                // joined halfway through a block without metadata
+                None => continue,
+            };
+            METRICS.flashblocks.inc();
+            debug!(
+                number = block.number.map(|n| n.as_u64()),
+                index,
+                txs = block.transactions.len(),
+                "Flashblock"
+            );
+            if block.transactions.is_empty() {
+                continue;
+            }
+
+            self.blocks
+                .send(NewBlock {
+                    block,
+                    header_at: received_at,
+                    fetched_at: Instant::now(),
+                    orphaned: Vec::new(),
+                    flashblock: Some(index),
+                })
+                .await
+                .map_err(|_| TaskError::Fatal("block consumer is gone".to_string()))?;
+        }
+    }
+}
+
+// Turns flashblocks into blocks of the txs each one added.
+#[derive(Default)]
+struct Assembler {
+    // the header fields only come with a block's first flashblock
+    current: Option<(String, Base)>,
+}
+
+impl Assembler {
+    // None when the block it belongs to isn't known, e.g. after joining the
+    // stream halfway through one.
+    fn block(&mut self, flashblock: Flashblock) -> Option<Block<Transaction>> {
+        if let Some(base) = flashblock.base {
+            self.current = Some((flashblock.payload_id.clone(), base));
+        }
+        let base = self
+            .current
+            .as_ref()
+            .filter(|(payload_id, _)| *payload_id == flashblock.payload_id)
+            .map(|(_, base)| base);
+        let number = match (base, &flashblock.metadata) {
+            (Some(base), _) => base.block_number.as_u64(),
+            (None, Some(metadata)) => metadata.block_number,
+            (None, None) => return None,
+        };
+
+        Some(Block {
+            number: Some(number.into()),
+            parent_hash: base.map(|b| b.parent_hash).unwrap_or_default(),
+            timestamp: base.map(|b| b.timestamp).unwrap_or_default(),
+            transactions: flashblock
+                .diff
+                .transactions
+                .iter()
+                .filter_map(|raw| decode(raw, number))
+                .collect(),
+            ..Default::default()
+        })
+    }
+}
+
+// A signed tx as the block feed would give it to us, minus the block hash,
+// which doesn't exist yet. Deposits don't decode, they never come from users
+// buying shares.
+fn decode(raw: &Bytes, number: u64) -> Option<Transaction> {
+    let mut tx: Transaction = match rlp::decode(raw) {
+        Ok(tx) => tx,
+        Err(e) => {
+            debug!("Skipping flashblock tx: {}", e);
+            return None;
+        }
+    };
+    if let Err(e) = tx.recover_from_mut() {
+        debug!(hash = ?tx.hash, "Skipping flashblock tx without a valid signature: {}", e);
+        return None;
+    }
+    tx.block_number = Some(number.into());
+    Some(tx)
+}
+
+// Serve recorded flashblocks (one JSON payload per line, as written with
+// flashblocks.record_path) on a local websocket, `interval` apart, to every
+// client that connects. For trying the bot without the real stream.
+pub async fn replay(
+    path: &Path,
+    listen: SocketAddr,
+    interval: Duration,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let recorded = tokio::fs::read_to_string(path)
+        .await
+        .map_err(|e| format!("failed to read {:?}: {}", path, e))?;
+    let payloads = Arc::new(read_payloads(recorded.as_bytes())?);
+
+    let listener = TcpListener::bind(listen).await?;
+    println!(
+        "Replaying {} flashblocks on ws://{} every {:?}",
+        payloads.len(),
+        listen,
+        interval
+    );
+    Ok(serve(listener, payloads, interval).await?)
+}
+
+async fn serve(
+    listener: TcpListener,
+    payloads: Arc<Vec<String>>,
+    interval: Duration,
+) -> std::io::Result<()> {
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let payloads = payloads.clone();
+        tokio::spawn(async move {
+            let mut ws = match tokio_tungstenite::accept_async(stream).await {
+                Ok(ws) => ws,
+                Err(e) => {
+                    println!("{}: handshake failed: {}", peer, e);
+                    return;
+                }
+            };
+            println!("{}: connected", peer);
+            for payload in payloads.iter() {
+                tokio::time::sleep(interval).await;
+                if ws.send(Message::Text(payload.clone())).await.is_err() {
+                    println!("{}: disconnected", peer);
+                    return;
+                }
+            }
+            println!("{}: sent every flashblock", peer);
+            // keep the connection up until the client goes, like the real stream
+            while let Some(Ok(_)) = ws.next().await {}
+        });
+    }
+}
+
+// One payload per line, skipping blank ones.
+fn read_payloads(reader: impl BufRead) -> std::io::Result<Vec<String>> {
+    reader
+        .lines()
+        .filter(|line| !matches!(line, Ok(line) if line.trim().is_empty()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::supervisor::Supervisor;
+    use ethers::{
+        signers::{LocalWallet, Signer},
+        types::transaction::eip2718::TypedTransaction,
+    };
+    use serde_json::json;
+
+    // anvil's first account
+    const KEY: &str = "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80";
+
+    fn signed_tx(nonce: u64) -> (Bytes, LocalWallet) {
+        let wallet = KEY.parse::<LocalWallet>().unwrap().with_chain_id(8453u64);
+        let tx: TypedTransaction = Eip1559TransactionRequest::new()
+            .to(Address::repeat_byte(1))
+            .nonce(nonce)
+            .gas(100_000)
+            .max_fee_per_gas(2)
+            .max_priority_fee_per_gas(1)
+            .chain_id(8453)
+            .into();
+        let signature = wallet.sign_transaction_sync(&tx).unwrap();
+        (tx.rlp_signed(&signature), wallet)
+    }
+
+    fn flashblock(value: serde_json::Value) -> Flashblock {
+        serde_json::from_value(value).unwrap()
+    }
+
+    #[test]
+    fn first_flashblock_sets_the_header() {
+        let (raw, wallet) = signed_tx(0);
+        let mut assembler = Assembler::default();
+        let block = assembler
+            .block(flashblock(json!({
+                "payload_id": "0x01",
+                "index": 0,
+                "base": {
+                    "parent_hash": H256::repeat_byte(7),
+                    "block_number": "0x64",
+                    "timestamp": "0x10",
+                },
+                "diff": { "transactions": [raw] },
+            })))
+            .unwrap();
+
+        assert_eq!(block.number, Some(100.into()));
+        assert_eq!(block.parent_hash, H256::repeat_byte(7));
+        assert_eq!(block.timestamp, 16.into());
+        assert_eq!(block.transactions.len(), 1);
+        let tx = &block.transactions[0];
+        assert_eq!(tx.from, wallet.address());
+        assert_eq!(tx.block_number, Some(100.into()));
+        assert_eq!(tx.block_hash, None);
+    }
+
+    #[test]
+    fn later_flashblocks_keep_the_header() {
+        let (raw, _) = signed_tx(1);
+        let mut assembler = Assembler::default();
+        assembler.block(flashblock(json!({
+            "payload_id": "0x01",
+            "index": 0,
+            "base": {
+                "parent_hash": H256::repeat_byte(7),
+                "block_number": "0x64",
+                "timestamp": "0x10",
+            },
+            "diff": { "transactions": [] },
+        })));
+        let block = assembler
+            .block(flashblock(json!({
+                "payload_id": "0x01",
+                "index": 1,
+                "diff": { "transactions": [raw] },
+            })))
+            .unwrap();
+
+        assert_eq!(block.number, Some(100.into()));
+        assert_eq!(block.parent_hash, H256::repeat_byte(7));
+        assert_eq!(block.transactions.len(), 1);
+    }
+
+    #[test]
+    fn unknown_block_needs_metadata() {
+        let mut assembler = Assembler::default();
+        let joined = json!({
+            "payload_id": "0x02",
+            "index": 3,
+            "diff": { "transactions": [] },
+        });
+        assert!(assembler.block(flashblock(joined)).is_none());
+
+        let block = assembler
+            .block(flashblock(json!({
+                "payload_id": "0x02",
+                "index": 4,
+                "diff": { "transactions": [] },
+                "metadata": { "block_number": 101 },
+            })))
+            .unwrap();
+        assert_eq!(block.number, Some(101.into()));
+        assert_eq!(block.parent_hash, H256::zero());
+    }
+
+    #[test]
+    fn undecodable_txs_are_skipped() {
+        let (raw, _) = signed_tx(2);
+        let mut unsigned = raw.to_vec();
+        // r and s are the last 64 bytes, zero them
+        let len = unsigned.len();
+        unsigned[len - 64..].fill(0);
+
+        let mut assembler = Assembler::default();
+        let block = assembler
+            .block(flashblock(json!({
+                "payload_id": "0x03",
+                "index": 1,
+                "diff": { "transactions": ["0xdeadbeef", Bytes::from(unsigned), raw] },
+                "metadata": { "block_number": 102 },
+            })))
+            .unwrap();
+        assert_eq!(block.transactions.len(), 1);
+    }
+
+    #[test]
+    fn replay_skips_blank_lines() {
+        let recorded = "{\"index\":0}\n\n  \n{\"index\":1}\n";
+        let payloads = read_payloads(recorded.as_bytes()).unwrap();
+        assert_eq!(payloads, ["{\"index\":0}", "{\"index\":1}"]);
+    }
+
+    // What the sequencer would send for block 100: its first flashblock, one
+    // we can't read, then a second one with another tx.
+    fn recording() -> Vec<String> {
+        let (first, _) = signed_tx(0);
+        let (second, _) = signed_tx(1);
+        vec![
+            json!({
+                "payload_id": "0x01",
+                "index": 0,
+                "base": {
+                    "parent_hash": H256::repeat_byte(7),
+                    "block_number": "0x64",
+                    "timestamp": "0x10",
+                },
+                "diff": { "transactions": [first] },
+            })
+            .to_string(),
+            "not a flashblock".to_string(),
+            json!({
+                "payload_id": "0x01",
+                "index": 1,
+                "diff": { "transactions": [second] },
+            })
+            .to_string(),
+        ]
+    }
+
+    #[tokio::test]
+    async fn run_follows_a_replayed_stream_and_records_it() {
+        let payloads = recording();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        tokio::spawn(serve(
+            listener,
+            Arc::new(payloads.clone()),
+            Duration::from_millis(10),
+        ));
+
+        let record = std::env::temp_dir().join(format!(
+            "friendrekt-test-flashblocks-{}.jsonl",
+            std::process::id()
+        ));
+        let _ = std::fs::remove_file(&record);
+        let config = FlashblocksConfig {
+            url: Some(url),
+            stall_secs: 5,
+            record_path: Some(record.clone()),
+        };
+        let (blocks, mut block_rx) = mpsc::channel(8);
+        // dropping the supervisor would shut it down
+        let supervisor = Supervisor::new(0, Duration::from_secs(1));
+        let run = tokio::spawn(Flashblocks::new(&config, blocks).run(supervisor.shutdown()));
+
+        for (index, nonce) in [(0, 0), (1, 1)] {
+            let block = tokio::time::timeout(Duration::from_secs(5), block_rx.recv())
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(block.flashblock, Some(index));
+            assert_eq!(block.block.number, Some(100.into()));
+            assert_eq!(block.block.transactions.len(), 1);
+            assert_eq!(block.block.transactions[0].nonce, nonce.into());
+        }
+        run.abort();
+
+        // the unreadable payload isn't kept
+        let recorded = std::fs::read_to_string(&record).unwrap();
+        assert_eq!(
+            read_payloads(recorded.as_bytes()).unwrap(),
+            [payloads[0].clone(), payloads[2].clone()]
+        );
+        std::fs::remove_file(&record).unwrap();
+    }
+}
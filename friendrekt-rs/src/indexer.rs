@@ -0,0 +1,205 @@
+use crate::bindings::shares::shares::TradeFilter;
+use ethers::{contract::EthEvent, prelude::*};
+use std::collections::{HashMap, VecDeque};
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+
+// How many trades we keep per subject, and how many subjects we track.
+const TRADES_PER_SUBJECT: usize = 64;
+const MAX_SUBJECTS: usize = 50_000;
+// Blocks per eth_getLogs request when backfilling.
+const BACKFILL_CHUNK: u64 = 500;
+
+// A friend.tech Trade event along with where it was mined.
+#[derive(Clone, Debug)]
+pub struct Trade {
+    pub block_number: u64,
+    pub block_hash: H256,
+    pub tx_hash: H256,
+    pub log_index: U256,
+    pub trader: Address,
+    pub subject: Address,
+    pub is_buy: bool,
+    pub share_amount: U256,
+    pub eth_amount: U256,
+    pub protocol_eth_amount: U256,
+    pub subject_eth_amount: U256,
+}
+
+impl Trade {
+    // What the trader paid for a buy or got for a sell, fees included.
+    pub fn net_eth(&self) -> U256 {
+        let fees = self.protocol_eth_amount + self.subject_eth_amount;
+        if self.is_buy {
+            self.eth_amount + fees
+        } else {
+            self.eth_amount.saturating_sub(fees)
+        }
+    }
+
+    fn from_log(log: &Log) -> Option<Self> {
+        let event = <TradeFilter as EthEvent>::decode_log(&log.clone().into()).ok()?;
+        Some(Trade {
+            block_number: log.block_number?.as_u64(),
+            block_hash: log.block_hash?,
+            tx_hash: log.transaction_hash?,
+            log_index: log.log_index?,
+            trader: event.trader,
+            subject: event.subject,
+            is_buy: event.is_buy,
+            share_amount: event.share_amount,
+            eth_amount: event.eth_amount,
+            protocol_eth_amount: event.protocol_eth_amount,
+            subject_eth_amount: event.subject_eth_amount,
+        })
+    }
+}
+
+#[derive(Default)]
+struct Inner {
+    subjects: HashMap<Address, VecDeque<Trade>>,
+    // subjects in the order we first saw them, oldest evicted first
+    order: VecDeque<Address>,
+}
+
+// Recent friend.tech trades grouped by subject, fed from every new block.
+pub struct TradeIndex {
+    ft: Address,
+    inner: RwLock<Inner>,
+}
+
+impl TradeIndex {
+    pub fn new(ft: Address) -> Self {
+        TradeIndex {
+            ft,
+            inner: RwLock::new(Inner::default()),
+        }
+    }
+
+    fn filter(&self) -> Filter {
+        trade_filter(self.ft)
+    }
+
+    // Index the Trade events of a single block.
+    pub async fn index_block<M: Middleware>(&self, client: &M, block_hash: H256) {
+        match client
+            .get_logs(&self.filter().at_block_hash(block_hash))
+            .await
+        {
+            Ok(logs) => {
+                self.insert(logs.iter().filter_map(Trade::from_log).collect())
+                    .await
+            }
+            Err(e) => warn!(?block_hash, "Failed to get logs: {}", e),
+        }
+    }
+
+    // Index the Trade events of the last `blocks` blocks.
+    pub async fn backfill<M: Middleware>(&self, client: &M, blocks: u64) {
+        let head = match client.get_block_number().await {
+            Ok(head) => head.as_u64(),
+            Err(e) => {
+                warn!("Failed to get head for backfill: {}", e);
+                return;
+            }
+        };
+
+        let mut from = head.saturating_sub(blocks);
+        while from <= head {
+            let to = (from + BACKFILL_CHUNK - 1).min(head);
+            match fetch_trades(client, self.ft, from, to).await {
+                Ok(trades) => self.insert(trades).await,
+                Err(e) => warn!(from, to, "Failed to backfill: {}", e),
+            }
+            from = to + 1;
+        }
+
+        let subjects = self.inner.read().await.subjects.len();
+        info!(blocks, subjects, "Backfilled trades");
+    }
+
+    async fn insert(&self, trades: Vec<Trade>) {
+        let mut inner = self.inner.write().await;
+        for trade in trades {
+            let subject = trade.subject;
+            if !inner.subjects.contains_key(&subject) {
+                if inner.order.len() == MAX_SUBJECTS {
+                    if let Some(evicted) = inner.order.pop_front() {
+                        inner.subjects.remove(&evicted);
+                    }
+                }
+                inner.order.push_back(subject);
+            }
+
+            let trades = inner.subjects.entry(subject).or_default();
+            // backfill and the live feed can overlap
+            if trades
+                .iter()
+                .any(|t| t.tx_hash == trade.tx_hash && t.log_index == trade.log_index)
+            {
+                continue;
+            }
+            // and deliver blocks out of order, keep the history sorted
+            let key = (trade.block_number, trade.log_index);
+            let at = trades
+                .iter()
+                .rposition(|t| (t.block_number, t.log_index) < key)
+                .map(|i| i + 1)
+                .unwrap_or(0);
+            trades.insert(at, trade);
+            if trades.len() > TRADES_PER_SUBJECT {
+                trades.pop_front();
+            }
+        }
+    }
+
+    // Forget the trades in blocks a reorg took out. Returns how many.
+    pub async fn retract(&self, blocks: &[H256]) -> usize {
+        let mut inner = self.inner.write().await;
+        let mut retracted = 0;
+        for trades in inner.subjects.values_mut() {
+            let before = trades.len();
+            trades.retain(|t| !blocks.contains(&t.block_hash));
+            retracted += before - trades.len();
+        }
+        retracted
+    }
+
+    // Number of times the subject sold their own shares after someone else
+    // had bought in, i.e. dumped on their buyers.
+    pub async fn dumps(&self, subject: &Address) -> usize {
+        let inner = self.inner.read().await;
+        let trades = match inner.subjects.get(subject) {
+            Some(trades) => trades,
+            None => return 0,
+        };
+
+        let mut others_bought = false;
+        let mut dumps = 0;
+        for trade in trades {
+            if trade.trader != *subject && trade.is_buy {
+                others_bought = true;
+            } else if trade.trader == *subject && !trade.is_buy && others_bought {
+                dumps += 1;
+            }
+        }
+
+        dumps
+    }
+}
+
+fn trade_filter(ft: Address) -> Filter {
+    Filter::new().address(ft).topic0(TradeFilter::signature())
+}
+
+// All Trade events of the friend.tech contract between two blocks, inclusive.
+pub async fn fetch_trades<M: Middleware>(
+    client: &M,
+    ft: Address,
+    from: u64,
+    to: u64,
+) -> Result<Vec<Trade>, M::Error> {
+    let filter = trade_filter(ft).from_block(from).to_block(to);
+    let logs = client.get_logs(&filter).await?;
+    Ok(logs.iter().filter_map(Trade::from_log).collect())
+}
@@ -0,0 +1,536 @@
+use crate::{config::SignerConfig, notify::Dependency};
+use async_trait::async_trait;
+use ethers::{
+    prelude::*,
+    types::transaction::{eip2718::TypedTransaction, eip712::Eip712},
+    utils::{hex, rlp},
+};
+use serde_json::{json, Value};
+use std::{
+    error::Error,
+    fmt,
+    io::{self, BufRead, IsTerminal},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::Arc,
+    time::Duration,
+};
+use tracing::info;
+
+// A wallet we sign with: a key held in memory, decrypted from a keystore or
+// given in plain text, or an account of a remote signer.
+#[derive(Clone, Debug)]
+pub enum Key {
+    Local(LocalWallet),
+    Remote(RemoteSigner),
+}
+
+#[derive(Debug)]
+pub enum KeyError {
+    Wallet(WalletError),
+    Remote(String),
+}
+
+impl fmt::Display for KeyError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            KeyError::Wallet(e) => write!(f, "{}", e),
+            KeyError::Remote(e) => write!(f, "remote signer: {}", e),
+        }
+    }
+}
+
+impl Error for KeyError {}
+
+#[async_trait]
+impl Signer for Key {
+    type Error = KeyError;
+
+    async fn sign_message<S: Send + Sync + AsRef<[u8]>>(
+        &self,
+        message: S,
+    ) -> Result<Signature, KeyError> {
+        match self {
+            Key::Local(wallet) => wallet.sign_message(message).await.map_err(KeyError::Wallet),
+            Key::Remote(remote) => remote.sign_message(message.as_ref()).await,
+        }
+    }
+
+    async fn sign_transaction(&self, tx: &TypedTransaction) -> Result<Signature, KeyError> {
+        match self {
+            Key::Local(wallet) => wallet.sign_transaction(tx).await.map_err(KeyError::Wallet),
+            Key::Remote(remote) => remote.sign_transaction(tx).await,
+        }
+    }
+
+    async fn sign_typed_data<T: Eip712 + Send + Sync>(
+        &self,
+        payload: &T,
+    ) -> Result<Signature, KeyError> {
+        match self {
+            Key::Local(wallet) => wallet
+                .sign_typed_data(payload)
+                .await
+                .map_err(KeyError::Wallet),
+            Key::Remote(_) => Err(KeyError::Remote(
+                "signing typed data isn't supported".to_string(),
+            )),
+        }
+    }
+
+    fn address(&self) -> Address {
+        match self {
+            Key::Local(wallet) => wallet.address(),
+            Key::Remote(remote) => remote.address,
+        }
+    }
+
+    fn chain_id(&self) -> u64 {
+        match self {
+            Key::Local(wallet) => wallet.chain_id(),
+            Key::Remote(remote) => remote.chain_id,
+        }
+    }
+
+    fn with_chain_id<T: Into<u64>>(self, chain_id: T) -> Self {
+        match self {
+            Key::Local(wallet) => Key::Local(wallet.with_chain_id(chain_id)),
+            Key::Remote(remote) => Key::Remote(RemoteSigner {
+                chain_id: chain_id.into(),
+                ..remote
+            }),
+        }
+    }
+}
+
+// An account held by a signing daemon, e.g. clef or a KMS/HSM bridge,
+// reached over JSON-RPC. It has to answer:
+//
+// - eth_accounts: the addresses it can sign for
+// - eth_signTransaction [tx]: the signed tx, raw as a hex string or geth style
+//   as {"raw": ...}
+// - eth_sign [address, data]: a 65 byte signature of the prefixed message,
+//   optional, nothing we send needs it
+//
+// Signatures are checked against the address and what we asked it to sign,
+// so a misbehaving signer can't make us broadcast something else.
+#[derive(Clone, Debug)]
+pub struct RemoteSigner {
+    client: reqwest::Client,
+    url: String,
+    address: Address,
+    chain_id: u64,
+    health: Arc<Dependency>,
+}
+
+impl RemoteSigner {
+    // Fails unless the signer holds `address`.
+    pub async fn connect(
+        url: &str,
+        address: Address,
+        timeout: Duration,
+    ) -> Result<Self, Box<dyn Error>> {
+        let remote = RemoteSigner {
+            client: reqwest::Client::builder().timeout(timeout).build()?,
+            url: url.to_string(),
+            address,
+            chain_id: 1,
+            health: Arc::new(Dependency::new(format!("Remote signer {:?}", address))),
+        };
+        let accounts = remote
+            .call("eth_accounts", json!([]))
+            .await
+            .map_err(|e| e.to_string())?;
+        let accounts: Vec<Address> = serde_json::from_value(accounts)
+            .map_err(|e| format!("unexpected eth_accounts reply from {}: {}", url, e))?;
+        if !accounts.contains(&address) {
+            return Err(format!("the remote signer at {} doesn't hold {:?}", url, address).into());
+        }
+        Ok(remote)
+    }
+
+    async fn call(&self, method: &str, params: Value) -> Result<Value, KeyError> {
+        let request = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": method,
+            "params": params,
+        });
+        let reply = match self.client.post(&self.url).json(&request).send().await {
+            Ok(reply) => reply.error_for_status(),
+            Err(e) => Err(e),
+        };
+        let mut reply: Value = match reply {
+            Ok(reply) => reply
+                .json()
+                .await
+                .map_err(|e| KeyError::Remote(e.to_string()))?,
+            Err(e) => {
+                self.health.down(&e);
+                return Err(KeyError::Remote(e.to_string()));
+            }
+        };
+        self.health.up();
+        if let Some(error) = reply.get("error") {
+            return Err(KeyError::Remote(format!("{} failed: {}", method, error)));
+        }
+        Ok(reply["result"].take())
+    }
+
+    async fn sign_transaction(&self, tx: &TypedTransaction) -> Result<Signature, KeyError> {
+        let mut tx = tx.clone();
+        let chain_id = tx.chain_id().map_or(self.chain_id, |id| id.as_u64());
+        tx.set_chain_id(chain_id);
+        tx.set_from(self.address);
+
+        // ethers leaves the chain id out of tx requests, the signer needs it
+        let mut request = serde_json::to_value(&tx).map_err(|e| KeyError::Remote(e.to_string()))?;
+        request["chainId"] = json!(U64::from(chain_id));
+        let result = self.call("eth_signTransaction", json!([request])).await?;
+        let raw = result
+            .as_str()
+            .or_else(|| result["raw"].as_str())
+            .ok_or_else(|| {
+                KeyError::Remote(format!("unexpected eth_signTransaction reply: {}", result))
+            })?;
+        let raw = hex::decode(raw).map_err(|e| KeyError::Remote(e.to_string()))?;
+        let (signed, signature) = TypedTransaction::decode_signed(&rlp::Rlp::new(&raw))
+            .map_err(|e| KeyError::Remote(format!("unreadable signed tx: {}", e)))?;
+
+        let sighash = tx.sighash();
+        if signed.sighash() != sighash {
+            return Err(KeyError::Remote(
+                "signed a different tx than the one asked for".to_string(),
+            ));
+        }
+        self.check(&signature, sighash)?;
+        Ok(signature)
+    }
+
+    async fn sign_message(&self, message: &[u8]) -> Result<Signature, KeyError> {
+        let result = self
+            .call(
+                "eth_sign",
+                json!([self.address, format!("0x{}", hex::encode(message))]),
+            )
+            .await?;
+        let signature = result
+            .as_str()
+            .and_then(|s| s.parse::<Signature>().ok())
+            .ok_or_else(|| KeyError::Remote(format!("unexpected eth_sign reply: {}", result)))?;
+        self.check(&signature, ethers::utils::hash_message(message))?;
+        Ok(signature)
+    }
+
+    fn check(&self, signature: &Signature, hash: H256) -> Result<(), KeyError> {
+        match signature.recover(hash) {
+            Ok(address) if address == self.address => Ok(()),
+            Ok(address) => Err(KeyError::Remote(format!(
+                "signed as {:?} instead of {:?}",
+                address, self.address
+            ))),
+            Err(e) => Err(KeyError::Remote(format!("invalid signature: {}", e))),
+        }
+    }
+}
+
+// Decrypts keystores, asking for each passphrase at most once. Shared by the
+// operator and hot wallet loads, which often use the same passphrase.
+pub struct Keystores {
+    password_file: Option<PathBuf>,
+    passphrases: Vec<String>,
+}
+
+impl Keystores {
+    pub fn new(config: &SignerConfig) -> Self {
+        Keystores {
+            password_file: config.password_file.clone(),
+            passphrases: Vec::new(),
+        }
+    }
+
+    fn decrypt(&mut self, path: &Path) -> Result<LocalWallet, Box<dyn Error>> {
+        if let Some(file) = &self.password_file {
+            let passphrase = std::fs::read_to_string(file)
+                .map_err(|e| format!("failed to read {:?}: {}", file, e))?;
+            let passphrase = passphrase.trim_end_matches(['\r', '\n']);
+            return LocalWallet::decrypt_keystore(path, passphrase)
+                .map_err(|e| format!("failed to decrypt {:?}: {}", path, e).into());
+        }
+
+        // keystores often share a passphrase
+        for passphrase in &self.passphrases {
+            if let Ok(wallet) = LocalWallet::decrypt_keystore(path, passphrase) {
+                return Ok(wallet);
+            }
+        }
+        let passphrase = prompt(path)?;
+        let wallet = LocalWallet::decrypt_keystore(path, &passphrase)
+            .map_err(|e| format!("failed to decrypt {:?}: {}", path, e))?;
+        self.passphrases.push(passphrase);
+        Ok(wallet)
+    }
+}
+
+// Read a passphrase from the terminal without echoing it.
+fn prompt(path: &Path) -> Result<String, Box<dyn Error>> {
+    if !io::stdin().is_terminal() {
+        return Err(format!(
+            "no terminal to ask for the passphrase of {:?}, set signer.password_file",
+            path
+        )
+        .into());
+    }
+
+    eprint!("Passphrase for {}: ", path.display());
+    let stty = |arg| {
+        Command::new("stty")
+            .arg(arg)
+            .stdin(Stdio::inherit())
+            .status()
+    };
+    let _ = stty("-echo");
+    let mut line = String::new();
+    let read = io::stdin().lock().read_line(&mut line);
+    let _ = stty("echo");
+    eprintln!();
+    read?;
+    Ok(line.trim_end_matches(['\r', '\n']).to_string())
+}
+
+async fn remote(config: &SignerConfig, address: Address) -> Result<Key, Box<dyn Error>> {
+    // validated on load
+    let url = config.remote_url.as_deref().unwrap();
+    let timeout = Duration::from_secs(config.remote_timeout_secs);
+    Ok(Key::Remote(
+        RemoteSigner::connect(url, address, timeout).await?,
+    ))
+}
+
+// The operator wallet, which owns the Sniper and refills the hot wallets.
+pub async fn operator(
+    config: &SignerConfig,
+    keystores: &mut Keystores,
+) -> Result<Key, Box<dyn Error>> {
+    if let Some(address) = config.remote {
+        return remote(config, address).await;
+    }
+    if let Some(path) = &config.keystore {
+        return Ok(Key::Local(keystores.decrypt(path)?));
+    }
+    // validated on load
+    Ok(Key::Local(config.private_key.as_deref().unwrap().parse()?))
+}
+
+// The hot wallets snipes are sent from, or the operator if there are none.
+pub async fn hot(
+    config: &SignerConfig,
+    operator: &Key,
+    keystores: &mut Keystores,
+) -> Result<Vec<Key>, Box<dyn Error>> {
+    let mut keys = Vec::new();
+    for key in &config.hot_keys {
+        keys.push(Key::Local(key.parse()?));
+    }
+    for path in &config.hot_keystores {
+        keys.push(Key::Local(keystores.decrypt(path)?));
+    }
+    for address in &config.hot_remote {
+        keys.push(remote(config, *address).await?);
+    }
+    if keys.is_empty() {
+        return Ok(vec![operator.clone()]);
+    }
+
+    let mut seen = Vec::new();
+    for key in &keys {
+        let address = key.address();
+        if address == operator.address() {
+            return Err(format!(
+                "hot wallet {:?} is the operator wallet, which refills them",
+                address
+            )
+            .into());
+        }
+        if seen.contains(&address) {
+            return Err(format!("hot wallet {:?} is configured twice", address).into());
+        }
+        seen.push(address);
+    }
+    info!(
+        keys = config.hot_keys.len(),
+        keystores = config.hot_keystores.len(),
+        remote = config.hot_remote.len(),
+        "Loaded hot wallets"
+    );
+    Ok(keys)
+}
+
+// Keystore paths are checked on load, before any passphrase is asked for.
+pub fn missing_keystore(config: &SignerConfig) -> Option<&PathBuf> {
+    config
+        .keystore
+        .iter()
+        .chain(&config.hot_keystores)
+        .find(|path| !path.is_file())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::core::rand::thread_rng;
+    use hyper::{
+        service::{make_service_fn, service_fn},
+        Body, Response, Server,
+    };
+    use std::{convert::Infallible, net::SocketAddr};
+
+    type Reply = Arc<dyn Fn(TypedTransaction) -> Value + Send + Sync>;
+
+    // A signing daemon holding `wallet`, answering eth_signTransaction with
+    // whatever `reply` makes of the tx it was asked to sign.
+    async fn daemon(wallet: &LocalWallet, reply: Reply) -> Result<RemoteSigner, Box<dyn Error>> {
+        let accounts = json!([wallet.address()]);
+        let make = make_service_fn(move |_| {
+            let accounts = accounts.clone();
+            let reply = reply.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |req: hyper::Request<Body>| {
+                    let accounts = accounts.clone();
+                    let reply = reply.clone();
+                    async move {
+                        let body = hyper::body::to_bytes(req.into_body()).await.unwrap();
+                        let request: Value = serde_json::from_slice(&body).unwrap();
+                        let result = match request["method"].as_str() {
+                            Some("eth_accounts") => accounts,
+                            Some("eth_signTransaction") => {
+                                reply(serde_json::from_value(request["params"][0].clone()).unwrap())
+                            }
+                            _ => Value::Null,
+                        };
+                        let reply = json!({"jsonrpc": "2.0", "id": 1, "result": result});
+                        Ok::<_, Infallible>(Response::new(Body::from(reply.to_string())))
+                    }
+                }))
+            }
+        });
+        let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(make);
+        let url = format!("http://{}", server.local_addr());
+        tokio::spawn(server);
+        RemoteSigner::connect(&url, wallet.address(), Duration::from_secs(5)).await
+    }
+
+    fn raw(wallet: &LocalWallet, tx: &TypedTransaction) -> String {
+        let signature = wallet.sign_transaction_sync(tx).unwrap();
+        format!("0x{}", hex::encode(tx.rlp_signed(&signature)))
+    }
+
+    fn tx() -> TypedTransaction {
+        Eip1559TransactionRequest::new()
+            .to(Address::repeat_byte(2))
+            .nonce(7)
+            .gas(100_000)
+            .max_fee_per_gas(10)
+            .max_priority_fee_per_gas(1)
+            .chain_id(8453)
+            .into()
+    }
+
+    async fn sign(
+        reply: impl Fn(&LocalWallet, TypedTransaction) -> Value + Send + Sync + 'static,
+    ) -> Result<Signature, KeyError> {
+        let wallet = LocalWallet::new(&mut thread_rng());
+        let signer = wallet.clone();
+        let remote = daemon(&wallet, Arc::new(move |tx| reply(&signer, tx)))
+            .await
+            .unwrap();
+        let signature = remote.sign_transaction(&tx()).await?;
+        assert_eq!(signature.recover(tx().sighash()).unwrap(), wallet.address());
+        Ok(signature)
+    }
+
+    #[tokio::test]
+    async fn takes_raw_txs_as_strings_or_geth_style() {
+        sign(|wallet, tx| json!(raw(wallet, &tx))).await.unwrap();
+        sign(|wallet, tx| json!({ "raw": raw(wallet, &tx) }))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn rejects_signatures_from_another_signer() {
+        let error = sign(|_, tx| json!(raw(&LocalWallet::new(&mut thread_rng()), &tx)))
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("signed as"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn rejects_signatures_over_another_tx() {
+        let error = sign(|wallet, mut tx| {
+            tx.set_nonce(8);
+            json!(raw(wallet, &tx))
+        })
+        .await
+        .unwrap_err();
+        assert!(error.to_string().contains("different tx"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn rejects_malformed_replies() {
+        for reply in [
+            json!(42),
+            json!({ "tx": "0x00" }),
+            json!("0xzz"),
+            json!("0x1234"),
+        ] {
+            let error = sign(move |_, _| reply.clone()).await.unwrap_err();
+            assert!(matches!(error, KeyError::Remote(_)), "{}", error);
+        }
+    }
+
+    #[tokio::test]
+    async fn connect_needs_the_signer_to_hold_the_address() {
+        let wallet = LocalWallet::new(&mut thread_rng());
+        let url = daemon(&wallet, Arc::new(|_| Value::Null))
+            .await
+            .unwrap()
+            .url;
+        let other = Address::repeat_byte(3);
+        let error = RemoteSigner::connect(&url, other, Duration::from_secs(5))
+            .await
+            .unwrap_err();
+        assert!(error.to_string().contains("doesn't hold"), "{}", error);
+    }
+
+    #[test]
+    fn keystores_sharing_a_passphrase_are_decrypted_with_it() {
+        let dir = std::env::temp_dir().join(format!("friendrekt-keystores-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (first, _) =
+            LocalWallet::new_keystore(&dir, &mut thread_rng(), "hunter2", Some("first")).unwrap();
+        let (second, _) =
+            LocalWallet::new_keystore(&dir, &mut thread_rng(), "hunter2", Some("second")).unwrap();
+
+        // as if asked for on the first keystore, a prompt in a test fails
+        let mut keystores = Keystores {
+            password_file: None,
+            passphrases: vec!["wrong".to_string(), "hunter2".to_string()],
+        };
+        let decrypted = keystores.decrypt(&dir.join("first")).unwrap();
+        assert_eq!(decrypted.address(), first.address());
+        let decrypted = keystores.decrypt(&dir.join("second")).unwrap();
+        assert_eq!(decrypted.address(), second.address());
+
+        // a password file wins, trailing newline and all
+        let file = dir.join("password");
+        std::fs::write(&file, "hunter2\n").unwrap();
+        let mut keystores = Keystores {
+            password_file: Some(file),
+            passphrases: Vec::new(),
+        };
+        let decrypted = keystores.decrypt(&dir.join("second")).unwrap();
+        assert_eq!(decrypted.address(), second.address());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,182 @@
+use crate::metrics::METRICS;
+use std::{
+    collections::VecDeque,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::Mutex;
+use tracing::info;
+
+// Steps between a block header arriving and our snipe being broadcast, in
+// the order they happen.
+#[derive(Clone, Copy)]
+pub enum Stage {
+    // eth_getBlockByHash for the header we were notified of
+    Fetch,
+    // until the tx task has worked out what the tx is
+    Classify,
+    // cache, watchlist or Kosetto and follower lookup
+    Lookup,
+    // strategy, filters and risk limits
+    Decide,
+    Sign,
+    // eth_sendRawTransaction to the sequencer
+    Send,
+}
+
+const STAGES: [Stage; 6] = [
+    Stage::Fetch,
+    Stage::Classify,
+    Stage::Lookup,
+    Stage::Decide,
+    Stage::Sign,
+    Stage::Send,
+];
+
+impl Stage {
+    pub fn name(self) -> &'static str {
+        match self {
+            Stage::Fetch => "fetch",
+            Stage::Classify => "classify",
+            Stage::Lookup => "lookup",
+            Stage::Decide => "decide",
+            Stage::Sign => "sign",
+            Stage::Send => "send",
+        }
+    }
+}
+
+// How long each stage took for one tx, measured from the header arriving.
+#[derive(Clone, Copy)]
+pub struct Timeline {
+    start: Instant,
+    last: Instant,
+    stages: [Option<Duration>; STAGES.len()],
+}
+
+impl Timeline {
+    pub fn new(start: Instant) -> Self {
+        Timeline {
+            start,
+            last: start,
+            stages: [None; STAGES.len()],
+        }
+    }
+
+    // The stage finished now.
+    pub fn mark(&mut self, stage: Stage) {
+        self.mark_at(stage, Instant::now());
+    }
+
+    pub fn mark_at(&mut self, stage: Stage, at: Instant) {
+        self.stages[stage as usize] = Some(at.saturating_duration_since(self.last));
+        self.last = at;
+    }
+
+    pub fn ms(&self, stage: Stage) -> u64 {
+        self.stages[stage as usize]
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or_default()
+    }
+
+    // Since the header arrived.
+    pub fn elapsed_ms(&self) -> u64 {
+        self.start.elapsed().as_millis() as u64
+    }
+}
+
+struct Samples {
+    // per stage, then the total, most recent last
+    stages: Vec<VecDeque<Duration>>,
+    // snipes recorded since the last report
+    fresh: usize,
+}
+
+// Keeps the timelines of the last `window` snipes and logs percentiles of
+// each stage every so often.
+pub struct Latency {
+    samples: Mutex<Samples>,
+    window: usize,
+}
+
+impl Latency {
+    pub fn new(window: usize) -> Arc<Self> {
+        Arc::new(Latency {
+            samples: Mutex::new(Samples {
+                stages: vec![VecDeque::with_capacity(window); STAGES.len() + 1],
+                fresh: 0,
+            }),
+            window,
+        })
+    }
+
+    pub async fn record(&self, timeline: &Timeline) {
+        let total = timeline.last.saturating_duration_since(timeline.start);
+        METRICS
+            .snipe_latency
+            .with_label_values(&["total"])
+            .observe(total.as_secs_f64());
+
+        let mut samples = self.samples.lock().await;
+        let window = self.window;
+        let mut push = |i: usize, d: Duration| {
+            let stage = &mut samples.stages[i];
+            if stage.len() == window {
+                stage.pop_front();
+            }
+            stage.push_back(d);
+        };
+
+        for (i, stage) in STAGES.iter().enumerate() {
+            if let Some(d) = timeline.stages[i] {
+                METRICS
+                    .snipe_latency
+                    .with_label_values(&[stage.name()])
+                    .observe(d.as_secs_f64());
+                push(i, d);
+            }
+        }
+        push(STAGES.len(), total);
+        samples.fresh += 1;
+    }
+
+    // Log percentiles every `interval`, if anything was sniped since.
+    pub fn report(self: &Arc<Self>, interval: Duration) {
+        let latency = self.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                latency.log().await;
+            }
+        });
+    }
+
+    async fn log(&self) {
+        let mut samples = self.samples.lock().await;
+        if samples.fresh == 0 {
+            return;
+        }
+        let fresh = samples.fresh;
+        samples.fresh = 0;
+
+        let names = STAGES.iter().map(|s| s.name()).chain(["total"]);
+        for (name, stage) in names.zip(&samples.stages) {
+            if stage.is_empty() {
+                continue;
+            }
+            let mut sorted = stage.iter().copied().collect::<Vec<_>>();
+            sorted.sort();
+            let ms = |p: usize| sorted[(sorted.len() - 1) * p / 100].as_millis() as u64;
+            info!(
+                stage = name,
+                samples = sorted.len(),
+                fresh,
+                p50_ms = ms(50),
+                p90_ms = ms(90),
+                p99_ms = ms(99),
+                max_ms = ms(100),
+                "Snipe latency"
+            );
+        }
+    }
+}
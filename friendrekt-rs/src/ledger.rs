@@ -0,0 +1,400 @@
+use crate::{bindings::shares::shares::shares, cli::Client, indexer::Trade, math};
+use ethers::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashSet, VecDeque},
+    fs::File,
+    io::Write,
+    path::Path,
+};
+
+// Which lots a sell is matched against.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum LotMethod {
+    #[default]
+    Fifo,
+    Lifo,
+}
+
+// Shares acquired by a single buy, what's left of them and what they cost.
+pub struct Lot {
+    pub subject: Address,
+    pub block_number: u64,
+    pub tx_hash: H256,
+    pub shares: U256,
+    // price, protocol and subject fees and gas, for the shares still in the lot
+    pub cost: U256,
+}
+
+// Shares sold out of one lot.
+pub struct Disposal {
+    pub subject: Address,
+    pub shares: U256,
+    pub acquired_block: u64,
+    pub sold_block: u64,
+    pub tx_hash: H256,
+    pub cost: U256,
+    // what the sell paid after fees, less its gas
+    pub proceeds: U256,
+}
+
+impl Disposal {
+    pub fn pnl(&self) -> f64 {
+        math::wei_to_eth(self.proceeds) - math::wei_to_eth(self.cost)
+    }
+}
+
+pub struct Ledger {
+    pub method: LotMethod,
+    pub lots: BTreeMap<Address, VecDeque<Lot>>,
+    pub disposals: Vec<Disposal>,
+    // sum of buys paid and sells received, fees included, gas excluded
+    pub bought: U256,
+    pub sold: U256,
+    pub gas: U256,
+    // problems found while building or reconciling the ledger
+    pub issues: Vec<String>,
+}
+
+impl Ledger {
+    // Build the ledger from the Sniper's trades. `gas` is what each
+    // transaction cost, it's spread over the fills in it.
+    pub fn build(method: LotMethod, trades: &[Trade], gas: &BTreeMap<H256, U256>) -> Self {
+        let mut ledger = Ledger {
+            method,
+            lots: BTreeMap::new(),
+            disposals: Vec::new(),
+            bought: U256::zero(),
+            sold: U256::zero(),
+            gas: U256::zero(),
+            issues: Vec::new(),
+        };
+
+        let mut trades = trades.to_vec();
+        trades.sort_by_key(|t| (t.block_number, t.log_index));
+        let mut fills = BTreeMap::<H256, usize>::new();
+        for trade in &trades {
+            *fills.entry(trade.tx_hash).or_default() += 1;
+        }
+
+        for trade in &trades {
+            let gas = gas
+                .get(&trade.tx_hash)
+                .map(|g| *g / U256::from(fills[&trade.tx_hash]))
+                .unwrap_or_default();
+            ledger.gas += gas;
+            if trade.is_buy {
+                ledger.buy(trade, gas);
+            } else {
+                ledger.sell(trade, gas);
+            }
+        }
+
+        ledger
+    }
+
+    fn buy(&mut self, trade: &Trade, gas: U256) {
+        if trade.share_amount.is_zero() {
+            return;
+        }
+
+        self.bought += trade.net_eth();
+        self.lots.entry(trade.subject).or_default().push_back(Lot {
+            subject: trade.subject,
+            block_number: trade.block_number,
+            tx_hash: trade.tx_hash,
+            shares: trade.share_amount,
+            cost: trade.net_eth() + gas,
+        });
+    }
+
+    fn sell(&mut self, trade: &Trade, gas: U256) {
+        self.sold += trade.net_eth();
+        let proceeds = trade.net_eth().saturating_sub(gas);
+        let lots = self.lots.entry(trade.subject).or_default();
+
+        let mut remaining = trade.share_amount;
+        while !remaining.is_zero() {
+            let lot = match self.method {
+                LotMethod::Fifo => lots.front_mut(),
+                LotMethod::Lifo => lots.back_mut(),
+            };
+            let lot = match lot {
+                Some(lot) => lot,
+                None => break,
+            };
+
+            let shares = remaining.min(lot.shares);
+            let cost = lot.cost * shares / lot.shares;
+            self.disposals.push(Disposal {
+                subject: trade.subject,
+                shares,
+                acquired_block: lot.block_number,
+                sold_block: trade.block_number,
+                tx_hash: trade.tx_hash,
+                cost,
+                proceeds: proceeds * shares / trade.share_amount,
+            });
+
+            lot.shares -= shares;
+            lot.cost -= cost;
+            if lot.shares.is_zero() {
+                match self.method {
+                    LotMethod::Fifo => lots.pop_front(),
+                    LotMethod::Lifo => lots.pop_back(),
+                };
+            }
+            remaining -= shares;
+        }
+
+        if !remaining.is_zero() {
+            self.issues.push(format!(
+                "{:?} sold {} shares of {:?} with no lot to match, is start_block too late?",
+                trade.tx_hash, remaining, trade.subject
+            ));
+        }
+    }
+
+    pub fn held(&self, subject: &Address) -> U256 {
+        self.lots
+            .get(subject)
+            .map(|lots| lots.iter().fold(U256::zero(), |acc, l| acc + l.shares))
+            .unwrap_or_default()
+    }
+
+    pub fn realised(&self) -> f64 {
+        self.disposals.iter().map(Disposal::pnl).sum()
+    }
+
+    // Check the ledger against what the chain says the Sniper holds. Every
+    // subject we traded should have the balance our lots add up to, and the
+    // ETH balance should have moved by exactly our trading since `from_block`,
+    // anything else is a deposit, a withdrawal or a trade we missed.
+    pub async fn reconcile(
+        &mut self,
+        ft: &shares<Client>,
+        sniper: Address,
+        from_block: u64,
+    ) -> Result<(), Box<dyn std::error::Error>> {
+        for subject in self.lots.keys().copied().collect::<Vec<_>>() {
+            let held = ft.shares_balance(subject, sniper).call().await?;
+            let expected = self.held(&subject);
+            if held != expected {
+                self.issues.push(format!(
+                    "{:?}: Sniper holds {} shares, ledger has {}",
+                    subject, held, expected
+                ));
+            }
+        }
+
+        let client = ft.client();
+        let before = client
+            .get_balance(sniper, Some(from_block.saturating_sub(1).into()))
+            .await?;
+        let balance = client.get_balance(sniper, None).await?;
+        // compared in wei, only shown in ETH
+        if before + self.sold != balance + self.bought {
+            let expected = math::wei_to_eth(before + self.sold) - math::wei_to_eth(self.bought);
+            self.issues.push(format!(
+                "Sniper balance is {:.6} ETH but trading since block {} leaves {:.6} ETH, \
+                 the difference is deposits, withdrawals or missed trades",
+                math::wei_to_eth(balance),
+                from_block,
+                expected
+            ));
+        }
+
+        Ok(())
+    }
+
+    pub fn print(&self) {
+        let open = self.lots.values().map(VecDeque::len).sum::<usize>();
+        let cost = self
+            .lots
+            .values()
+            .flatten()
+            .fold(U256::zero(), |acc, l| acc + l.cost);
+
+        println!("Lot matching: {:?}", self.method);
+        println!("Bought: {:.6} ETH", math::wei_to_eth(self.bought));
+        println!("Sold: {:.6} ETH", math::wei_to_eth(self.sold));
+        println!("Gas: {:.6} ETH", math::wei_to_eth(self.gas));
+        println!("Disposals: {}", self.disposals.len());
+        println!("Realised PnL: {:.6} ETH", self.realised());
+        println!(
+            "Open lots: {} costing {:.6} ETH",
+            open,
+            math::wei_to_eth(cost)
+        );
+
+        if self.issues.is_empty() {
+            println!("Reconciled, no discrepancies");
+        } else {
+            println!("-------------------");
+            println!("{} discrepancies:", self.issues.len());
+            for issue in &self.issues {
+                println!("  {}", issue);
+            }
+        }
+    }
+
+    pub fn write_lots_csv(&self, path: &Path) -> std::io::Result<()> {
+        let mut file = File::create(path)?;
+        writeln!(file, "subject,block,tx_hash,shares,cost_eth")?;
+        for lot in self.lots.values().flatten() {
+            writeln!(
+                file,
+                "{:?},{},{:?},{},{}",
+                lot.subject,
+                lot.block_number,
+                lot.tx_hash,
+                lot.shares,
+                math::wei_to_eth(lot.cost)
+            )?;
+        }
+
+        Ok(())
+    }
+
+    pub fn write_disposals_csv(&self, path: &Path) -> std::io::Result<()> {
+        let mut file = File::create(path)?;
+        writeln!(
+            file,
+            "subject,shares,acquired_block,sold_block,tx_hash,cost_eth,proceeds_eth,pnl_eth"
+        )?;
+        for d in &self.disposals {
+            writeln!(
+                file,
+                "{:?},{},{},{},{:?},{},{},{}",
+                d.subject,
+                d.shares,
+                d.acquired_block,
+                d.sold_block,
+                d.tx_hash,
+                math::wei_to_eth(d.cost),
+                math::wei_to_eth(d.proceeds),
+                d.pnl()
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+// What each of the trades' transactions cost in gas, from their receipts.
+pub async fn gas_costs<M: Middleware>(
+    client: &M,
+    trades: &[Trade],
+) -> Result<BTreeMap<H256, U256>, M::Error> {
+    let mut costs = BTreeMap::new();
+    let hashes = trades.iter().map(|t| t.tx_hash).collect::<HashSet<_>>();
+    for hash in hashes {
+        if let Some(receipt) = client.get_transaction_receipt(hash).await? {
+            let used = receipt.gas_used.unwrap_or_default();
+            let price = receipt.effective_gas_price.unwrap_or_default();
+            costs.insert(hash, used * price);
+        }
+    }
+
+    Ok(costs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn subject() -> Address {
+        Address::repeat_byte(5)
+    }
+
+    fn trade(block: u64, is_buy: bool, shares: u64, eth: u64) -> Trade {
+        Trade {
+            block_number: block,
+            block_hash: H256::from_low_u64_be(block),
+            tx_hash: H256::from_low_u64_be(block),
+            log_index: U256::zero(),
+            trader: Address::repeat_byte(1),
+            subject: subject(),
+            is_buy,
+            share_amount: shares.into(),
+            eth_amount: eth.into(),
+            protocol_eth_amount: U256::zero(),
+            subject_eth_amount: U256::zero(),
+        }
+    }
+
+    // two lots, 2 shares for 100 then 2 for 300, and 3 sold for 600
+    fn trades() -> Vec<Trade> {
+        // out of order, the ledger sorts them
+        vec![
+            trade(3, false, 3, 600),
+            trade(1, true, 2, 100),
+            trade(2, true, 2, 300),
+        ]
+    }
+
+    fn matched(ledger: &Ledger) -> Vec<(u64, u64, u64, u64)> {
+        ledger
+            .disposals
+            .iter()
+            .map(|d| {
+                (
+                    d.acquired_block,
+                    d.shares.as_u64(),
+                    d.cost.as_u64(),
+                    d.proceeds.as_u64(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fifo_sells_the_oldest_lot_first() {
+        let ledger = Ledger::build(LotMethod::Fifo, &trades(), &BTreeMap::new());
+
+        assert_eq!(matched(&ledger), [(1, 2, 100, 400), (2, 1, 150, 200)]);
+        assert_eq!(ledger.held(&subject()), U256::one());
+        let lot = &ledger.lots[&subject()][0];
+        assert_eq!((lot.block_number, lot.cost.as_u64()), (2, 150));
+        // summed in ETH, so only about right
+        assert!((ledger.realised() - math::wei_to_eth(350.into())).abs() < 1e-24);
+        assert!(ledger.issues.is_empty());
+    }
+
+    #[test]
+    fn lifo_sells_the_newest_lot_first() {
+        let ledger = Ledger::build(LotMethod::Lifo, &trades(), &BTreeMap::new());
+
+        assert_eq!(matched(&ledger), [(2, 2, 300, 400), (1, 1, 50, 200)]);
+        let lot = &ledger.lots[&subject()][0];
+        assert_eq!((lot.block_number, lot.cost.as_u64()), (1, 50));
+    }
+
+    #[test]
+    fn gas_is_spread_over_a_transactions_fills() {
+        let mut buys = vec![trade(1, true, 1, 100), trade(1, true, 1, 100)];
+        buys[1].log_index = U256::one();
+        let gas = BTreeMap::from([(H256::from_low_u64_be(1), U256::from(20))]);
+        let ledger = Ledger::build(LotMethod::Fifo, &buys, &gas);
+
+        assert_eq!(ledger.gas, 20.into());
+        assert_eq!(ledger.bought, 200.into());
+        let costs = ledger.lots[&subject()]
+            .iter()
+            .map(|l| l.cost.as_u64())
+            .collect::<Vec<_>>();
+        assert_eq!(costs, [110, 110]);
+    }
+
+    #[test]
+    fn unmatched_sells_are_reported() {
+        let trades = [trade(1, true, 1, 100), trade(2, false, 3, 300)];
+        let ledger = Ledger::build(LotMethod::Fifo, &trades, &BTreeMap::new());
+
+        assert_eq!(matched(&ledger), [(1, 1, 100, 100)]);
+        assert_eq!(ledger.held(&subject()), U256::zero());
+        assert_eq!(ledger.issues.len(), 1);
+        assert!(ledger.issues[0].contains("sold 2 shares"));
+    }
+}
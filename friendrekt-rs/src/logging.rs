@@ -0,0 +1,24 @@
+use crate::config::{LogConfig, LogFormat};
+use tracing_subscriber::EnvFilter;
+
+// Install the global subscriber. RUST_LOG, when set, wins over log.level.
+pub fn init(config: &LogConfig) -> Result<(), Box<dyn std::error::Error>> {
+    let filter = match std::env::var("RUST_LOG") {
+        Ok(directives) => EnvFilter::try_new(directives)?,
+        Err(_) => EnvFilter::try_new(&config.level)?,
+    };
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+
+    let installed = match config.format {
+        LogFormat::Text => builder.try_init(),
+        LogFormat::Pretty => builder.pretty().try_init(),
+        // one object per line, event and span fields at the top level
+        LogFormat::Json => builder
+            .json()
+            .flatten_event(true)
+            .with_current_span(true)
+            .with_span_list(true)
+            .try_init(),
+    };
+    installed.map_err(|e| e.to_string().into())
+}
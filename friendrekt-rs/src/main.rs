@@ -1,351 +1,565 @@
 // Heavily based on:
 // https://github.com/evmcheb/friendrekt
 
+mod admin;
+// generated, and checked against abigen's output by build.rs, so rustfmt has
+// to leave it alone; #[rustfmt::skip] confuses rustc about its glob imports
+#[allow(unused_imports, clippy::deprecated_cfg_attr)]
+#[cfg_attr(rustfmt, rustfmt::skip)]
 mod bindings;
 mod bset;
+mod bytecode;
+mod candidates;
+mod cli;
+mod config;
+mod decisions;
 mod fasthttp;
+mod feed;
+mod filters;
+mod flashblocks;
+mod indexer;
+mod keys;
+mod latency;
+mod ledger;
+mod logging;
 mod math;
+mod metrics;
+mod notify;
+mod pipeline;
+mod portfolio;
+mod prewarm;
 mod prod_kosetto;
+mod risk;
+mod rpc;
+mod signers;
+mod snipes;
+mod strategy;
+mod supervisor;
+mod watchlist;
 
 use bindings::shares::shares::shares;
 use bindings::sniper::sniper::sniper;
-use bset::FIFOCache;
+use bset::SeenSet;
+use candidates::Candidates;
+use clap::Parser;
+use cli::{Cli, Command};
+use config::Config;
+use decisions::Decisions;
 use dotenv::dotenv;
-use ethers::{prelude::*, types::transaction::eip2930::AccessList, utils::hex};
-use prod_kosetto::{TwitterInfo, User};
-use std::{collections::HashMap, env, str::FromStr, sync::Arc, time::Duration};
-use tokio::sync::Mutex;
-
-const MAX: u64 = 100;
-
-async fn get_followers(id: String) -> u64 {
-    let req_url = format!("http://127.0.0.1:8000/{}", id);
-    let client = reqwest::Client::new();
-    let resp = client.get(req_url).send().await;
-    if resp.is_err() || !resp.as_ref().unwrap().status().is_success() {
-        println!("Failed to get followers for {}", id);
-        return 0;
-    }
-
-    let data = resp.unwrap().text().await.unwrap();
-    data.parse().unwrap_or(0)
-}
-
-async fn twitter_id_search(address: Address) -> Option<TwitterInfo> {
-    let req_url = format!("https://prod-api.kosetto.com/users/{:?}", address);
-    let client = reqwest::Client::new();
-
-    if let Ok(resp) = client
-        .get(req_url)
-        .timeout(Duration::from_secs(60))
-        .send()
-        .await
-    {
-        if resp.status().is_success() {
-            if let Ok(data) = resp.text().await {
-                if let Ok(response) = serde_json::from_str::<User>(&data) {
-                    let followers = get_followers(response.twitterUserId.clone()).await;
-
-                    // are we late? the supply_limit is the max shares
-                    // we'd be willing to buy at each tier of followers
-                    // this limit is checked in Sniper.sol
-                    let supply_limit = match followers {
-                        f if f > 1_000_000 => MAX,
-                        f if f > 500_000 => 60,
-                        f if f > 250_000 => 60,
-                        f if f > 100_000 => 40,
-                        f if f > 20_000 => 30,
-                        _ => 0,
-                    };
+use ethers::prelude::*;
+use feed::BlockFeed;
+use filters::Filters;
+use flashblocks::Flashblocks;
+use indexer::TradeIndex;
+use keys::Keystores;
+use latency::{Latency, Stage, Timeline};
+use metrics::METRICS;
+use notify::{notify, Event};
+use pipeline::{Batch, Kind, Workers};
+use portfolio::{Portfolio, TradeScanner};
+use prewarm::PrewarmQueue;
+use prod_kosetto::{Kosetto, Profiles};
+use risk::RiskManager;
+use signers::SignerPool;
+use snipes::Snipes;
+use std::{sync::Arc, time::Duration};
+use strategy::Strategy;
+use supervisor::{Supervisor, TaskError};
+use tokio::sync::{mpsc, oneshot, Mutex};
+use tracing::{debug, info, info_span, warn, Instrument};
+use watchlist::Watchlist;
 
-                    return Some(TwitterInfo {
-                        twitter_username: response.twitterUsername,
-                        twitter_user_id: response.twitterUserId,
-                        followers,
-                        supply_limit,
-                    });
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+    let cli = Cli::parse();
+    let config = match Config::load(&cli) {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("Invalid configuration: {}", e);
+            std::process::exit(1);
+        }
+    };
+    logging::init(&config.log)?;
+
+    match cli.command.clone().unwrap_or(Command::Run) {
+        Command::Run => run(config).await,
+        Command::Notify { message } => notify::test(&config.notify, &message).await,
+        Command::Decisions {
+            subject,
+            since,
+            until,
+            decision,
+            limit,
+            json,
+        } => {
+            let query = decisions::Query {
+                subject,
+                since,
+                until,
+                decision,
+                limit,
+            };
+            for decision in decisions::query(&config.audit.path, &query)? {
+                if json {
+                    println!("{}", serde_json::to_string(&decision)?);
+                } else {
+                    println!("{}", decision);
                 }
             }
+            Ok(())
         }
+        Command::ReplayFlashblocks {
+            file,
+            listen,
+            interval_ms,
+        } => flashblocks::replay(&file, listen, Duration::from_millis(interval_ms)).await,
+        command => cli::execute(command, &config, cli.yes).await,
     }
-
-    None
 }
 
-#[tokio::main]
-async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    dotenv().ok();
-    let ws_url: String = env::var("BASE_WSS_URL").expect("BASE_WSS_URL is not set");
-    let private_key: String = env::var("PRIVATE_KEY").expect("PRIVATE_KEY is not set");
-    let ft_address: String = env::var("FT_ADDRESS").expect("FT_ADDRESS must be set in .env");
-    let sniper_address: String =
-        env::var("SNIPER_ADDRESS").expect("SNIPER_ADDRESS must be set in .env");
-
-    let provider = Provider::<Ws>::connect(ws_url).await?;
-    let cid = provider.get_chainid().await?.as_u64();
-    let signer: LocalWallet = private_key
-        .parse::<LocalWallet>()
-        .unwrap()
-        .with_chain_id(cid);
-
-    let provider = Arc::new(SignerMiddleware::new(provider, signer));
-    println!("Connected to {:?} with ChainID {}", provider.address(), cid);
-
-    let _share_sniper = Arc::new(sniper::new(
-        Address::from_str(&sniper_address).unwrap(),
-        provider.clone(),
-    ));
+async fn run(config: Config) -> Result<(), Box<dyn std::error::Error>> {
+    let mut keystores = Keystores::new(&config.signer);
+    let provider = cli::connect(&config, &mut keystores).await?;
+    let cid = provider.signer().chain_id();
 
-    let _friendtech = Arc::new(shares::new(
-        Address::from_str(&ft_address).unwrap(),
-        provider.clone(),
-    ));
+    let _share_sniper = Arc::new(sniper::new(config.sniper(), provider.clone()));
+    let _friendtech = Arc::new(shares::new(config.contracts.friendtech, provider.clone()));
 
-    let amount = U256::from(5);
-    println!("-------------------");
-    println!("friend.tech share calculations.\nAmount is hardcoded to 5:");
+    let amount = U256::from(config.strategy.amount);
     for supply in 1..41 {
         if supply % 5 != 0 && supply != 1 {
             continue;
         }
 
         let price = math::get_price(U256::from(supply), amount);
-        let price = math::wei_to_eth(price);
-        println!("Cost for {} shares @ {}: {}", amount, supply, price);
+        info!(
+            amount = %amount,
+            supply,
+            price_eth = math::wei_to_eth(price),
+            "friend.tech share price"
+        );
     }
 
-    let blockclient = provider.clone();
-    tokio::spawn(async move {
-        let mut stream = blockclient.subscribe_blocks().await.unwrap();
-        while let Some(block) = stream.next().await {
-            let block = blockclient
-                .get_block_with_txs(block.hash.unwrap())
-                .await
-                .unwrap();
-
-            if let Some(block) = block {
-                for tx in block.transactions {
-                    // Used to check for RelayMessages
-                    let blockclient = blockclient.clone();
-                    let address_to_info =
-                        Arc::new(Mutex::new(HashMap::<Address, TwitterInfo>::new()));
-                    let friendtech = _friendtech.clone();
-                    let share_sniper = _share_sniper.clone();
-                    let fasthttp =
-                        fasthttp::FastHttp::new("https://mainnet-sequencer.base.org/".to_string());
-
-                    tokio::spawn(async move {
-                        let mut current_nonce = blockclient
-                            .get_transaction_count(blockclient.address(), None)
-                            .await
-                            .unwrap();
-
-                        let mut seen = FIFOCache::<H256>::new(10);
-                        let buy_sig = Bytes::from_str("0x6945b123").unwrap();
-                        let relay_txn_sig = Bytes::from_str("0xd764ad0b").unwrap();
-
-                        if seen.contains(&tx.hash) {
-                            return;
-                        } else {
-                            println!("-------------------");
-                            println!("New pending tx: {:?}", tx.hash);
-                            seen.insert(tx.hash);
-                        }
-
-                        if tx.input.starts_with(&buy_sig) && tx.input.len() == 68 {
-                            // tx that bought shares
-                            if tx.to.is_none() {
-                                return;
-                            }
-
-                            if let Some(tt) = tx.transaction_type {
-                                if tt != U64::from(2) {
-                                    return;
-                                }
-                            }
-
-                            if tx.value != U256::zero() && tx.to.unwrap() != friendtech.address() {
-                                return;
-                            }
+    // Twitter info is shared by every tx task so lookups done for one tx
+    // (or by the prewarm queue) are reused by the next
+    let address_to_info = Arc::new(Profiles::new(
+        "profiles",
+        config.cache.profiles,
+        Some(Duration::from_secs(config.cache.profile_ttl_secs)),
+    ));
+    // process-wide, so a tx in a block delivered twice is only handled once
+    let seen = Arc::new(SeenSet::<H256>::new("seen_txs", config.cache.seen_txs));
+    let watchlist = if config.features.watchlist {
+        let watchlist = Watchlist::load(config.watchlist.path.clone()).await;
+        watchlist.watch(Duration::from_secs(config.watchlist.reload_secs));
+        watchlist
+    } else {
+        Watchlist::empty()
+    };
+    let watch_supply_limit = config.watchlist.default_supply_limit;
+
+    let supervisor = Supervisor::new(
+        config.supervisor.max_restarts,
+        Duration::from_secs(config.supervisor.drain_secs),
+    );
+    if config.features.notify {
+        notify::start(&config.notify);
+        if let Some(eth) = config.notify.low_balance_eth {
+            let client = provider.clone();
+            let sniper = _share_sniper.address();
+            // validated on load
+            let threshold = ethers::utils::parse_ether(eth).unwrap();
+            let interval = Duration::from_secs(config.notify.balance_secs);
+            supervisor.spawn("low balance", async move {
+                notify::watch_balance(client.as_ref(), sniper, threshold, interval).await;
+                Ok(())
+            });
+        }
+    }
 
-                            let mut address_to_info = address_to_info.lock().await;
-                            let info = match address_to_info.get(&tx.from) {
-                                Some(info) => {
-                                    // found twitter info cache
-                                    Some(info.clone())
-                                }
-                                None => {
-                                    if let Some(live_info) = twitter_id_search(tx.from).await {
-                                        println!(
-                                            "[buyShares] Put Twitter user in cache! {} – Followers: {}",
-                                            live_info.twitter_user_id,
-                                            live_info.followers
-                                        );
-
-                                        address_to_info.insert(tx.from, live_info);
-                                        address_to_info.get(&tx.from).cloned()
-                                    } else {
-                                        println!(
-                                            "No registered friend.tech account for {}",
-                                            tx.from
-                                        );
-                                        None
-                                    }
-                                }
-                            };
+    if config.features.metrics {
+        let listen = config.metrics.listen;
+        supervisor.supervise("metrics", move |_| async move {
+            metrics::serve(listen).await?;
+            Ok(())
+        });
+
+        let client = provider.clone();
+        let sniper = _share_sniper.address();
+        let interval = Duration::from_secs(config.metrics.balance_secs);
+        supervisor.spawn("balance", async move {
+            metrics::watch_balance(client.as_ref(), sniper, interval).await;
+            Ok(())
+        });
+    }
 
-                            drop(address_to_info);
+    let config = Arc::new(config);
+    let kosetto = Arc::new(Kosetto::new(&config, watchlist.clone()));
+    let strategy = Strategy::new(config.strategy.tiers.clone());
+    let decisions = Decisions::new(
+        config.admin.recent_decisions,
+        config.features.audit.then(|| config.audit.path.clone()),
+    );
+
+    let prewarm = config.features.prewarm.then(|| {
+        PrewarmQueue::spawn(
+            address_to_info.clone(),
+            kosetto.clone(),
+            config.prewarm.workers,
+            config.prewarm.queue_size,
+            Duration::from_secs(config.prewarm.negative_ttl_secs),
+        )
+    });
 
-                            if info.is_none() {
-                                return;
-                            }
+    let mut trades = None;
+    let mut filters = None;
+    if config.features.filters {
+        let index = Arc::new(TradeIndex::new(_friendtech.address()));
+        let backfill_blocks = config.filters.backfill_blocks;
+        if backfill_blocks > 0 {
+            let index = index.clone();
+            let client = provider.clone();
+            supervisor.spawn("backfill", async move {
+                index.backfill(&client, backfill_blocks).await;
+                Ok(())
+            });
+        }
 
-                            let info = info.unwrap();
+        let loaded = Filters::load(
+            config.filters.blocklist_path.clone(),
+            index.clone(),
+            config.filters.max_subject_dumps,
+        )
+        .await;
+        loaded.watch(Duration::from_secs(config.filters.reload_secs));
+        trades = Some(index);
+        filters = Some(loaded);
+    }
 
-                            if info.supply_limit == 0 {
-                                return;
-                            }
+    let risk = if config.features.risk {
+        let risk = RiskManager::new(&config.risk);
+        #[cfg(unix)]
+        risk.listen_for_signals()?;
+        risk.watch(
+            shares::new(_friendtech.address(), provider.clone()),
+            _share_sniper.address(),
+            Duration::from_secs(config.risk.refresh_secs),
+        );
+
+        // positions opened before this run count towards max_open_positions
+        if config.portfolio.start_block > 0 {
+            let risk = risk.clone();
+            let client = provider.clone();
+            let ft = shares::new(_friendtech.address(), provider.clone());
+            let sniper = _share_sniper.address();
+            let mut scanner = TradeScanner::new(
+                ft.address(),
+                sniper,
+                config.portfolio.start_block,
+                config.portfolio.scan_chunk,
+            );
+            supervisor.spawn("open positions", async move {
+                scanner.scan(client.as_ref()).await?;
+                let portfolio = Portfolio::load(&ft, sniper, &scanner.trades)
+                    .await
+                    .map_err(|e| TaskError::Retry(e.to_string()))?;
+                risk.add_positions(portfolio.positions.iter().map(|p| p.subject).collect())
+                    .await;
+                Ok(())
+            });
+        }
+        Some(risk)
+    } else {
+        None
+    };
+
+    if config.features.admin {
+        let admin = Arc::new(admin::Admin {
+            // validated on load
+            token: config.admin.token.clone().unwrap(),
+            config: config.clone(),
+            strategy: strategy.clone(),
+            risk: risk.clone(),
+            watchlist: watchlist.clone(),
+            filters: filters.clone(),
+            kosetto: kosetto.clone(),
+            cache: address_to_info.clone(),
+            decisions: decisions.clone(),
+        });
+        let listen = config.admin.listen;
+        supervisor.supervise("admin", move |_| {
+            let admin = admin.clone();
+            async move {
+                admin.serve(listen).await?;
+                Ok(())
+            }
+        });
+    }
 
-                            let share_subject = Address::from_slice(&tx.input[16..36]);
-                            let max_fee = tx.max_fee_per_gas.unwrap();
-                            let prio_fee = tx.max_priority_fee_per_gas.unwrap();
-
-                            println!("-------------------");
-                            println!("buyShares on a worthy subject: {:?}", share_subject);
-                            println!("-------------------");
-                            println!("Followers: {}", info.followers);
-                            println!("Supply Limit: {}", info.supply_limit);
-                            println!("\n***\n");
-
-                            let binding = share_sniper
-                                .do_snipe_many_shares(
-                                    vec![tx.from],
-                                    vec![U256::from(amount)],
-                                    vec![U256::from(info.supply_limit)],
-                                )
-                                .calldata()
-                                .unwrap();
-
-                            let txn = Eip1559TransactionRequest {
-                                to: Some(NameOrAddress::Address(share_sniper.address())),
-                                from: Some(blockclient.address()),
-                                nonce: Some(current_nonce),
-                                gas: Some(U256::from(1_000_000)),
-                                value: None,
-                                data: Some(binding),
-                                chain_id: Some(U64::from(cid)),
-                                max_priority_fee_per_gas: Some(prio_fee),
-                                max_fee_per_gas: Some(max_fee),
-                                access_list: AccessList::default(),
-                            }
-                            .into();
-
-                            let sig = blockclient
-                                .sign_transaction(&txn, *txn.from().unwrap())
-                                .await
-                                .unwrap();
-
-                            let raw = txn.rlp_signed(&sig);
-                            let hash = fasthttp
-                                .send_request(format!("0x{}", hex::encode(raw)))
-                                .await;
-
-                            println!(
-                                "{} {} Sent snipe: https://basescan.org/tx/{:#?}#eventlog",
-                                info.twitter_username, info.followers, hash
-                            );
-
-                            current_nonce += U256::one();
-                        } else if tx.input.starts_with(&relay_txn_sig) {
-                            // From my testing, I haven't seen any relay_txn_sig txns come through.
-                            // Could be a bug in my code, but I suspect it's just not used anymore.
-                            let address_to_info_2 = address_to_info.clone();
-
-                            let event = blockclient.get_transaction_receipt(tx.hash).await.unwrap();
-                            if event.is_none() {
-                                return;
-                            }
+    let signers = SignerPool::new(
+        keys::hot(&config.signer, provider.signer(), &mut keystores)
+            .await?
+            .into_iter()
+            .map(|key| key.with_chain_id(cid))
+            .collect(),
+        config.signer.selection,
+    );
+    info!(wallets = ?signers.addresses(), "Sending snipes from");
+    check_allowlist(
+        &_share_sniper,
+        &signers.addresses(),
+        config.features.dry_run,
+    )
+    .await?;
+    {
+        let signers = signers.clone();
+        let client = provider.clone();
+        let refill = config.signer.refill();
+        let interval = Duration::from_secs(config.signer.balance_secs);
+        let dry_run = config.features.dry_run;
+        supervisor.spawn("signers", async move {
+            signers
+                .watch(client.as_ref(), client.address(), refill, interval, dry_run)
+                .await;
+            Ok(())
+        });
+    }
 
-                            let event = event.unwrap();
-                            let deposit_event = event.logs.iter().find(|e| e.topics[0] == H256::from_str("0xb0444523268717a02698be47d0803aa7468c00acbed2f8bd93a0459cde61dd89").unwrap());
-                            if deposit_event.is_none() {
-                                return;
-                            }
+    let latency = Latency::new(config.latency.window);
+    let snipes = Snipes::new();
+    latency.report(Duration::from_secs(config.latency.report_secs));
 
-                            let deposit_event = deposit_event.unwrap();
-                            let address = Address::from_slice(&deposit_event.data[12..32]);
-                            if address_to_info_2.lock().await.contains_key(&address) {
-                                return;
-                            }
+    let (blocks, block_rx) = mpsc::channel(64);
+    if config.features.flashblocks {
+        let flashblocks = Flashblocks::new(&config.flashblocks, blocks.clone());
+        supervisor.supervise("flashblocks", move |shutdown| {
+            flashblocks.clone().run(shutdown)
+        });
+    }
+    let feed = BlockFeed::new(
+        provider.provider().as_ref().clone(),
+        blocks,
+        config.rpc.reorg_depth,
+        Duration::from_secs(config.rpc.stall_secs),
+        config.rpc.max_backfill_blocks,
+    );
+    supervisor.supervise("block feed", move |shutdown| feed.clone().run(shutdown));
+
+    let block_rx = Arc::new(Mutex::new(block_rx));
+    let workers = Workers::new(config.pipeline.workers);
+    let tasks = supervisor.clone();
+    let candidates = Arc::new(Candidates {
+        client: provider.clone(),
+        profiles: address_to_info.clone(),
+        watchlist: watchlist.clone(),
+        kosetto: kosetto.clone(),
+        strategy: strategy.clone(),
+        filters: filters.clone(),
+        risk: risk.clone(),
+        decisions: decisions.clone(),
+        latency: latency.clone(),
+        snipes: snipes.clone(),
+        signers: signers.clone(),
+        receipts: supervisor.clone(),
+        config: config.clone(),
+        friendtech: _friendtech.clone(),
+        sniper: _share_sniper.clone(),
+        fasthttp: Arc::new(fasthttp::FastHttp::new(config.rpc.sequencer_url.clone())),
+        chain_id: cid,
+        amount,
+        watch_supply_limit,
+    });
+    let audit = decisions.clone();
+    supervisor.supervise("blocks", move |mut shutdown| {
+        let blockclient = provider.clone();
+        let block_rx = block_rx.clone();
+        let trades = trades.clone();
+        let seen = seen.clone();
+        let prewarm = prewarm.clone();
+        let decisions = decisions.clone();
+        let snipes = snipes.clone();
+        let workers = workers.clone();
+        let candidates = candidates.clone();
+        let supervisor = tasks.clone();
+
+        async move {
+            let mut block_rx = block_rx.lock().await;
+            // block-level steps run in block order, each waits for this
+            let (_, mut previous) = oneshot::channel();
+            loop {
+                // stop taking new blocks on shutdown, txs already spawned finish
+                let block = tokio::select! {
+                    block = block_rx.recv() => block,
+                    _ = shutdown.wait() => return Ok(()),
+                };
+                let new_block = block.ok_or(TaskError::Fatal("block feed stopped".to_string()))?;
+                if !new_block.orphaned.is_empty() {
+                    let orphaned = new_block
+                        .orphaned
+                        .iter()
+                        .map(|b| b.hash)
+                        .collect::<Vec<_>>();
+                    let trades_retracted = match &trades {
+                        Some(trades) => trades.retract(&orphaned).await,
+                        None => 0,
+                    };
+                    let retracted = decisions.retract(&orphaned).await;
+
+                    // txs from the orphaned blocks that make it into the new
+                    // branch are decided on again, except the ones we sniped:
+                    // our snipe is followed below instead
+                    let sniped = retracted
+                        .iter()
+                        .filter(|d| d.snipe.is_some())
+                        .map(|d| d.tx)
+                        .collect::<Vec<_>>();
+                    for tx in new_block.orphaned.iter().flat_map(|b| &b.txs) {
+                        if !sniped.contains(tx) {
+                            seen.forget(tx);
+                        }
+                    }
+
+                    let reorged = snipes.orphaned(&orphaned).await;
+                    for hash in &reorged {
+                        METRICS.snipes.with_label_values(&["reorged"]).inc();
+                        warn!(snipe = ?hash, "Snipe's block was reorged out");
+                        notify(Event::Reorged { hash: *hash });
+                        let client = blockclient.clone();
+                        let snipes = snipes.clone();
+                        let hash = *hash;
+                        supervisor.spawn("receipt", async move {
+                            snipes.follow(client.as_ref(), hash).await;
+                            Ok(())
+                        });
+                    }
+                    info!(
+                        blocks = orphaned.len(),
+                        trades = trades_retracted,
+                        decisions = retracted.len(),
+                        snipes = reorged.len(),
+                        "Retracted orphaned blocks"
+                    );
+                }
+                let block = new_block.block;
+                let mut block_timeline = Timeline::new(new_block.header_at);
+                block_timeline.mark_at(Stage::Fetch, new_block.fetched_at);
+                // flashblocks are counted as they come in
+                if new_block.flashblock.is_none() {
+                    METRICS.blocks.inc();
+                    let now = std::time::SystemTime::now()
+                        .duration_since(std::time::UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_secs_f64();
+                    METRICS
+                        .block_lag
+                        .observe((now - block.timestamp.as_u64() as f64).max(0.0));
+                }
+                let block_span = info_span!(
+                    "block",
+                    number = block.number.map(|n| n.as_u64()),
+                    flashblock = new_block.flashblock,
+                    hash = ?block.hash,
+                    txs = block.transactions.len()
+                );
+
+                if let (Some(trades), Some(hash)) = (&trades, block.hash) {
+                    let trades = trades.clone();
+                    let client = blockclient.clone();
+                    tokio::spawn(
+                        async move { trades.index_block(&client, hash).await }
+                            .instrument(block_span.clone()),
+                    );
+                }
 
-                            match twitter_id_search(address).await {
-                                Some(info) => {
-                                    // found twitter info cache
-                                    Some(info.clone())
-                                }
-                                None => {
-                                    if let Some(live_info) = twitter_id_search(address).await {
-                                        println!(
-                                            "[relaxTxn] Put Twitter user in cache! {} – Followers: {}",
-                                            live_info.twitter_user_id,
-                                            live_info.followers
-                                        );
-
-                                        address_to_info_2.lock().await.insert(address, live_info);
-                                        address_to_info_2.lock().await.get(&address).cloned()
-                                    } else {
-                                        println!(
-                                            "No registered friend.tech account for {}",
-                                            address
-                                        );
-                                        None
-                                    }
+                let mut batch = Batch::new(block.number.map(|n| n.as_u64()), new_block.header_at);
+                for tx in block.transactions {
+                    if !seen.first_seen(tx.hash) {
+                        continue;
+                    }
+                    let kind = Kind::of(&tx);
+                    METRICS.txs.with_label_values(&[kind.name()]).inc();
+                    let mut timeline = block_timeline;
+                    timeline.mark(Stage::Classify);
+
+                    match kind {
+                        Kind::BuyShares | Kind::Relay => {}
+                        Kind::Transfer => {
+                            // iiuc this is a simple ETH transfer, so either side
+                            // could be about to sign up. Look them up in the
+                            // background once the block is done.
+                            if prewarm.is_some() {
+                                if let Some(to) = tx.to {
+                                    batch.prewarm(to);
                                 }
-                            };
-                        } else if tx.input.len() == 0 {
-                            // iiuc this is a simple ETH transfer
-                            let address_to_info_3 = address_to_info.clone();
-
-                            if tx.to.is_none() {
-                                return;
-                            }
-
-                            let to = tx.to.unwrap();
-                            let from = tx.from;
-
-                            let to_info = twitter_id_search(to).await;
-                            let from_info = twitter_id_search(from).await;
-                            if address_to_info_3.lock().await.contains_key(&to)
-                                || address_to_info_3.lock().await.contains_key(&from)
-                            {
-                                return;
-                            }
-
-                            if let Some(to_info) = to_info {
-                                println!(
-                                    "Cached (to) {} with {} followers",
-                                    to_info.twitter_user_id, to_info.followers
-                                );
-
-                                address_to_info_3.lock().await.insert(to, to_info);
-                            }
-
-                            if let Some(from_info) = from_info {
-                                println!(
-                                    "Cached (from) {} with {} followers",
-                                    from_info.twitter_user_id, from_info.followers
-                                );
-
-                                address_to_info_3.lock().await.insert(from, from_info);
+                                batch.prewarm(tx.from);
                             }
+                            continue;
+                        }
+                        Kind::Other => continue,
+                    }
+
+                    let tx_span =
+                        info_span!(parent: &block_span, "tx", hash = ?tx.hash, from = ?tx.from);
+                    let worker = workers.acquire().instrument(tx_span.clone()).await;
+                    let candidates = candidates.clone();
+                    let in_flight = supervisor.track();
+                    batch.spawn(
+                        worker,
+                        async move {
+                            let _in_flight = in_flight;
+                            debug!("New tx");
+                            candidates.handle(tx, kind, timeline).await
                         }
-                    });
+                        .instrument(tx_span),
+                    );
                 }
+
+                let (done, next) = oneshot::channel();
+                let previous = std::mem::replace(&mut previous, next);
+                let prewarm = prewarm.clone();
+                supervisor.spawn("block", batch.finish(prewarm, previous, done));
             }
         }
     });
 
-    loop {
-        tokio::time::sleep(tokio::time::Duration::from_secs(100)).await;
+    let result = supervisor.wait().await;
+    // what the drained snipes recorded
+    audit.close().await;
+    result
+}
+
+// Snipes from a wallet the Sniper doesn't allowlist revert, so refuse to
+// start with one. A dry run sends nothing and only warns.
+async fn check_allowlist<M: Middleware>(
+    sniper: &sniper<M>,
+    wallets: &[Address],
+    dry_run: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let mut missing = Vec::new();
+    for wallet in wallets {
+        let listed = sniper.allowlist(*wallet).call().await.map_err(|e| {
+            format!(
+                "failed to read the allowlist of {:?}, Snipers deployed before it could be managed need redeploying: {}",
+                sniper.address(),
+                e
+            )
+        })?;
+        if !listed {
+            missing.push(format!("{:?}", wallet));
+        }
+    }
+    if missing.is_empty() {
+        return Ok(());
+    }
+
+    let error = format!(
+        "{} aren't allowlisted on the Sniper, run `friendrekt allowlist add`",
+        missing.join(", ")
+    );
+    if dry_run {
+        warn!("{}", error);
+        return Ok(());
     }
+    Err(error.into())
 }
@@ -31,3 +31,151 @@ pub fn get_price(supply: U256, amount: U256) -> U256 {
     let summation = sum2 - sum1;
     summation * one_ether / sixteen_thousand
 }
+
+// friend.tech charges a 5% protocol fee and a 5% subject fee on top of the price
+pub fn get_price_after_fee(supply: U256, amount: U256) -> U256 {
+    let price = get_price(supply, amount);
+    price + price * U256::from(10) / U256::from(100)
+}
+
+// and takes the same fees out of what a sale pays
+pub fn get_sell_price_after_fee(supply: U256, amount: U256) -> U256 {
+    let price = get_price(supply, amount);
+    price - price * U256::from(10) / U256::from(100)
+}
+
+// Largest amount, and then supply limit, at or below the given ones for
+// which buying at the limit stays within max_spend, fees included.
+pub fn fit_to_budget(amount: U256, limit: u64, max_spend: U256) -> Option<(U256, u64)> {
+    if amount.is_zero() || limit == 0 {
+        return None;
+    }
+    let fits = |supply: U256, amount: U256| get_price_after_fee(supply, amount) <= max_spend;
+
+    // the price goes up with both, so the cheapest supply decides whether an
+    // amount fits at all and each can be searched for on its own
+    let amount = last_fitting(U256::one(), amount, |amount| fits(U256::one(), amount))?;
+    let supply = last_fitting(U256::one(), U256::from(limit), |supply| {
+        fits(supply, amount)
+    })?;
+    Some((amount, supply.as_u64()))
+}
+
+// The largest value in low..=high that fits, given that every value below
+// one that fits does too.
+fn last_fitting(low: U256, high: U256, fits: impl Fn(U256) -> bool) -> Option<U256> {
+    if !fits(low) {
+        return None;
+    }
+    let (mut low, mut high) = (low, high);
+    while low < high {
+        // rounded up, so it always moves
+        let mid = low + (high - low + 1) / 2;
+        if fits(mid) {
+            low = mid;
+        } else {
+            high = mid - 1;
+        }
+    }
+    Some(low)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eth(milli: u64) -> U256 {
+        U256::from(milli) * U256::exp10(15)
+    }
+
+    #[test]
+    fn first_share_is_free() {
+        assert_eq!(get_price(U256::zero(), U256::one()), U256::zero());
+        // (1 + 4) * 1e18 / 16000
+        assert_eq!(
+            get_price(U256::one(), U256::from(2)),
+            U256::from(312_500_000_000_000u64)
+        );
+    }
+
+    #[test]
+    fn fees_are_ten_percent() {
+        let price = get_price(U256::from(10), U256::one());
+        assert_eq!(
+            get_price_after_fee(U256::from(10), U256::one()),
+            price * 11 / 10
+        );
+        assert_eq!(
+            get_sell_price_after_fee(U256::from(10), U256::one()),
+            price * 9 / 10
+        );
+    }
+
+    #[test]
+    fn wei_to_eth_keeps_the_fraction() {
+        assert_eq!(wei_to_eth(eth(1500)), 1.5);
+        assert_eq!(wei_to_eth(U256::zero()), 0.0);
+    }
+
+    #[test]
+    fn fit_to_budget_keeps_what_fits() {
+        let (amount, limit) = fit_to_budget(U256::one(), 10, eth(1000)).unwrap();
+        assert_eq!((amount, limit), (U256::one(), 10));
+    }
+
+    #[test]
+    fn fit_to_budget_lowers_the_limit_first() {
+        let max_spend = get_price_after_fee(U256::from(20), U256::one());
+        let (amount, limit) = fit_to_budget(U256::one(), 50, max_spend).unwrap();
+        assert_eq!((amount, limit), (U256::one(), 20));
+        assert!(get_price_after_fee(U256::from(21), U256::one()) > max_spend);
+    }
+
+    #[test]
+    fn fit_to_budget_then_the_amount() {
+        // two shares don't fit at any supply, one does at the lowest
+        let max_spend = get_price_after_fee(U256::one(), U256::one());
+        assert!(get_price_after_fee(U256::one(), U256::from(2)) > max_spend);
+        let (amount, limit) = fit_to_budget(U256::from(2), 3, max_spend).unwrap();
+        assert_eq!((amount, limit), (U256::one(), 1));
+    }
+
+    #[test]
+    fn fit_to_budget_finds_what_trying_everything_does() {
+        let every = |amount: u64, limit: u64, max_spend: U256| {
+            (1..=amount).rev().find_map(|amount| {
+                (1..=limit).rev().find_map(|supply| {
+                    let fits = get_price_after_fee(supply.into(), amount.into()) <= max_spend;
+                    fits.then_some((U256::from(amount), supply))
+                })
+            })
+        };
+        for max_spend in [0, 1, 10, 100, 1000] {
+            for amount in 1..=4 {
+                for limit in [1, 2, 7, 30] {
+                    assert_eq!(
+                        fit_to_budget(amount.into(), limit, eth(max_spend)),
+                        every(amount, limit, eth(max_spend)),
+                        "{} shares up to supply {} for {} mETH",
+                        amount,
+                        limit,
+                        max_spend
+                    );
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn fit_to_budget_handles_huge_inputs() {
+        let (amount, limit) = fit_to_budget(u64::MAX.into(), u64::MAX, eth(1000)).unwrap();
+        assert!(get_price_after_fee(limit.into(), amount) <= eth(1000));
+        assert!(get_price_after_fee((limit + 1).into(), amount) > eth(1000));
+    }
+
+    #[test]
+    fn fit_to_budget_gives_up_when_nothing_fits() {
+        assert_eq!(fit_to_budget(U256::from(2), 10, U256::zero()), None);
+        assert_eq!(fit_to_budget(U256::zero(), 10, eth(1000)), None);
+    }
+}
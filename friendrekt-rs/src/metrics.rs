@@ -0,0 +1,291 @@
+use ethers::prelude::*;
+use hyper::{
+    service::{make_service_fn, service_fn},
+    Body, Method, Request, Response, Server, StatusCode,
+};
+use prometheus::{
+    exponential_buckets, Encoder, Gauge, GaugeVec, Histogram, HistogramOpts, HistogramVec,
+    IntCounter, IntCounterVec, IntGauge, IntGaugeVec, Opts, Registry, TextEncoder,
+};
+use std::{
+    convert::Infallible,
+    net::SocketAddr,
+    sync::LazyLock,
+    time::{Duration, Instant},
+};
+use tracing::{debug, info, warn};
+
+const RECEIPT_TIMEOUT: Duration = Duration::from_secs(60);
+
+// Everything the bot exports on /metrics. Counters are bumped from wherever
+// the thing happens, so they live in a process-wide registry.
+pub struct Metrics {
+    registry: Registry,
+    pub blocks: IntCounter,
+    pub flashblocks: IntCounter,
+    pub reorgs: IntCounter,
+    // seconds between a block's timestamp and us processing it
+    pub block_lag: Histogram,
+    pub txs: IntCounterVec,
+    // seconds from a block's header arriving to every tx in it being handled
+    pub block_seconds: Histogram,
+    pub workers_busy: IntGauge,
+    // seconds the block loop waited for a free worker
+    pub worker_wait: Histogram,
+    pub lookups: HistogramVec,
+    pub cache: IntCounterVec,
+    // size and churn of every bset::Cache, by name
+    pub cache_entries: IntGaugeVec,
+    pub cache_capacity: IntGaugeVec,
+    pub cache_evictions: IntCounterVec,
+    pub snipes: IntCounterVec,
+    pub broadcast: HistogramVec,
+    // per stage from header arrival to broadcast, see latency.rs
+    pub snipe_latency: HistogramVec,
+    pub gas_spent: Gauge,
+    pub sniper_balance: Gauge,
+    // per hot wallet of the signer pool
+    pub signer_balance: GaugeVec,
+    pub signer_pending: IntGaugeVec,
+}
+
+pub static METRICS: LazyLock<Metrics> = LazyLock::new(Metrics::new);
+
+// 1ms up to ~16s
+fn latency_buckets() -> Vec<f64> {
+    exponential_buckets(0.001, 2.0, 15).unwrap()
+}
+
+impl Metrics {
+    fn new() -> Self {
+        let registry = Registry::new_custom(Some("friendrekt".to_string()), None).unwrap();
+
+        let blocks = IntCounter::new("blocks_total", "Blocks processed").unwrap();
+        let flashblocks = IntCounter::new("flashblocks_total", "Flashblocks received").unwrap();
+        let reorgs = IntCounter::new("reorgs_total", "Chain reorganisations seen").unwrap();
+        let block_lag = Histogram::with_opts(
+            HistogramOpts::new(
+                "block_lag_seconds",
+                "Time between a block's timestamp and processing it",
+            )
+            .buckets(vec![0.25, 0.5, 1.0, 2.0, 3.0, 5.0, 10.0, 30.0, 60.0]),
+        )
+        .unwrap();
+        let txs =
+            IntCounterVec::new(Opts::new("txs_total", "Transactions by type"), &["kind"]).unwrap();
+        let block_seconds = Histogram::with_opts(
+            HistogramOpts::new(
+                "block_seconds",
+                "Time from a block's header arriving to all of its txs being handled",
+            )
+            .buckets(latency_buckets()),
+        )
+        .unwrap();
+        let workers_busy =
+            IntGauge::new("workers_busy", "Workers handling a tx right now").unwrap();
+        let worker_wait = Histogram::with_opts(
+            HistogramOpts::new(
+                "worker_wait_seconds",
+                "Time spent waiting for a free worker",
+            )
+            .buckets(latency_buckets()),
+        )
+        .unwrap();
+        let lookups = HistogramVec::new(
+            HistogramOpts::new("lookup_seconds", "Kosetto and follower lookups")
+                .buckets(latency_buckets()),
+            &["source", "result"],
+        )
+        .unwrap();
+        let cache = IntCounterVec::new(
+            Opts::new("cache_total", "Twitter info cache lookups"),
+            &["result"],
+        )
+        .unwrap();
+        let cache_entries = IntGaugeVec::new(
+            Opts::new("cache_entries", "Entries held by each cache"),
+            &["cache"],
+        )
+        .unwrap();
+        let cache_capacity = IntGaugeVec::new(
+            Opts::new("cache_capacity", "Most entries each cache holds"),
+            &["cache"],
+        )
+        .unwrap();
+        let cache_evictions = IntCounterVec::new(
+            Opts::new(
+                "cache_evictions_total",
+                "Entries dropped for space or expiry",
+            ),
+            &["cache", "reason"],
+        )
+        .unwrap();
+        let snipes = IntCounterVec::new(
+            Opts::new("snipes_total", "Snipes by how far they got"),
+            &["stage"],
+        )
+        .unwrap();
+        let broadcast = HistogramVec::new(
+            HistogramOpts::new("broadcast_seconds", "eth_sendRawTransaction round trips")
+                .buckets(latency_buckets()),
+            &["endpoint", "result"],
+        )
+        .unwrap();
+        let snipe_latency = HistogramVec::new(
+            HistogramOpts::new(
+                "snipe_latency_seconds",
+                "Time spent in each stage of a snipe, from block header to broadcast",
+            )
+            .buckets(latency_buckets()),
+            &["stage"],
+        )
+        .unwrap();
+        let gas_spent =
+            Gauge::new("gas_spent_eth", "Gas paid by landed and reverted snipes").unwrap();
+        let sniper_balance =
+            Gauge::new("sniper_balance_eth", "ETH held by the Sniper contract").unwrap();
+        let signer_balance = GaugeVec::new(
+            Opts::new("signer_balance_eth", "ETH held by each signer wallet"),
+            &["wallet"],
+        )
+        .unwrap();
+        let signer_pending = IntGaugeVec::new(
+            Opts::new(
+                "signer_pending",
+                "Snipes sent from each signer wallet and not mined yet",
+            ),
+            &["wallet"],
+        )
+        .unwrap();
+
+        registry.register(Box::new(blocks.clone())).unwrap();
+        registry.register(Box::new(flashblocks.clone())).unwrap();
+        registry.register(Box::new(reorgs.clone())).unwrap();
+        registry.register(Box::new(block_lag.clone())).unwrap();
+        registry.register(Box::new(txs.clone())).unwrap();
+        registry.register(Box::new(block_seconds.clone())).unwrap();
+        registry.register(Box::new(workers_busy.clone())).unwrap();
+        registry.register(Box::new(worker_wait.clone())).unwrap();
+        registry.register(Box::new(lookups.clone())).unwrap();
+        registry.register(Box::new(cache.clone())).unwrap();
+        registry.register(Box::new(cache_entries.clone())).unwrap();
+        registry.register(Box::new(cache_capacity.clone())).unwrap();
+        registry
+            .register(Box::new(cache_evictions.clone()))
+            .unwrap();
+        registry.register(Box::new(snipes.clone())).unwrap();
+        registry.register(Box::new(broadcast.clone())).unwrap();
+        registry.register(Box::new(snipe_latency.clone())).unwrap();
+        registry.register(Box::new(gas_spent.clone())).unwrap();
+        registry.register(Box::new(sniper_balance.clone())).unwrap();
+        registry.register(Box::new(signer_balance.clone())).unwrap();
+        registry.register(Box::new(signer_pending.clone())).unwrap();
+
+        Metrics {
+            registry,
+            blocks,
+            flashblocks,
+            reorgs,
+            block_lag,
+            txs,
+            block_seconds,
+            workers_busy,
+            worker_wait,
+            lookups,
+            cache,
+            cache_entries,
+            cache_capacity,
+            cache_evictions,
+            snipes,
+            broadcast,
+            snipe_latency,
+            gas_spent,
+            sniper_balance,
+            signer_balance,
+            signer_pending,
+        }
+    }
+
+    fn encode(&self) -> Vec<u8> {
+        let mut buf = Vec::new();
+        // only fails on a broken registry, which would be a bug here
+        TextEncoder::new()
+            .encode(&self.registry.gather(), &mut buf)
+            .unwrap();
+        buf
+    }
+}
+
+async fn handle(req: Request<Body>) -> Result<Response<Body>, Infallible> {
+    let response = match (req.method(), req.uri().path()) {
+        (&Method::GET, "/metrics") => Response::builder()
+            .header("Content-Type", TextEncoder::new().format_type())
+            .body(Body::from(METRICS.encode())),
+        _ => Response::builder()
+            .status(StatusCode::NOT_FOUND)
+            .body(Body::empty()),
+    };
+    Ok(response.unwrap())
+}
+
+// Serve /metrics on `addr` until the process exits.
+pub async fn serve(addr: SocketAddr) -> Result<(), hyper::Error> {
+    let service = make_service_fn(|_| async { Ok::<_, Infallible>(service_fn(handle)) });
+    let server = Server::try_bind(&addr)?.serve(service);
+    info!(%addr, "Serving metrics");
+    server.await
+}
+
+// How a snipe went, with the block it was mined in.
+#[derive(Clone, Copy)]
+pub enum Outcome {
+    Landed { block: H256 },
+    Reverted { block: H256 },
+    Dropped,
+}
+
+// Wait for a snipe to be included and record how it went. Base has 2s
+// blocks, one that isn't in after a minute isn't coming.
+pub async fn track_snipe<M: Middleware>(client: &M, hash: H256) -> Outcome {
+    let started = Instant::now();
+    while started.elapsed() < RECEIPT_TIMEOUT {
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        let (receipt, block) = match client.get_transaction_receipt(hash).await {
+            Ok(Some(receipt)) => match receipt.block_hash {
+                Some(block) => (receipt, block),
+                None => continue,
+            },
+            Ok(None) => continue,
+            Err(e) => {
+                debug!(snipe = ?hash, "Failed to get receipt: {}", e);
+                continue;
+            }
+        };
+
+        let (stage, outcome) = if receipt.status == Some(U64::one()) {
+            ("landed", Outcome::Landed { block })
+        } else {
+            ("reverted", Outcome::Reverted { block })
+        };
+        METRICS.snipes.with_label_values(&[stage]).inc();
+        if let (Some(gas), Some(price)) = (receipt.gas_used, receipt.effective_gas_price) {
+            METRICS.gas_spent.add(crate::math::wei_to_eth(gas * price));
+        }
+        return outcome;
+    }
+
+    METRICS.snipes.with_label_values(&["dropped"]).inc();
+    warn!(snipe = ?hash, "Snipe wasn't included");
+    Outcome::Dropped
+}
+
+// Keep the Sniper balance gauge up to date.
+pub async fn watch_balance<M: Middleware>(client: &M, sniper: Address, interval: Duration) {
+    loop {
+        match client.get_balance(sniper, None).await {
+            Ok(balance) => METRICS.sniper_balance.set(crate::math::wei_to_eth(balance)),
+            Err(e) => warn!("Failed to get Sniper balance: {}", e),
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
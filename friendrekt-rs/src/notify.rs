@@ -0,0 +1,469 @@
+use crate::{config::NotifyConfig, math};
+use async_trait::async_trait;
+use ethers::prelude::*;
+use std::{
+    collections::BTreeMap,
+    fmt,
+    process::Stdio,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        OnceLock,
+    },
+    time::{Duration, Instant},
+};
+use tokio::{io::AsyncWriteExt, sync::mpsc};
+use tracing::{info, warn};
+
+type NotifyResult = Result<(), Box<dyn std::error::Error + Send + Sync>>;
+
+// events of one kind listed in a digest before the rest are just counted
+const MAX_LISTED: usize = 5;
+
+// Something the operator should hear about.
+pub enum Event {
+    SnipeSent {
+        subject: Address,
+        handle: String,
+        hash: H256,
+    },
+    Filled {
+        hash: H256,
+    },
+    Reverted {
+        hash: H256,
+    },
+    Dropped {
+        hash: H256,
+    },
+    Reorged {
+        hash: H256,
+    },
+    LowBalance {
+        balance: U256,
+        threshold: U256,
+    },
+    Outage {
+        dependency: String,
+        error: String,
+    },
+    Recovered {
+        dependency: String,
+    },
+    RiskLimit {
+        subject: Address,
+        reason: String,
+    },
+    Refilled {
+        wallet: Address,
+        amount: U256,
+    },
+    RefillFailed {
+        wallet: Address,
+        error: String,
+    },
+}
+
+impl Event {
+    fn kind(&self) -> &'static str {
+        match self {
+            Event::SnipeSent { .. } => "snipes sent",
+            Event::Filled { .. } => "snipes filled",
+            Event::Reverted { .. } => "snipes reverted",
+            Event::Dropped { .. } => "snipes dropped",
+            Event::Reorged { .. } => "snipes reorged out",
+            Event::LowBalance { .. } => "low balance",
+            Event::Outage { .. } => "outages",
+            Event::Recovered { .. } => "recoveries",
+            Event::RiskLimit { .. } => "risk limits hit",
+            Event::Refilled { .. } => "signer refills",
+            Event::RefillFailed { .. } => "failed signer refills",
+        }
+    }
+}
+
+impl fmt::Display for Event {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Event::SnipeSent {
+                subject,
+                handle,
+                hash,
+            } => write!(f, "Sniped {} ({:?}): {:?}", handle, subject, hash),
+            Event::Filled { hash } => write!(f, "Snipe filled: {:?}", hash),
+            Event::Reverted { hash } => write!(f, "Snipe reverted: {:?}", hash),
+            Event::Dropped { hash } => write!(f, "Snipe wasn't included: {:?}", hash),
+            Event::Reorged { hash } => write!(
+                f,
+                "Snipe's block was reorged out, waiting for it to be included again: {:?}",
+                hash
+            ),
+            Event::LowBalance { balance, threshold } => write!(
+                f,
+                "Sniper balance is {:.6} ETH, below {:.6} ETH",
+                math::wei_to_eth(*balance),
+                math::wei_to_eth(*threshold)
+            ),
+            Event::Outage { dependency, error } => write!(f, "{} is down: {}", dependency, error),
+            Event::Recovered { dependency } => write!(f, "{} is back up", dependency),
+            Event::RiskLimit { subject, reason } => {
+                write!(f, "Didn't snipe {:?}: {}", subject, reason)
+            }
+            Event::Refilled { wallet, amount } => write!(
+                f,
+                "Sent {:.6} ETH from the treasury to {:?}",
+                math::wei_to_eth(*amount),
+                wallet
+            ),
+            Event::RefillFailed { wallet, error } => {
+                write!(f, "Couldn't refill {:?}: {}", wallet, error)
+            }
+        }
+    }
+}
+
+// Somewhere to deliver notifications to.
+#[async_trait]
+pub trait Notifier: Send + Sync {
+    fn name(&self) -> &'static str;
+    async fn send(&self, message: &str) -> NotifyResult;
+}
+
+// POSTs {"text": message} to a URL, which Slack and most chat webhooks accept.
+pub struct Webhook {
+    client: reqwest::Client,
+    url: String,
+}
+
+#[async_trait]
+impl Notifier for Webhook {
+    fn name(&self) -> &'static str {
+        "webhook"
+    }
+
+    async fn send(&self, message: &str) -> NotifyResult {
+        self.client
+            .post(&self.url)
+            .json(&serde_json::json!({ "text": message }))
+            .timeout(Duration::from_secs(10))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+// A message from a Telegram bot, through its sendMessage method.
+pub struct Telegram {
+    client: reqwest::Client,
+    url: String,
+    chat_id: String,
+}
+
+#[async_trait]
+impl Notifier for Telegram {
+    fn name(&self) -> &'static str {
+        "telegram"
+    }
+
+    async fn send(&self, message: &str) -> NotifyResult {
+        self.client
+            .post(&self.url)
+            .json(&serde_json::json!({ "chat_id": self.chat_id, "text": message }))
+            .timeout(Duration::from_secs(10))
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+}
+
+// Runs a local command with the message on stdin.
+pub struct Command {
+    program: String,
+    args: Vec<String>,
+}
+
+#[async_trait]
+impl Notifier for Command {
+    fn name(&self) -> &'static str {
+        "command"
+    }
+
+    async fn send(&self, message: &str) -> NotifyResult {
+        let mut child = tokio::process::Command::new(&self.program)
+            .args(&self.args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .kill_on_drop(true)
+            .spawn()?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin.write_all(message.as_bytes()).await?;
+        }
+
+        let status = tokio::time::timeout(Duration::from_secs(30), child.wait()).await??;
+        if !status.success() {
+            return Err(format!("{} exited with {}", self.program, status).into());
+        }
+        Ok(())
+    }
+}
+
+// Every notifier the config sets up.
+pub fn notifiers(config: &NotifyConfig) -> Vec<Box<dyn Notifier>> {
+    let client = reqwest::Client::new();
+    let mut notifiers: Vec<Box<dyn Notifier>> = Vec::new();
+    for url in &config.webhook_urls {
+        notifiers.push(Box::new(Webhook {
+            client: client.clone(),
+            url: url.clone(),
+        }));
+    }
+    if let Some(telegram) = &config.telegram {
+        notifiers.push(Box::new(Telegram {
+            client: client.clone(),
+            url: format!(
+                "{}/bot{}/sendMessage",
+                telegram.api_url.trim_end_matches('/'),
+                telegram.bot_token
+            ),
+            chat_id: telegram.chat_id.clone(),
+        }));
+    }
+    if let Some((program, args)) = config.command.split_first() {
+        notifiers.push(Box::new(Command {
+            program: program.clone(),
+            args: args.to_vec(),
+        }));
+    }
+    notifiers
+}
+
+async fn send_all(notifiers: &[Box<dyn Notifier>], message: &str) {
+    let sends = notifiers.iter().map(|n| async move {
+        if let Err(e) = n.send(message).await {
+            warn!(notifier = n.name(), "Failed to send notification: {}", e);
+        }
+    });
+    futures::future::join_all(sends).await;
+}
+
+// Send `message` through every notifier, for checking the config.
+pub async fn test(config: &NotifyConfig, message: &str) -> Result<(), Box<dyn std::error::Error>> {
+    let notifiers = notifiers(config);
+    if notifiers.is_empty() {
+        return Err("no notifiers configured in [notify]".into());
+    }
+
+    let mut failed = 0;
+    for notifier in &notifiers {
+        match notifier.send(message).await {
+            Ok(()) => println!("{}: sent", notifier.name()),
+            Err(e) => {
+                println!("{}: failed: {}", notifier.name(), e);
+                failed += 1;
+            }
+        }
+    }
+    if failed > 0 {
+        return Err(format!("{} of {} notifiers failed", failed, notifiers.len()).into());
+    }
+    Ok(())
+}
+
+static EVENTS: OnceLock<mpsc::UnboundedSender<Event>> = OnceLock::new();
+
+// Queue an event for the notifiers. Does nothing unless `start` was called,
+// e.g. for the one-off commands.
+pub fn notify(event: Event) {
+    if let Some(events) = EVENTS.get() {
+        let _ = events.send(event);
+    }
+}
+
+// Deliver events as they come, but at most one message per `min_interval`:
+// whatever happens in between is rolled into a digest.
+pub fn start(config: &NotifyConfig) {
+    let notifiers = notifiers(config);
+    if notifiers.is_empty() {
+        return;
+    }
+    info!(notifiers = notifiers.len(), "Sending notifications");
+
+    let (events, mut rx) = mpsc::unbounded_channel();
+    if EVENTS.set(events).is_err() {
+        return;
+    }
+    let min_interval = Duration::from_secs(config.min_interval_secs);
+    tokio::spawn(async move {
+        let mut last_sent: Option<Instant> = None;
+        while let Some(event) = rx.recv().await {
+            let mut batch = vec![event];
+            if let Some(last_sent) = last_sent {
+                let until = tokio::time::Instant::from_std(last_sent + min_interval);
+                loop {
+                    tokio::select! {
+                        event = rx.recv() => match event {
+                            Some(event) => batch.push(event),
+                            None => break,
+                        },
+                        _ = tokio::time::sleep_until(until) => break,
+                    }
+                }
+            }
+            while let Ok(event) = rx.try_recv() {
+                batch.push(event);
+            }
+
+            send_all(&notifiers, &digest(&batch)).await;
+            last_sent = Some(Instant::now());
+        }
+    });
+}
+
+fn digest(batch: &[Event]) -> String {
+    if let [event] = batch {
+        return event.to_string();
+    }
+
+    let mut kinds = BTreeMap::<&str, Vec<&Event>>::new();
+    for event in batch {
+        kinds.entry(event.kind()).or_default().push(event);
+    }
+
+    let mut message = format!("friendrekt: {} events", batch.len());
+    for (kind, events) in kinds {
+        message.push_str(&format!("\n\n{} {}:", events.len(), kind));
+        for event in events.iter().take(MAX_LISTED) {
+            message.push_str(&format!("\n- {}", event));
+        }
+        if events.len() > MAX_LISTED {
+            message.push_str(&format!("\n- and {} more", events.len() - MAX_LISTED));
+        }
+    }
+    message
+}
+
+// Whether something we depend on is up. Notifies when it goes down and when
+// it comes back, not on every failure in between.
+#[derive(Debug)]
+pub struct Dependency {
+    name: String,
+    down: AtomicBool,
+}
+
+impl Dependency {
+    pub fn new(name: impl Into<String>) -> Self {
+        Dependency {
+            name: name.into(),
+            down: AtomicBool::new(false),
+        }
+    }
+
+    pub fn up(&self) {
+        if self.down.swap(false, Ordering::SeqCst) {
+            notify(Event::Recovered {
+                dependency: self.name.clone(),
+            });
+        }
+    }
+
+    pub fn down(&self, error: impl fmt::Display) {
+        if !self.down.swap(true, Ordering::SeqCst) {
+            notify(Event::Outage {
+                dependency: self.name.clone(),
+                error: error.to_string(),
+            });
+        }
+    }
+}
+
+// Notify once when the Sniper balance drops below `threshold`, and again
+// only after it has been topped up.
+pub async fn watch_balance<M: Middleware>(
+    client: &M,
+    sniper: Address,
+    threshold: U256,
+    interval: Duration,
+) {
+    let mut low = false;
+    loop {
+        if let Ok(balance) = client.get_balance(sniper, None).await {
+            if balance < threshold && !low {
+                notify(Event::LowBalance { balance, threshold });
+            }
+            low = balance < threshold;
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn filled(n: u64) -> Event {
+        Event::Filled {
+            hash: H256::from_low_u64_be(n),
+        }
+    }
+
+    #[test]
+    fn single_event_is_sent_as_is() {
+        let event = Event::Recovered {
+            dependency: "RPC".to_string(),
+        };
+        assert_eq!(digest(&[event]), "RPC is back up");
+    }
+
+    #[test]
+    fn digest_groups_events_by_kind() {
+        let batch = [
+            filled(1),
+            Event::Outage {
+                dependency: "Kosetto".to_string(),
+                error: "timed out".to_string(),
+            },
+            filled(2),
+        ];
+        let message = digest(&batch);
+
+        assert!(message.starts_with("friendrekt: 3 events\n\n"));
+        let outages = message
+            .find("1 outages:\n- Kosetto is down: timed out")
+            .unwrap();
+        let fills = message.find("2 snipes filled:").unwrap();
+        // kinds in alphabetical order, events in the order they happened
+        assert!(outages < fills);
+        assert!(message.ends_with(&format!("\n- {}\n- {}", filled(1), filled(2))));
+    }
+
+    #[test]
+    fn digest_counts_what_it_doesnt_list() {
+        let batch = (0..MAX_LISTED as u64 + 3).map(filled).collect::<Vec<_>>();
+        let message = digest(&batch);
+
+        assert_eq!(message.matches("\n- Snipe filled").count(), MAX_LISTED);
+        assert!(message.contains(&filled(MAX_LISTED as u64 - 1).to_string()));
+        assert!(!message.contains(&filled(MAX_LISTED as u64).to_string()));
+        assert!(message.ends_with("\n- and 3 more"));
+    }
+
+    #[tokio::test]
+    async fn dependency_notifies_on_changes_only() {
+        let (events, mut rx) = mpsc::unbounded_channel();
+        EVENTS.set(events).unwrap();
+
+        let rpc = Dependency::new("RPC");
+        rpc.up();
+        rpc.down("connection refused");
+        rpc.down("connection refused again");
+        rpc.up();
+        rpc.up();
+
+        let mut sent = Vec::new();
+        while let Ok(event) = rx.try_recv() {
+            sent.push(event.to_string());
+        }
+        assert_eq!(sent, ["RPC is down: connection refused", "RPC is back up"]);
+    }
+}
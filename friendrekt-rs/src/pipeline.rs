@@ -0,0 +1,167 @@
+use crate::{metrics::METRICS, prewarm::PrewarmQueue, supervisor::TaskError};
+use ethers::prelude::*;
+use std::{collections::HashSet, future::Future, sync::Arc, time::Instant};
+use tokio::{
+    sync::{oneshot, OwnedSemaphorePermit, Semaphore},
+    task::JoinSet,
+};
+use tracing::{debug, error, warn};
+
+const BUY_SHARES: [u8; 4] = [0x69, 0x45, 0xb1, 0x23];
+const RELAY_MESSAGE: [u8; 4] = [0xd7, 0x64, 0xad, 0x0b];
+
+// What a tx is, as far as we care.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    BuyShares,
+    Relay,
+    Transfer,
+    Other,
+}
+
+impl Kind {
+    pub fn of(tx: &Transaction) -> Self {
+        if tx.input.starts_with(&BUY_SHARES) {
+            Kind::BuyShares
+        } else if tx.input.starts_with(&RELAY_MESSAGE) {
+            Kind::Relay
+        } else if tx.input.is_empty() {
+            Kind::Transfer
+        } else {
+            Kind::Other
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::BuyShares => "buy_shares",
+            Kind::Relay => "relay",
+            Kind::Transfer => "transfer",
+            Kind::Other => "other",
+        }
+    }
+}
+
+// A fixed number of workers for the txs that need lookups, shared by every
+// block. While they're all busy, e.g. on slow Kosetto lookups, the block loop
+// waits for one instead of piling up tasks, and the feed in turn waits for
+// the block loop.
+pub struct Workers {
+    permits: Arc<Semaphore>,
+}
+
+// A worker taken for one tx, given back when dropped.
+pub struct Worker {
+    _permit: OwnedSemaphorePermit,
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        METRICS.workers_busy.dec();
+    }
+}
+
+impl Workers {
+    pub fn new(workers: usize) -> Arc<Self> {
+        Arc::new(Workers {
+            permits: Arc::new(Semaphore::new(workers)),
+        })
+    }
+
+    pub async fn acquire(&self) -> Worker {
+        let started = Instant::now();
+        // never closed
+        let permit = self.permits.clone().acquire_owned().await.unwrap();
+        METRICS.worker_wait.observe(started.elapsed().as_secs_f64());
+        METRICS.workers_busy.inc();
+        Worker { _permit: permit }
+    }
+}
+
+// The txs of one block being worked on. Once they're all done the batch runs
+// the block-level steps, in block order.
+pub struct Batch {
+    number: Option<u64>,
+    header_at: Instant,
+    // each resolves to an address worth looking up ahead of time, if any
+    txs: JoinSet<Result<Option<Address>, TaskError>>,
+    prewarm: Vec<Address>,
+}
+
+impl Batch {
+    pub fn new(number: Option<u64>, header_at: Instant) -> Self {
+        Batch {
+            number,
+            header_at,
+            txs: JoinSet::new(),
+            prewarm: Vec::new(),
+        }
+    }
+
+    // Work on a tx, giving the worker back when done.
+    pub fn spawn<F>(&mut self, worker: Worker, task: F)
+    where
+        F: Future<Output = Result<Option<Address>, TaskError>> + Send + 'static,
+    {
+        self.txs.spawn(async move {
+            let _worker = worker;
+            task.await
+        });
+    }
+
+    // Queue an address for lookup once the block is done.
+    pub fn prewarm(&mut self, address: Address) {
+        self.prewarm.push(address);
+    }
+
+    // Wait for every tx, then for the block before this one to finish too,
+    // and run the block-level steps. A tx failing fatally fails the batch,
+    // after the rest have finished.
+    pub async fn finish(
+        mut self,
+        prewarm: Option<Arc<PrewarmQueue>>,
+        previous: oneshot::Receiver<()>,
+        done: oneshot::Sender<()>,
+    ) -> Result<(), TaskError> {
+        let handled = self.txs.len();
+        let mut fatal = None;
+        while let Some(result) = self.txs.join_next().await {
+            match result {
+                Ok(Ok(Some(address))) => self.prewarm.push(address),
+                Ok(Ok(None)) => {}
+                Ok(Err(TaskError::Fatal(e))) => fatal = Some(TaskError::Fatal(e)),
+                Ok(Err(TaskError::Retry(e))) => warn!(task = "tx", "Task failed: {}", e),
+                Err(e) => error!(task = "tx", "Task panicked: {}", e),
+            }
+        }
+
+        METRICS
+            .block_seconds
+            .observe(self.header_at.elapsed().as_secs_f64());
+        // the previous block's sender is dropped once it's done, either way
+        let _ = previous.await;
+
+        let mut queued = 0;
+        if let Some(prewarm) = &prewarm {
+            let addresses = self.prewarm.drain(..).collect::<HashSet<_>>();
+            for address in addresses {
+                if prewarm.push(address).await {
+                    queued += 1;
+                }
+            }
+        }
+        debug!(
+            number = self.number,
+            handled,
+            prewarm_queued = queued,
+            elapsed_ms = self.header_at.elapsed().as_millis() as u64,
+            "Processed block"
+        );
+
+        drop(done);
+        match fatal {
+            Some(fatal) => Err(fatal),
+            None => Ok(()),
+        }
+    }
+}
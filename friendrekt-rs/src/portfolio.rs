@@ -0,0 +1,176 @@
+use crate::{
+    bindings::shares::shares::shares,
+    cli::Client,
+    indexer::{self, Trade},
+    math,
+};
+use ethers::prelude::*;
+use std::{collections::BTreeMap, fs::File, io::Write, path::Path};
+
+// One subject the Sniper contract still holds shares of.
+pub struct Position {
+    pub subject: Address,
+    pub held: U256,
+    pub supply: U256,
+    // share of what we paid for our buys (fees included) still held
+    pub cost_basis: U256,
+    // what selling all held shares would pay right now, after fees
+    pub value: U256,
+}
+
+impl Position {
+    pub fn pnl(&self) -> f64 {
+        math::wei_to_eth(self.value) - math::wei_to_eth(self.cost_basis)
+    }
+}
+
+pub struct Portfolio {
+    pub positions: Vec<Position>,
+    pub eth_balance: U256,
+}
+
+// Finds the Sniper contract's trades by scanning friend.tech Trade events.
+// The trader isn't indexed, so we walk every Trade log from start_block and
+// keep ours; later scans resume where the previous one stopped.
+pub struct TradeScanner {
+    ft: Address,
+    trader: Address,
+    next_block: u64,
+    chunk: u64,
+    pub trades: Vec<Trade>,
+}
+
+impl TradeScanner {
+    pub fn new(ft: Address, trader: Address, start_block: u64, chunk: u64) -> Self {
+        TradeScanner {
+            ft,
+            trader,
+            next_block: start_block,
+            chunk: chunk.max(1),
+            trades: Vec::new(),
+        }
+    }
+
+    // Scan up to the current head, returning how many new trades we found.
+    pub async fn scan<M: Middleware>(&mut self, client: &M) -> Result<usize, M::Error> {
+        let head = client.get_block_number().await?.as_u64();
+        let before = self.trades.len();
+        while self.next_block <= head {
+            let to = (self.next_block + self.chunk - 1).min(head);
+            let trades = indexer::fetch_trades(client, self.ft, self.next_block, to).await?;
+            self.trades
+                .extend(trades.into_iter().filter(|t| t.trader == self.trader));
+            self.next_block = to + 1;
+        }
+
+        Ok(self.trades.len() - before)
+    }
+}
+
+impl Portfolio {
+    pub async fn load(
+        ft: &shares<Client>,
+        sniper: Address,
+        trades: &[Trade],
+    ) -> Result<Self, Box<dyn std::error::Error>> {
+        // shares bought and what we paid for them, per subject
+        let mut bought = BTreeMap::<Address, (U256, U256)>::new();
+        for trade in trades.iter().filter(|t| t.is_buy) {
+            let entry = bought.entry(trade.subject).or_default();
+            entry.0 += trade.share_amount;
+            entry.1 += trade.net_eth();
+        }
+
+        let mut positions = Vec::new();
+        for (subject, (shares_bought, paid)) in bought {
+            let held = ft.shares_balance(subject, sniper).call().await?;
+            if held.is_zero() {
+                continue;
+            }
+
+            let supply = ft.shares_supply(subject).call().await?;
+            // the last share can never be sold
+            let sellable = held.min(supply.saturating_sub(U256::one()));
+            let value = math::get_sell_price_after_fee(supply - sellable, sellable);
+            let cost_basis = if shares_bought.is_zero() {
+                U256::zero()
+            } else {
+                paid * held.min(shares_bought) / shares_bought
+            };
+
+            positions.push(Position {
+                subject,
+                held,
+                supply,
+                cost_basis,
+                value,
+            });
+        }
+
+        let eth_balance = ft.client().get_balance(sniper, None).await?;
+        Ok(Portfolio {
+            positions,
+            eth_balance,
+        })
+    }
+
+    pub fn cost_basis(&self) -> U256 {
+        self.positions
+            .iter()
+            .fold(U256::zero(), |acc, p| acc + p.cost_basis)
+    }
+
+    pub fn value(&self) -> U256 {
+        self.positions
+            .iter()
+            .fold(U256::zero(), |acc, p| acc + p.value)
+    }
+
+    pub fn print(&self) {
+        println!(
+            "{:<42} {:>6} {:>6} {:>12} {:>12} {:>12}",
+            "subject", "held", "supply", "cost (ETH)", "value (ETH)", "pnl (ETH)"
+        );
+        for p in &self.positions {
+            println!(
+                "{:<42} {:>6} {:>6} {:>12.6} {:>12.6} {:>12.6}",
+                format!("{:?}", p.subject),
+                p.held,
+                p.supply,
+                math::wei_to_eth(p.cost_basis),
+                math::wei_to_eth(p.value),
+                p.pnl()
+            );
+        }
+
+        let cost = math::wei_to_eth(self.cost_basis());
+        let value = math::wei_to_eth(self.value());
+        let balance = math::wei_to_eth(self.eth_balance);
+        println!("-------------------");
+        println!("Positions: {}", self.positions.len());
+        println!("Cost basis: {:.6} ETH", cost);
+        println!("Value: {:.6} ETH", value);
+        println!("Unrealised PnL: {:.6} ETH", value - cost);
+        println!("Sniper balance: {:.6} ETH", balance);
+        println!("Total: {:.6} ETH", balance + value);
+    }
+
+    pub fn write_csv(&self, path: &Path) -> std::io::Result<()> {
+        let mut file = File::create(path)?;
+        writeln!(file, "subject,held,supply,cost_basis_eth,value_eth,pnl_eth")?;
+        for p in &self.positions {
+            writeln!(
+                file,
+                "{:?},{},{},{},{},{}",
+                p.subject,
+                p.held,
+                p.supply,
+                math::wei_to_eth(p.cost_basis),
+                math::wei_to_eth(p.value),
+                p.pnl()
+            )?;
+        }
+
+        Ok(())
+    }
+}
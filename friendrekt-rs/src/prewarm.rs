@@ -0,0 +1,134 @@
+use crate::prod_kosetto::{Kosetto, Lookup, Profiles};
+use ethers::types::Address;
+use std::{collections::HashSet, sync::Arc, time::Duration};
+use tokio::sync::{mpsc, Mutex};
+
+// Looks up addresses in the background so their Twitter info is already
+// cached by the time they show up in a buyShares tx. The queue is bounded
+// and served by a fixed number of workers; anything pushed while the queue
+// is full is dropped rather than waited on.
+pub struct PrewarmQueue {
+    sender: mpsc::Sender<Address>,
+    // queued or currently being looked up
+    pending: Mutex<HashSet<Address>>,
+    cache: Arc<Profiles>,
+    kosetto: Arc<Kosetto>,
+    // how long to remember that an address has no account
+    negative_ttl: Duration,
+}
+
+impl PrewarmQueue {
+    pub fn spawn(
+        cache: Arc<Profiles>,
+        kosetto: Arc<Kosetto>,
+        workers: usize,
+        capacity: usize,
+        negative_ttl: Duration,
+    ) -> Arc<Self> {
+        let (sender, receiver) = mpsc::channel(capacity.max(1));
+        let queue = Arc::new(PrewarmQueue {
+            sender,
+            pending: Mutex::new(HashSet::new()),
+            cache,
+            kosetto,
+            negative_ttl,
+        });
+
+        let receiver = Arc::new(Mutex::new(receiver));
+        for _ in 0..workers.max(1) {
+            let queue = queue.clone();
+            let receiver = receiver.clone();
+            tokio::spawn(async move {
+                loop {
+                    let address = match receiver.lock().await.recv().await {
+                        Some(address) => address,
+                        None => return,
+                    };
+                    queue.resolve(address).await;
+                }
+            });
+        }
+
+        queue
+    }
+
+    // Queue an address for lookup. Returns false if it was skipped because
+    // it's already known, with or without an account, already queued, or
+    // the queue is full.
+    pub async fn push(&self, address: Address) -> bool {
+        if self.cache.contains(&address) {
+            return false;
+        }
+
+        let mut pending = self.pending.lock().await;
+        if !pending.insert(address) {
+            return false;
+        }
+
+        if self.sender.try_send(address).is_err() {
+            pending.remove(&address);
+            return false;
+        }
+
+        true
+    }
+
+    async fn resolve(&self, address: Address) {
+        // only profiles with their follower count make it into the cache
+        match self.kosetto.lookup_into(&self.cache, address).await {
+            Lookup::Found(_) => {}
+            Lookup::NotRegistered => {
+                self.cache.insert_with_ttl(address, None, self.negative_ttl);
+            }
+            // resolved on the watchlist, later txs find it there
+            Lookup::Watched(_) => {}
+            // transient error, let a later transfer queue it again
+            Lookup::Failed => {}
+        }
+
+        self.pending.lock().await.remove(&address);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::prod_kosetto::tests::kosetto;
+
+    async fn prewarmed(followers: Option<u64>, address: Address) -> Arc<Profiles> {
+        let cache = Arc::new(Profiles::new("test_prewarm", 16, None));
+        let queue = PrewarmQueue::spawn(
+            cache.clone(),
+            Arc::new(kosetto(followers)),
+            1,
+            4,
+            Duration::from_secs(60),
+        );
+        assert!(queue.push(address).await);
+        // done once it's no longer pending
+        while queue.pending.lock().await.contains(&address) {
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        cache
+    }
+
+    #[tokio::test]
+    async fn caches_profiles_with_followers() {
+        let address = Address::repeat_byte(1);
+        let cache = prewarmed(Some(1234), address).await;
+        assert_eq!(cache.get(&address).flatten().unwrap().followers, 1234);
+    }
+
+    #[tokio::test]
+    async fn sidecar_failure_isnt_cached() {
+        let address = Address::repeat_byte(1);
+        let cache = prewarmed(None, address).await;
+        assert!(!cache.contains(&address));
+    }
+
+    #[tokio::test]
+    async fn caches_addresses_without_an_account() {
+        let cache = prewarmed(Some(1234), Address::zero()).await;
+        assert!(matches!(cache.get(&Address::zero()), Some(None)));
+    }
+}
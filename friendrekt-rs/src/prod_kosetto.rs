@@ -1,7 +1,16 @@
-use serde::Deserialize;
+use crate::{
+    bset::Cache, config::Config, metrics::METRICS, notify::Dependency, watchlist::Watchlist,
+};
+use ethers::types::Address;
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tracing::{debug, warn};
 
 // Kosetto user
-#[allow(non_snake_case)]
+#[allow(non_snake_case, dead_code)]
 #[derive(Deserialize)]
 pub struct User {
     pub address: String,
@@ -10,16 +19,276 @@ pub struct User {
 }
 
 // Kosetto response
+#[allow(dead_code)]
 #[derive(Deserialize)]
 pub struct ApiResponse {
     pub users: Vec<User>,
 }
 
 // Hold some info about a Twitter user
-#[derive(Clone, Debug)]
+#[derive(Clone, Debug, Serialize)]
 pub struct TwitterInfo {
     pub twitter_username: String,
     pub twitter_user_id: String,
     pub followers: u64,
-    pub supply_limit: u64,
+}
+
+// Twitter info by address, shared by every tx task and the prewarm workers.
+// None caches an address Kosetto says has no account.
+pub type Profiles = Cache<Address, Option<TwitterInfo>>;
+
+// Outcome of a Kosetto lookup. A failed request is not the same as an
+// address that has no friend.tech account, so we keep them apart. Failed
+// also covers the follower lookup failing, a profile without its follower
+// count would be filtered as if it had none.
+pub enum Lookup {
+    Found(TwitterInfo),
+    // on the watchlist, which knows the address from now on. Followers
+    // aren't looked up, so it's not worth caching.
+    Watched(TwitterInfo),
+    NotRegistered,
+    Failed,
+}
+
+// Looks up friend.tech users on Kosetto and their followers on the sidecar.
+pub struct Kosetto {
+    client: reqwest::Client,
+    kosetto_url: String,
+    followers_url: String,
+    timeout: Duration,
+    watchlist: Arc<Watchlist>,
+    kosetto_health: Dependency,
+    followers_health: Dependency,
+}
+
+impl Kosetto {
+    pub fn new(config: &Config, watchlist: Arc<Watchlist>) -> Self {
+        Kosetto {
+            client: reqwest::Client::new(),
+            kosetto_url: config.lookup.kosetto_url.trim_end_matches('/').to_string(),
+            followers_url: config
+                .lookup
+                .followers_url
+                .trim_end_matches('/')
+                .to_string(),
+            timeout: Duration::from_secs(config.lookup.timeout_secs),
+            watchlist,
+            kosetto_health: Dependency::new("Kosetto"),
+            followers_health: Dependency::new("Follower sidecar"),
+        }
+    }
+
+    pub async fn get_followers(&self, id: String) -> Option<u64> {
+        let started = Instant::now();
+        let followers = self.fetch_followers(&id).await;
+        let result = match followers {
+            Some(_) => {
+                self.followers_health.up();
+                "ok"
+            }
+            None => {
+                self.followers_health.down("follower lookup failed");
+                "failed"
+            }
+        };
+        METRICS
+            .lookups
+            .with_label_values(&["followers", result])
+            .observe(started.elapsed().as_secs_f64());
+
+        if followers.is_none() {
+            warn!(twitter_user_id = id, "Failed to get followers");
+        }
+        followers
+    }
+
+    async fn fetch_followers(&self, id: &str) -> Option<u64> {
+        let req_url = format!("{}/{}", self.followers_url, id);
+        let resp = self.client.get(req_url).timeout(self.timeout).send().await;
+        match resp {
+            Ok(resp) if resp.status().is_success() => resp.text().await.ok()?.parse().ok(),
+            _ => None,
+        }
+    }
+
+    pub async fn lookup(&self, address: Address) -> Lookup {
+        let started = Instant::now();
+        let lookup = self.fetch_user(address).await;
+        let result = match &lookup {
+            Lookup::Found(_) => "found",
+            Lookup::Watched(_) => "watched",
+            Lookup::NotRegistered => "not_registered",
+            Lookup::Failed => "failed",
+        };
+        // includes the follower lookup, which is also timed on its own
+        METRICS
+            .lookups
+            .with_label_values(&["kosetto", result])
+            .observe(started.elapsed().as_secs_f64());
+        lookup
+    }
+
+    // Look `address` up and cache the profile if one was found. Watched
+    // profiles, addresses without an account and failures aren't cached.
+    pub async fn lookup_into(&self, cache: &Profiles, address: Address) -> Lookup {
+        let lookup = self.lookup(address).await;
+        if let Lookup::Found(info) = &lookup {
+            debug!(
+                ?address,
+                twitter_user_id = %info.twitter_user_id,
+                followers = info.followers,
+                "Cached Twitter info"
+            );
+            cache.insert(address, Some(info.clone()));
+        }
+        lookup
+    }
+
+    // Kosetto's health is its own, a failing sidecar only marks the sidecar
+    // as down.
+    async fn fetch_user(&self, address: Address) -> Lookup {
+        let req_url = format!("{}/users/{:?}", self.kosetto_url, address);
+
+        let resp = match self.client.get(req_url).timeout(self.timeout).send().await {
+            Ok(resp) => resp,
+            Err(_) => {
+                self.kosetto_health.down("user lookup failed");
+                return Lookup::Failed;
+            }
+        };
+
+        if resp.status() == reqwest::StatusCode::NOT_FOUND {
+            self.kosetto_health.up();
+            return Lookup::NotRegistered;
+        }
+
+        let response = match resp.error_for_status() {
+            Ok(resp) => resp.json::<User>().await,
+            Err(e) => Err(e),
+        };
+        let response = match response {
+            Ok(response) => response,
+            Err(_) => {
+                self.kosetto_health.down("user lookup failed");
+                return Lookup::Failed;
+            }
+        };
+        self.kosetto_health.up();
+
+        // watched subjects skip the follower lookup, their limits come from
+        // the watchlist entry
+        if self
+            .watchlist
+            .observe(address, &response.twitterUsername, &response.twitterUserId)
+            .await
+            .is_some()
+        {
+            return Lookup::Watched(TwitterInfo {
+                twitter_username: response.twitterUsername,
+                twitter_user_id: response.twitterUserId,
+                followers: 0,
+            });
+        }
+
+        let followers = match self.get_followers(response.twitterUserId.clone()).await {
+            Some(followers) => followers,
+            None => return Lookup::Failed,
+        };
+        Lookup::Found(TwitterInfo {
+            twitter_username: response.twitterUsername,
+            twitter_user_id: response.twitterUserId,
+            followers,
+        })
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+    use hyper::{
+        service::{make_service_fn, service_fn},
+        Body, Response, Server, StatusCode,
+    };
+    use std::{convert::Infallible, net::SocketAddr};
+
+    // Kosetto and the follower sidecar on one local server. Kosetto knows
+    // every address but the zero address, the sidecar answers with
+    // `followers` or fails if there are None.
+    pub fn kosetto(followers: Option<u64>) -> Kosetto {
+        let make = make_service_fn(move |_| async move {
+            Ok::<_, Infallible>(service_fn(move |req: hyper::Request<Body>| async move {
+                let path = req.uri().path();
+                let response = if path == format!("/users/{:?}", Address::zero()) {
+                    Response::builder()
+                        .status(StatusCode::NOT_FOUND)
+                        .body(Body::empty())
+                } else if path.starts_with("/users/") {
+                    Response::builder().body(Body::from(
+                        r#"{"address":"0x01","twitterUsername":"alice","twitterUserId":"42"}"#,
+                    ))
+                } else {
+                    match followers {
+                        Some(followers) => Response::builder().body(followers.to_string().into()),
+                        None => Response::builder()
+                            .status(StatusCode::INTERNAL_SERVER_ERROR)
+                            .body(Body::empty()),
+                    }
+                };
+                Ok::<_, Infallible>(response.unwrap())
+            }))
+        });
+        let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(make);
+        let url = format!("http://{}", server.local_addr());
+        tokio::spawn(server);
+
+        let mut config = Config::default();
+        config.lookup.kosetto_url = url.clone();
+        config.lookup.followers_url = format!("{}/followers", url);
+        config.lookup.timeout_secs = 5;
+        Kosetto::new(&config, Watchlist::empty())
+    }
+
+    fn profiles() -> Profiles {
+        Profiles::new("test_profiles", 16, None)
+    }
+
+    #[tokio::test]
+    async fn found_profiles_are_cached() {
+        let kosetto = kosetto(Some(1234));
+        let cache = profiles();
+        let address = Address::repeat_byte(1);
+
+        match kosetto.lookup_into(&cache, address).await {
+            Lookup::Found(info) => assert_eq!(info.followers, 1234),
+            _ => panic!("expected a profile"),
+        }
+        let cached = cache.get(&address).flatten().unwrap();
+        assert_eq!(cached.twitter_username, "alice");
+        assert_eq!(cached.followers, 1234);
+    }
+
+    #[tokio::test]
+    async fn sidecar_failure_fails_the_lookup_and_caches_nothing() {
+        let kosetto = kosetto(None);
+        let cache = profiles();
+        let address = Address::repeat_byte(1);
+
+        assert!(matches!(
+            kosetto.lookup_into(&cache, address).await,
+            Lookup::Failed
+        ));
+        assert!(!cache.contains(&address));
+    }
+
+    #[tokio::test]
+    async fn unregistered_addresses_arent_cached_here() {
+        let kosetto = kosetto(Some(1234));
+        let cache = profiles();
+
+        assert!(matches!(
+            kosetto.lookup_into(&cache, Address::zero()).await,
+            Lookup::NotRegistered
+        ));
+        assert!(!cache.contains(&Address::zero()));
+    }
 }
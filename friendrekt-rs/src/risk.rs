@@ -0,0 +1,590 @@
+use crate::{
+    bindings::shares::shares::shares,
+    cli::Client,
+    config::{ConfigError, RiskConfig, RiskLimits},
+    math,
+};
+use ethers::{prelude::*, utils::parse_ether};
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex, MutexGuard,
+    },
+    time::{Duration, Instant},
+};
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+
+const HOUR: Duration = Duration::from_secs(60 * 60);
+const DAY: Duration = Duration::from_secs(24 * 60 * 60);
+
+// Why a snipe was refused.
+pub enum RiskRejection {
+    KillSwitch,
+    SubjectLimit(U256),
+    HourlyLimit(U256),
+    DailyLimit(U256),
+    MaxPositions(usize),
+    GasLimit(U256),
+    Reserve(U256),
+}
+
+impl fmt::Display for RiskRejection {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let eth = |wei: &U256| math::wei_to_eth(*wei);
+        match self {
+            RiskRejection::KillSwitch => write!(f, "kill switch is engaged"),
+            RiskRejection::SubjectLimit(spent) => {
+                write!(f, "already spent {:.6} ETH on this subject", eth(spent))
+            }
+            RiskRejection::HourlyLimit(spent) => {
+                write!(f, "already spent {:.6} ETH in the last hour", eth(spent))
+            }
+            RiskRejection::DailyLimit(spent) => {
+                write!(f, "already spent {:.6} ETH in the last day", eth(spent))
+            }
+            RiskRejection::MaxPositions(n) => write!(f, "already holding {} subjects", n),
+            RiskRejection::GasLimit(spent) => {
+                write!(f, "already spent {:.6} ETH on gas this window", eth(spent))
+            }
+            RiskRejection::Reserve(balance) => write!(
+                f,
+                "Sniper balance of {:.6} ETH would drop below the reserve",
+                eth(balance)
+            ),
+        }
+    }
+}
+
+// Limits in wei, None when not set.
+#[derive(Clone)]
+struct Limits {
+    per_subject: Option<U256>,
+    per_hour: Option<U256>,
+    per_day: Option<U256>,
+    open_positions: Option<usize>,
+    gas: Option<U256>,
+    reserve: U256,
+}
+
+impl Limits {
+    // validated on load, or by set_limits
+    fn new(limits: &RiskLimits) -> Self {
+        let wei = |eth: Option<f64>| eth.map(|eth| parse_ether(eth).unwrap());
+        Limits {
+            per_subject: wei(limits.max_eth_per_subject),
+            per_hour: wei(limits.max_eth_per_hour),
+            per_day: wei(limits.max_eth_per_day),
+            open_positions: limits.max_open_positions,
+            gas: wei(limits.max_gas_eth),
+            reserve: wei(Some(limits.min_balance_eth)).unwrap(),
+        }
+    }
+}
+
+// The most one snipe could spend.
+#[derive(Clone, Copy, PartialEq)]
+struct Spend {
+    at: Instant,
+    subject: Address,
+    cost: U256,
+}
+
+#[derive(Default)]
+struct State {
+    // what each snipe could spend at most, for the last day
+    spends: VecDeque<Spend>,
+    gas: VecDeque<(Instant, U256)>,
+    // subjects the Sniper holds shares of, with when we sniped them or None
+    // if it held them before
+    positions: HashMap<Address, Option<Instant>>,
+    // last known Sniper balance, less what we've sniped since
+    balance: Option<U256>,
+    // when the last refresh started reading the chain
+    refreshed: Option<Instant>,
+}
+
+impl State {
+    // Forget spends that have left their window.
+    fn prune(&mut self, now: Instant, gas_window: Duration) {
+        let old = |at: Instant, window| now.saturating_duration_since(at) >= window;
+        while matches!(self.spends.front(), Some(spend) if old(spend.at, DAY)) {
+            self.spends.pop_front();
+        }
+        while matches!(self.gas.front(), Some((at, _)) if old(*at, gas_window)) {
+            self.gas.pop_front();
+        }
+    }
+
+    fn spent(&self, since: Duration, subject: Option<Address>) -> U256 {
+        self.spends
+            .iter()
+            .filter(|spend| spend.at.elapsed() < since)
+            .filter(|spend| subject.is_none_or(|subject| spend.subject == subject))
+            .fold(U256::zero(), |acc, spend| acc + spend.cost)
+    }
+
+    // Whether a refresh reading the chain now may not see what was sniped
+    // at `at` yet: anything since the previous refresh could still be in
+    // flight.
+    fn in_flight(&self, at: Instant) -> bool {
+        self.refreshed.is_none_or(|refreshed| at >= refreshed)
+    }
+}
+
+// Caps on what the sniping loop may spend, plus a kill switch that stops new
+// snipes. Only snipes go through here, selling and everything else carries on.
+pub struct RiskManager {
+    // as configured (in ETH) and in wei, both replaced by set_limits
+    limits: RwLock<(RiskLimits, Limits)>,
+    gas_window: Duration,
+    kill_file: PathBuf,
+    killed: AtomicBool,
+    state: Mutex<State>,
+}
+
+// What reserve counted for one snipe. Handed back when dropped, unless the
+// snipe was sent and it's committed.
+#[must_use]
+pub struct Reservation {
+    risk: Arc<RiskManager>,
+    subject: Address,
+    cost: U256,
+    gas: U256,
+    at: Instant,
+    // the subject wasn't an open position before
+    opened: bool,
+    committed: bool,
+}
+
+impl Reservation {
+    // The snipe went out, keep it counted.
+    pub fn commit(mut self) {
+        self.committed = true;
+    }
+}
+
+impl Drop for Reservation {
+    fn drop(&mut self) {
+        if !self.committed {
+            self.risk.release(self);
+        }
+    }
+}
+
+fn sum(window: &VecDeque<(Instant, U256)>, since: Duration) -> U256 {
+    window
+        .iter()
+        .filter(|(at, _)| at.elapsed() < since)
+        .fold(U256::zero(), |acc, (_, wei)| acc + wei)
+}
+
+impl RiskManager {
+    pub fn new(config: &RiskConfig) -> Arc<Self> {
+        let limits = config.limits();
+        Arc::new(RiskManager {
+            limits: RwLock::new((limits.clone(), Limits::new(&limits))),
+            gas_window: Duration::from_secs(config.gas_window_secs),
+            kill_file: config.kill_file.clone(),
+            killed: AtomicBool::new(false),
+            state: Mutex::new(State::default()),
+        })
+    }
+
+    pub async fn limits(&self) -> RiskLimits {
+        self.limits.read().await.0.clone()
+    }
+
+    // Replace the limits, what was spent so far still counts against them.
+    pub async fn set_limits(&self, limits: RiskLimits) -> Result<(), ConfigError> {
+        limits.validate()?;
+        info!(?limits, "Risk limits changed");
+        *self.limits.write().await = (limits.clone(), Limits::new(&limits));
+        Ok(())
+    }
+
+    pub fn kill(&self) {
+        if !self.killed.swap(true, Ordering::SeqCst) {
+            warn!("Kill switch engaged, no new snipes");
+        }
+    }
+
+    pub fn resume(&self) {
+        if self.killed.swap(false, Ordering::SeqCst) {
+            info!("Kill switch released");
+        }
+    }
+
+    pub fn is_killed(&self) -> bool {
+        self.killed.load(Ordering::SeqCst) || self.kill_file.exists()
+    }
+
+    // Engage the kill switch on SIGUSR1 and release it on SIGUSR2.
+    #[cfg(unix)]
+    pub fn listen_for_signals(self: &Arc<Self>) -> std::io::Result<()> {
+        use tokio::signal::unix::{signal, SignalKind};
+
+        let mut kill = signal(SignalKind::user_defined1())?;
+        let mut resume = signal(SignalKind::user_defined2())?;
+        let risk = self.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = kill.recv() => risk.kill(),
+                    _ = resume.recv() => risk.resume(),
+                }
+            }
+        });
+
+        Ok(())
+    }
+
+    // Seed the open positions from the ones the Sniper already holds.
+    pub async fn add_positions(&self, subjects: Vec<Address>) {
+        let mut state = self.state();
+        for subject in subjects {
+            state.positions.entry(subject).or_insert(None);
+        }
+        info!(positions = state.positions.len(), "Tracking open positions");
+    }
+
+    // Refresh the `sniper` balance and drop positions that have been sold,
+    // every `interval`.
+    pub fn watch(self: &Arc<Self>, ft: shares<Client>, sniper: Address, interval: Duration) {
+        let risk = self.clone();
+        tokio::spawn(async move {
+            loop {
+                risk.refresh(&ft, sniper).await;
+                tokio::time::sleep(interval).await;
+            }
+        });
+    }
+
+    async fn refresh(&self, ft: &shares<Client>, sniper: Address) {
+        let started = Instant::now();
+        let balance = match ft.client().get_balance(sniper, None).await {
+            Ok(balance) => Some(balance),
+            Err(e) => {
+                warn!("Failed to get Sniper balance: {}", e);
+                None
+            }
+        };
+
+        let positions = self.state().positions.keys().copied().collect::<Vec<_>>();
+        let mut sold = Vec::new();
+        for subject in positions {
+            match ft.shares_balance(subject, sniper).call().await {
+                Ok(held) if held.is_zero() => sold.push(subject),
+                Ok(_) => {}
+                Err(e) => warn!(?subject, "Failed to get share balance: {}", e),
+            }
+        }
+        self.refreshed(started, balance, &sold);
+    }
+
+    // Take in what a refresh that started reading the chain at `started`
+    // found. Snipes still in flight then aren't in what it read, so their
+    // positions are kept and their cost still comes off the balance.
+    fn refreshed(&self, started: Instant, balance: Option<U256>, sold: &[Address]) {
+        let mut state = self.state();
+        if let Some(balance) = balance {
+            let in_flight = state
+                .spends
+                .iter()
+                .filter(|spend| state.in_flight(spend.at))
+                .fold(U256::zero(), |acc, spend| acc + spend.cost);
+            state.balance = Some(balance.saturating_sub(in_flight));
+        }
+        for subject in sold {
+            match state.positions.get(subject) {
+                Some(Some(at)) if state.in_flight(*at) => {}
+                _ => {
+                    state.positions.remove(subject);
+                }
+            }
+        }
+        state.refreshed = Some(started);
+    }
+
+    fn state(&self) -> MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    // Check a snipe that could spend up to `cost` plus `gas` against the
+    // limits and, if it fits, count it straight away so concurrent snipes
+    // can't both squeeze under a limit. It stops counting once the
+    // reservation is dropped without being committed.
+    pub async fn reserve(
+        self: &Arc<Self>,
+        subject: Address,
+        cost: U256,
+        gas: U256,
+    ) -> Result<Reservation, RiskRejection> {
+        if self.is_killed() {
+            return Err(RiskRejection::KillSwitch);
+        }
+
+        let limits = self.limits.read().await.1.clone();
+        let gas_window = self.gas_window;
+        let mut state = self.state();
+        state.prune(Instant::now(), gas_window);
+
+        let spent = state.spent(DAY, Some(subject));
+        if matches!(limits.per_subject, Some(max) if spent + cost > max) {
+            return Err(RiskRejection::SubjectLimit(spent));
+        }
+
+        let spent = state.spent(HOUR, None);
+        if matches!(limits.per_hour, Some(max) if spent + cost > max) {
+            return Err(RiskRejection::HourlyLimit(spent));
+        }
+
+        let spent = state.spent(DAY, None);
+        if matches!(limits.per_day, Some(max) if spent + cost > max) {
+            return Err(RiskRejection::DailyLimit(spent));
+        }
+
+        let open = state.positions.len();
+        if !state.positions.contains_key(&subject)
+            && matches!(limits.open_positions, Some(max) if open >= max)
+        {
+            return Err(RiskRejection::MaxPositions(open));
+        }
+
+        let spent = sum(&state.gas, gas_window);
+        if matches!(limits.gas, Some(max) if spent + gas > max) {
+            return Err(RiskRejection::GasLimit(spent));
+        }
+
+        if let Some(balance) = state.balance {
+            if balance < cost + limits.reserve {
+                return Err(RiskRejection::Reserve(balance));
+            }
+            state.balance = Some(balance - cost);
+        }
+
+        let now = Instant::now();
+        state.spends.push_back(Spend {
+            at: now,
+            subject,
+            cost,
+        });
+        state.gas.push_back((now, gas));
+        let opened = !state.positions.contains_key(&subject);
+        if opened {
+            state.positions.insert(subject, Some(now));
+        }
+        Ok(Reservation {
+            risk: self.clone(),
+            subject,
+            cost,
+            gas,
+            at: now,
+            opened,
+            committed: false,
+        })
+    }
+
+    // Take back what `reservation` counted.
+    fn release(&self, reservation: &Reservation) {
+        let mut state = self.state();
+        let spend = Spend {
+            at: reservation.at,
+            subject: reservation.subject,
+            cost: reservation.cost,
+        };
+        if let Some(i) = state.spends.iter().position(|s| *s == spend) {
+            state.spends.remove(i);
+        }
+        let gas = (reservation.at, reservation.gas);
+        if let Some(i) = state.gas.iter().position(|g| *g == gas) {
+            state.gas.remove(i);
+        }
+        if reservation.opened {
+            state.positions.remove(&reservation.subject);
+        }
+        if let Some(balance) = &mut state.balance {
+            *balance += reservation.cost;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eth(milli: u64) -> U256 {
+        U256::exp10(15) * milli
+    }
+
+    fn risk(limits: impl FnOnce(&mut RiskConfig)) -> Arc<RiskManager> {
+        let mut config = RiskConfig {
+            kill_file: std::env::temp_dir().join("friendrekt-test-no-kill-file"),
+            ..Default::default()
+        };
+        limits(&mut config);
+        RiskManager::new(&config)
+    }
+
+    fn subject(n: u8) -> Address {
+        Address::repeat_byte(n)
+    }
+
+    #[tokio::test]
+    async fn dropped_reservations_are_given_back() {
+        let risk = risk(|c| c.max_eth_per_hour = Some(1.0));
+        let reservation = risk.reserve(subject(1), eth(600), eth(1)).await.ok();
+        assert!(reservation.is_some());
+        assert!(risk.reserve(subject(2), eth(600), eth(1)).await.is_err());
+
+        drop(reservation);
+        assert!(risk.reserve(subject(2), eth(600), eth(1)).await.is_ok());
+        assert!(risk.state().spends.is_empty());
+        assert!(risk.state().positions.is_empty());
+    }
+
+    #[tokio::test]
+    async fn committed_reservations_keep_counting() {
+        let risk = risk(|c| c.max_eth_per_hour = Some(1.0));
+        risk.reserve(subject(1), eth(600), eth(1))
+            .await
+            .ok()
+            .unwrap()
+            .commit();
+
+        let rejection = risk.reserve(subject(2), eth(600), eth(1)).await.err();
+        assert!(matches!(rejection, Some(RiskRejection::HourlyLimit(spent)) if spent == eth(600)));
+        assert!(risk.state().positions.contains_key(&subject(1)));
+    }
+
+    #[tokio::test]
+    async fn subject_limit_only_counts_that_subject() {
+        let risk = risk(|c| c.max_eth_per_subject = Some(1.0));
+        risk.reserve(subject(1), eth(600), eth(1))
+            .await
+            .ok()
+            .unwrap()
+            .commit();
+
+        let rejection = risk.reserve(subject(1), eth(600), eth(1)).await.err();
+        assert!(matches!(rejection, Some(RiskRejection::SubjectLimit(spent)) if spent == eth(600)));
+        assert!(risk.reserve(subject(2), eth(600), eth(1)).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn subject_spends_roll_out_of_the_window() {
+        let risk = risk(|c| c.max_eth_per_subject = Some(1.0));
+        risk.reserve(subject(1), eth(600), eth(1))
+            .await
+            .ok()
+            .unwrap()
+            .commit();
+
+        risk.state().prune(Instant::now() + DAY, HOUR);
+        assert!(risk.state().spends.is_empty());
+        assert!(risk.state().gas.is_empty());
+        assert_eq!(risk.state().spent(DAY, Some(subject(1))), U256::zero());
+    }
+
+    #[tokio::test]
+    async fn daily_and_gas_limits() {
+        let risk = risk(|c| {
+            c.max_eth_per_day = Some(1.0);
+            c.max_gas_eth = Some(0.01);
+        });
+        risk.reserve(subject(1), eth(600), eth(6))
+            .await
+            .ok()
+            .unwrap()
+            .commit();
+
+        let rejection = risk.reserve(subject(2), eth(600), eth(1)).await.err();
+        assert!(matches!(rejection, Some(RiskRejection::DailyLimit(_))));
+        let rejection = risk.reserve(subject(2), eth(100), eth(6)).await.err();
+        assert!(matches!(rejection, Some(RiskRejection::GasLimit(spent)) if spent == eth(6)));
+    }
+
+    #[tokio::test]
+    async fn max_open_positions_lets_held_subjects_through() {
+        let risk = risk(|c| c.max_open_positions = Some(1));
+        risk.reserve(subject(1), eth(100), eth(1))
+            .await
+            .ok()
+            .unwrap()
+            .commit();
+
+        let rejection = risk.reserve(subject(2), eth(100), eth(1)).await.err();
+        assert!(matches!(rejection, Some(RiskRejection::MaxPositions(1))));
+        assert!(risk.reserve(subject(1), eth(100), eth(1)).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn reserve_keeps_the_balance_above_the_minimum() {
+        let risk = risk(|c| c.min_balance_eth = 0.5);
+        risk.refreshed(Instant::now(), Some(eth(1000)), &[]);
+
+        let reservation = risk.reserve(subject(1), eth(400), eth(1)).await.ok();
+        assert_eq!(risk.state().balance, Some(eth(600)));
+        let rejection = risk.reserve(subject(2), eth(400), eth(1)).await.err();
+        assert!(matches!(rejection, Some(RiskRejection::Reserve(balance)) if balance == eth(600)));
+
+        drop(reservation);
+        assert_eq!(risk.state().balance, Some(eth(1000)));
+    }
+
+    #[tokio::test]
+    async fn kill_switch_stops_snipes_until_resumed() {
+        let risk = risk(|_| {});
+        risk.kill();
+        let rejection = risk.reserve(subject(1), eth(1), eth(1)).await.err();
+        assert!(matches!(rejection, Some(RiskRejection::KillSwitch)));
+
+        risk.resume();
+        assert!(risk.reserve(subject(1), eth(1), eth(1)).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn refresh_keeps_snipes_it_may_not_have_seen() {
+        let risk = risk(|_| {});
+        risk.add_positions(vec![subject(9)]).await;
+        risk.refreshed(Instant::now(), Some(eth(1000)), &[]);
+
+        // a refresh starts reading the chain, then a snipe is reserved and
+        // sent before it's done
+        let started = Instant::now();
+        risk.reserve(subject(1), eth(100), eth(1))
+            .await
+            .ok()
+            .unwrap()
+            .commit();
+        risk.refreshed(started, Some(eth(1000)), &[subject(1), subject(9)]);
+
+        let state = risk.state();
+        assert!(state.positions.contains_key(&subject(1)));
+        // held from before, and sold since
+        assert!(!state.positions.contains_key(&subject(9)));
+        assert_eq!(state.balance, Some(eth(900)));
+    }
+
+    #[tokio::test]
+    async fn refresh_drops_positions_sold_since_the_previous_one() {
+        let risk = risk(|_| {});
+        risk.reserve(subject(1), eth(100), eth(1))
+            .await
+            .ok()
+            .unwrap()
+            .commit();
+        // the snipe has landed by this one
+        risk.refreshed(Instant::now(), Some(eth(900)), &[]);
+        assert_eq!(risk.state().balance, Some(eth(800)));
+
+        // and this one sees what it bought sold, and the balance as is
+        risk.refreshed(Instant::now(), Some(eth(950)), &[subject(1)]);
+        let state = risk.state();
+        assert!(state.positions.is_empty());
+        assert_eq!(state.balance, Some(eth(950)));
+    }
+}
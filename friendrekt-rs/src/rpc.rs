@@ -0,0 +1,350 @@
+use crate::{config::RpcConfig, notify::Dependency};
+use async_trait::async_trait;
+use ethers::providers::{
+    Http, HttpClientError, JsonRpcClient, JsonRpcError, ProviderError, RpcError, Ws, WsClientError,
+};
+use serde::{de::DeserializeOwned, Serialize};
+use std::{
+    fmt,
+    sync::Arc,
+    time::{Duration, Instant},
+};
+use tokio::sync::RwLock;
+use tracing::{info, warn};
+
+enum Transport {
+    Ws(Ws),
+    Http(Http),
+}
+
+#[derive(Clone, Copy, Default)]
+struct Health {
+    head: u64,
+    latency: Duration,
+    // failed the last health check or request, ranked last until it recovers
+    failing: bool,
+}
+
+struct Endpoint {
+    url: String,
+    // host only, URLs tend to carry API keys
+    label: String,
+    is_ws: bool,
+    // websockets connect lazily so one that's down at startup can come back
+    transport: RwLock<Option<Arc<Transport>>>,
+    health: RwLock<Health>,
+    dependency: Dependency,
+}
+
+impl Endpoint {
+    fn new(url: &str) -> Self {
+        let label = reqwest::Url::parse(url)
+            .ok()
+            .and_then(|u| u.host_str().map(str::to_string))
+            .unwrap_or_else(|| "?".to_string());
+        let is_ws = url.starts_with("ws");
+        let transport = if is_ws {
+            None
+        } else {
+            // validated on load
+            Some(Arc::new(Transport::Http(url.parse().unwrap())))
+        };
+
+        Endpoint {
+            url: url.to_string(),
+            dependency: Dependency::new(format!("RPC {}", label)),
+            label,
+            is_ws,
+            transport: RwLock::new(transport),
+            health: RwLock::new(Health {
+                failing: true,
+                ..Health::default()
+            }),
+        }
+    }
+
+    async fn transport(&self, reconnects: usize) -> Option<Arc<Transport>> {
+        if let Some(transport) = self.transport.read().await.clone() {
+            return Some(transport);
+        }
+
+        match Ws::connect_with_reconnects(self.url.as_str(), reconnects).await {
+            Ok(ws) => {
+                let transport = Arc::new(Transport::Ws(ws));
+                *self.transport.write().await = Some(transport.clone());
+                info!(endpoint = %self.label, "Connected");
+                Some(transport)
+            }
+            Err(e) => {
+                warn!(endpoint = %self.label, "Failed to connect: {}", e);
+                None
+            }
+        }
+    }
+
+    async fn fail(&self, error: impl fmt::Display) {
+        self.dependency.down(error);
+        self.health.write().await.failing = true;
+        if self.is_ws {
+            // reconnect from scratch on the next health check
+            *self.transport.write().await = None;
+        }
+    }
+}
+
+struct Endpoints {
+    endpoints: Vec<Endpoint>,
+    // indices into endpoints, best first
+    ranking: RwLock<Vec<usize>>,
+    reconnects: usize,
+    max_head_lag: u64,
+}
+
+// A JSON-RPC client spread over every configured endpoint. Requests go to the
+// healthiest endpoint and move on to the next one when the transport fails;
+// a health check keeps the endpoints ranked by head lag and latency.
+#[derive(Clone)]
+pub struct Failover {
+    inner: Arc<Endpoints>,
+    // try HTTP endpoints first, e.g. for bulk requests we don't want on the
+    // websocket we're subscribed on
+    prefer_http: bool,
+}
+
+impl fmt::Debug for Failover {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Failover").finish_non_exhaustive()
+    }
+}
+
+impl Failover {
+    pub async fn connect(config: &RpcConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        let endpoints = config
+            .ws_urls()
+            .chain(config.http_urls.iter())
+            .map(|url| Endpoint::new(url))
+            .collect::<Vec<_>>();
+        let ranking = (0..endpoints.len()).collect();
+        let failover = Failover {
+            inner: Arc::new(Endpoints {
+                endpoints,
+                ranking: RwLock::new(ranking),
+                reconnects: config.reconnects,
+                max_head_lag: config.max_head_lag,
+            }),
+            prefer_http: false,
+        };
+
+        failover.check().await;
+        if failover.best_ws().await.is_none() {
+            return Err("couldn't connect to any websocket endpoint".into());
+        }
+        Ok(failover)
+    }
+
+    pub fn http_first(&self) -> Self {
+        Failover {
+            inner: self.inner.clone(),
+            prefer_http: true,
+        }
+    }
+
+    // Re-rank the endpoints every `interval`.
+    pub fn monitor(&self, interval: Duration) {
+        let failover = self.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                failover.check().await;
+            }
+        });
+    }
+
+    async fn check(&self) {
+        let inner = &self.inner;
+        for endpoint in &inner.endpoints {
+            let started = Instant::now();
+            let head = match endpoint.transport(inner.reconnects).await {
+                Some(transport) => {
+                    request::<_, ethers::types::U64>(&transport, "eth_blockNumber", ())
+                        .await
+                        .ok()
+                }
+                None => None,
+            };
+
+            match head {
+                Some(head) => {
+                    endpoint.dependency.up();
+                    *endpoint.health.write().await = Health {
+                        head: head.as_u64(),
+                        latency: started.elapsed(),
+                        failing: false,
+                    }
+                }
+                None => endpoint.fail("health check failed").await,
+            }
+        }
+
+        let mut health = Vec::new();
+        for endpoint in &inner.endpoints {
+            health.push(*endpoint.health.read().await);
+        }
+        let head = health.iter().map(|h| h.head).max().unwrap_or_default();
+
+        let mut ranking = (0..health.len()).collect::<Vec<_>>();
+        ranking.sort_by_key(|&i| {
+            let h = &health[i];
+            let lag = head - h.head;
+            (h.failing, lag > inner.max_head_lag, lag, h.latency)
+        });
+
+        let mut current = inner.ranking.write().await;
+        if current.first() != ranking.first() {
+            let best = ranking[0];
+            let h = &health[best];
+            info!(
+                endpoint = %inner.endpoints[best].label,
+                head = h.head,
+                lag = head - h.head,
+                latency_ms = h.latency.as_millis() as u64,
+                "Preferring endpoint"
+            );
+        }
+        *current = ranking;
+    }
+
+    // The best websocket to subscribe on, and its label.
+    pub async fn best_ws(&self) -> Option<(String, Ws)> {
+        let ranking = self.inner.ranking.read().await.clone();
+        for i in ranking {
+            let endpoint = &self.inner.endpoints[i];
+            if !endpoint.is_ws || endpoint.health.read().await.failing {
+                continue;
+            }
+            if let Some(transport) = endpoint.transport(self.inner.reconnects).await {
+                if let Transport::Ws(ws) = transport.as_ref() {
+                    return Some((endpoint.label.clone(), ws.clone()));
+                }
+            }
+        }
+
+        None
+    }
+
+    // Mark the websocket we were subscribed on as failing.
+    pub async fn report_ws(&self, label: &str) {
+        if let Some(endpoint) = self
+            .inner
+            .endpoints
+            .iter()
+            .find(|e| e.is_ws && e.label == label)
+        {
+            endpoint.fail("subscription stalled or ended").await;
+        }
+    }
+
+    async fn order(&self) -> Vec<usize> {
+        let mut ranking = self.inner.ranking.read().await.clone();
+        if self.prefer_http {
+            // stable, so still ranked within each kind
+            ranking.sort_by_key(|&i| self.inner.endpoints[i].is_ws);
+        }
+        ranking
+    }
+}
+
+async fn request<T, R>(transport: &Transport, method: &str, params: T) -> Result<R, FailoverError>
+where
+    T: fmt::Debug + Serialize + Send + Sync,
+    R: DeserializeOwned + Send,
+{
+    match transport {
+        Transport::Ws(ws) => ws.request(method, params).await.map_err(FailoverError::Ws),
+        Transport::Http(http) => http
+            .request(method, params)
+            .await
+            .map_err(FailoverError::Http),
+    }
+}
+
+#[async_trait]
+impl JsonRpcClient for Failover {
+    type Error = FailoverError;
+
+    async fn request<T, R>(&self, method: &str, params: T) -> Result<R, Self::Error>
+    where
+        T: fmt::Debug + Serialize + Send + Sync,
+        R: DeserializeOwned + Send,
+    {
+        // serialized once so it can be sent to more than one endpoint
+        let params = serde_json::to_value(params).map_err(FailoverError::Serde)?;
+        let mut last = FailoverError::NoEndpoints;
+        for i in self.order().await {
+            let endpoint = &self.inner.endpoints[i];
+            let transport = match endpoint.transport.read().await.clone() {
+                Some(transport) => transport,
+                None => continue,
+            };
+
+            match request(&transport, method, &params).await {
+                Ok(result) => return Ok(result),
+                // the node answered, another one won't answer differently
+                Err(e) if e.is_error_response() || e.is_serde_error() => return Err(e),
+                Err(e) => {
+                    warn!(method, endpoint = %endpoint.label, "Request failed: {}", e);
+                    endpoint.fail(&e).await;
+                    last = e;
+                }
+            }
+        }
+
+        Err(last)
+    }
+}
+
+#[derive(Debug)]
+pub enum FailoverError {
+    Ws(WsClientError),
+    Http(HttpClientError),
+    Serde(serde_json::Error),
+    NoEndpoints,
+}
+
+impl fmt::Display for FailoverError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FailoverError::Ws(e) => write!(f, "{}", e),
+            FailoverError::Http(e) => write!(f, "{}", e),
+            FailoverError::Serde(e) => write!(f, "{}", e),
+            FailoverError::NoEndpoints => write!(f, "no RPC endpoint available"),
+        }
+    }
+}
+
+impl std::error::Error for FailoverError {}
+
+impl RpcError for FailoverError {
+    fn as_error_response(&self) -> Option<&JsonRpcError> {
+        match self {
+            FailoverError::Ws(e) => e.as_error_response(),
+            FailoverError::Http(e) => e.as_error_response(),
+            _ => None,
+        }
+    }
+
+    fn as_serde_error(&self) -> Option<&serde_json::Error> {
+        match self {
+            FailoverError::Ws(e) => e.as_serde_error(),
+            FailoverError::Http(e) => e.as_serde_error(),
+            FailoverError::Serde(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<FailoverError> for ProviderError {
+    fn from(e: FailoverError) -> Self {
+        ProviderError::JsonRpcClientError(Box::new(e))
+    }
+}
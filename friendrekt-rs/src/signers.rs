@@ -0,0 +1,247 @@
+use crate::{
+    keys::{Key, KeyError},
+    math,
+    metrics::METRICS,
+    notify::{notify, Event},
+};
+use ethers::{prelude::*, types::transaction::eip2718::TypedTransaction};
+use serde::{Deserialize, Serialize};
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+use tokio::sync::Mutex;
+use tracing::{info, warn};
+
+// Which wallet of the pool sends the next snipe.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Selection {
+    // each in turn
+    #[default]
+    RoundRobin,
+    // the one with the fewest snipes waiting to be mined
+    LeastPending,
+}
+
+// Top hot wallets back up to `to` from the treasury once they drop below
+// `below`.
+#[derive(Clone, Copy)]
+pub struct Refill {
+    pub below: U256,
+    pub to: U256,
+}
+
+struct Wallet {
+    signer: Key,
+    // the next nonce to use, None until fetched and after a snipe failed
+    nonce: Mutex<Option<U256>>,
+    // snipes sent and not mined yet
+    pending: AtomicUsize,
+    // as of the last balance check, None before the first
+    balance: std::sync::Mutex<Option<U256>>,
+}
+
+impl Wallet {
+    fn label(&self) -> String {
+        format!("{:?}", self.signer.address())
+    }
+
+    fn balance(&self) -> Option<U256> {
+        *self.balance.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+// Hot wallets snipes are sent from, each with its own nonce sequence so
+// snipes from different wallets don't wait on each other. Every one of them
+// has to be allowlisted on the Sniper.
+pub struct SignerPool {
+    wallets: Vec<Arc<Wallet>>,
+    selection: Selection,
+    next: AtomicUsize,
+}
+
+// A wallet taken for one snipe along with the nonce reserved for it. Counts
+// as pending until dropped, so keep it until the snipe is mined or dropped.
+pub struct Lease {
+    wallet: Arc<Wallet>,
+    pub nonce: U256,
+}
+
+impl Lease {
+    pub fn address(&self) -> Address {
+        self.wallet.signer.address()
+    }
+
+    pub async fn sign(&self, tx: &TypedTransaction) -> Result<Signature, KeyError> {
+        self.wallet.signer.sign_transaction(tx).await
+    }
+
+    // The snipe never made it on chain, so its nonce may not have been used.
+    // Fetch it again before the wallet's next snipe.
+    pub async fn failed(&self) {
+        *self.wallet.nonce.lock().await = None;
+    }
+}
+
+impl Drop for Lease {
+    fn drop(&mut self) {
+        self.wallet.pending.fetch_sub(1, Ordering::SeqCst);
+        METRICS
+            .signer_pending
+            .with_label_values(&[&self.wallet.label()])
+            .dec();
+    }
+}
+
+impl SignerPool {
+    pub fn new(signers: Vec<Key>, selection: Selection) -> Arc<Self> {
+        Arc::new(SignerPool {
+            wallets: signers
+                .into_iter()
+                .map(|signer| {
+                    Arc::new(Wallet {
+                        signer,
+                        nonce: Mutex::new(None),
+                        pending: AtomicUsize::new(0),
+                        balance: std::sync::Mutex::new(None),
+                    })
+                })
+                .collect(),
+            selection,
+            next: AtomicUsize::new(0),
+        })
+    }
+
+    pub fn addresses(&self) -> Vec<Address> {
+        self.wallets.iter().map(|w| w.signer.address()).collect()
+    }
+
+    // Take a wallet for a snipe that may cost up to `cost` in gas and value.
+    // Wallets last seen with less than that are passed over. Errs if none
+    // is left, or the chosen one's nonce can't be fetched.
+    pub async fn acquire<M: Middleware>(&self, client: &M, cost: U256) -> Result<Lease, String> {
+        let start = self.next.fetch_add(1, Ordering::SeqCst);
+        let mut funded = (0..self.wallets.len())
+            .map(|i| &self.wallets[(start + i) % self.wallets.len()])
+            .filter(|w| w.balance().is_none_or(|balance| balance >= cost));
+        let wallet = match self.selection {
+            Selection::RoundRobin => funded.next(),
+            // ties go to the next in turn
+            Selection::LeastPending => funded.min_by_key(|w| w.pending.load(Ordering::SeqCst)),
+        }
+        .ok_or("no signer has enough ETH for gas")?
+        .clone();
+
+        let nonce = {
+            let mut next = wallet.nonce.lock().await;
+            let nonce = match *next {
+                Some(nonce) => nonce,
+                None => client
+                    .get_transaction_count(
+                        wallet.signer.address(),
+                        Some(BlockNumber::Pending.into()),
+                    )
+                    .await
+                    .map_err(|e| format!("failed to get nonce: {}", e))?,
+            };
+            *next = Some(nonce + 1);
+            nonce
+        };
+
+        wallet.pending.fetch_add(1, Ordering::SeqCst);
+        METRICS
+            .signer_pending
+            .with_label_values(&[&wallet.label()])
+            .inc();
+        Ok(Lease { wallet, nonce })
+    }
+
+    // Keep every wallet's balance up to date and, with `refill`, top up the
+    // ones running low from the treasury, i.e. the operator wallet `client`
+    // signs with. In a dry run refills are only logged.
+    pub async fn watch<M: Middleware>(
+        &self,
+        client: &M,
+        treasury: Address,
+        refill: Option<Refill>,
+        interval: Duration,
+        dry_run: bool,
+    ) {
+        loop {
+            for wallet in &self.wallets {
+                let address = wallet.signer.address();
+                let balance = match client.get_balance(address, None).await {
+                    Ok(balance) => balance,
+                    Err(e) => {
+                        warn!(wallet = ?address, "Failed to get signer balance: {}", e);
+                        continue;
+                    }
+                };
+                *wallet.balance.lock().unwrap_or_else(|e| e.into_inner()) = Some(balance);
+                METRICS
+                    .signer_balance
+                    .with_label_values(&[&wallet.label()])
+                    .set(math::wei_to_eth(balance));
+
+                let Some(refill) = refill else { continue };
+                // the treasury can't top itself up
+                if balance >= refill.below || address == treasury {
+                    continue;
+                }
+                let amount = refill.to - balance;
+                if dry_run {
+                    info!(
+                        wallet = ?address,
+                        amount_eth = math::wei_to_eth(amount),
+                        "Would refill signer"
+                    );
+                    continue;
+                }
+                match send(client, treasury, address, amount).await {
+                    Ok(hash) => {
+                        info!(
+                            wallet = ?address,
+                            amount_eth = math::wei_to_eth(amount),
+                            tx = ?hash,
+                            "Refilled signer"
+                        );
+                        notify(Event::Refilled {
+                            wallet: address,
+                            amount,
+                        });
+                    }
+                    Err(error) => {
+                        warn!(wallet = ?address, "Failed to refill signer: {}", error);
+                        notify(Event::RefillFailed {
+                            wallet: address,
+                            error,
+                        });
+                    }
+                }
+            }
+            tokio::time::sleep(interval).await;
+        }
+    }
+}
+
+// Send `amount` from the treasury and wait for it to be mined, so the next
+// balance check sees it.
+async fn send<M: Middleware>(
+    client: &M,
+    treasury: Address,
+    to: Address,
+    amount: U256,
+) -> Result<Option<H256>, String> {
+    let tx = TransactionRequest::pay(to, amount).from(treasury);
+    let receipt = client
+        .send_transaction(tx, None)
+        .await
+        .map_err(|e| e.to_string())?
+        .await
+        .map_err(|e| e.to_string())?;
+    Ok(receipt.map(|r| r.transaction_hash))
+}
@@ -0,0 +1,66 @@
+use crate::{
+    metrics::{self, Outcome},
+    notify::{notify, Event},
+};
+use ethers::prelude::*;
+use std::{collections::VecDeque, sync::Arc};
+use tokio::sync::Mutex;
+
+// How many mined snipes we remember. Reorgs on Base are a few blocks deep at
+// most, and we don't mine anywhere near this many snipes in that time.
+const MAX_MINED: usize = 1024;
+
+// Our snipes that were mined, with their block, for as long as a reorg could
+// still take that block back out.
+pub struct Snipes {
+    mined: Mutex<VecDeque<(H256, H256)>>,
+}
+
+impl Snipes {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Snipes {
+            mined: Mutex::new(VecDeque::new()),
+        })
+    }
+
+    // Wait for a snipe to be mined and notify how it went.
+    pub async fn follow<M: Middleware>(&self, client: &M, hash: H256) -> Outcome {
+        let outcome = metrics::track_snipe(client, hash).await;
+        let event = match outcome {
+            Outcome::Landed { block } => {
+                self.mined(block, hash).await;
+                Event::Filled { hash }
+            }
+            Outcome::Reverted { block } => {
+                self.mined(block, hash).await;
+                Event::Reverted { hash }
+            }
+            Outcome::Dropped => Event::Dropped { hash },
+        };
+        notify(event);
+        outcome
+    }
+
+    async fn mined(&self, block: H256, hash: H256) {
+        let mut mined = self.mined.lock().await;
+        if mined.len() == MAX_MINED {
+            mined.pop_front();
+        }
+        mined.push_back((block, hash));
+    }
+
+    // The snipes that had been mined in `blocks`, which a reorg just took
+    // out. They're forgotten here, follow them again to see where they end up.
+    pub async fn orphaned(&self, blocks: &[H256]) -> Vec<H256> {
+        let mut orphaned = Vec::new();
+        self.mined.lock().await.retain(|(block, hash)| {
+            if blocks.contains(block) {
+                orphaned.push(*hash);
+                false
+            } else {
+                true
+            }
+        });
+        orphaned
+    }
+}
@@ -0,0 +1,34 @@
+use crate::config::{self, ConfigError, Tier};
+use std::sync::Arc;
+use tokio::sync::RwLock;
+use tracing::info;
+
+// Follower tiers, which the admin API can change while we run.
+pub struct Strategy {
+    tiers: RwLock<Vec<Tier>>,
+}
+
+impl Strategy {
+    // `tiers` are validated and sorted on load.
+    pub fn new(tiers: Vec<Tier>) -> Arc<Self> {
+        Arc::new(Strategy {
+            tiers: RwLock::new(tiers),
+        })
+    }
+
+    pub async fn tiers(&self) -> Vec<Tier> {
+        self.tiers.read().await.clone()
+    }
+
+    pub async fn set_tiers(&self, mut tiers: Vec<Tier>) -> Result<(), ConfigError> {
+        config::check_tiers(&mut tiers)?;
+        info!(?tiers, "Strategy tiers changed");
+        *self.tiers.write().await = tiers;
+        Ok(())
+    }
+
+    // Supply limit for a subject with this many followers, 0 if we shouldn't buy.
+    pub async fn supply_limit(&self, followers: u64) -> u64 {
+        config::supply_limit(&self.tiers.read().await, followers)
+    }
+}
@@ -0,0 +1,226 @@
+use std::{
+    fmt,
+    future::Future,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
+use tokio::sync::{mpsc, watch, Mutex};
+use tracing::{error, info, warn};
+
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+// a task that ran this long before failing starts over with a clean slate
+const HEALTHY_AFTER: Duration = Duration::from_secs(60);
+
+// How a supervised task stopped. Any error converts into Retry, so tasks can
+// use `?` and only spell out the fatal cases.
+#[derive(Debug)]
+pub enum TaskError {
+    Retry(String),
+    Fatal(String),
+}
+
+impl<E: std::error::Error> From<E> for TaskError {
+    fn from(e: E) -> Self {
+        TaskError::Retry(e.to_string())
+    }
+}
+
+impl fmt::Display for TaskError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            TaskError::Retry(e) | TaskError::Fatal(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+// Handed to tasks so they can stop picking up new work on shutdown.
+#[derive(Clone)]
+pub struct Shutdown(watch::Receiver<bool>);
+
+impl Shutdown {
+    pub fn is_shutdown(&self) -> bool {
+        *self.0.borrow()
+    }
+
+    pub async fn wait(&mut self) {
+        let _ = self.0.wait_for(|shutdown| *shutdown).await;
+    }
+}
+
+// Held by a snipe in progress, shutdown waits for all of them to be dropped.
+pub struct InFlight(Arc<AtomicUsize>);
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::SeqCst);
+    }
+}
+
+// Owns the long running tasks of the bot. Failed tasks are restarted with
+// backoff, a task failing fatally (or too often in a row) brings the whole
+// process down, and SIGINT/SIGTERM stop new work and drain the snipes in
+// flight before exiting.
+pub struct Supervisor {
+    shutdown: watch::Sender<bool>,
+    fatal: mpsc::UnboundedSender<String>,
+    fatal_rx: Mutex<mpsc::UnboundedReceiver<String>>,
+    in_flight: Arc<AtomicUsize>,
+    max_restarts: u32,
+    drain_timeout: Duration,
+}
+
+impl Supervisor {
+    pub fn new(max_restarts: u32, drain_timeout: Duration) -> Arc<Self> {
+        let (shutdown, _) = watch::channel(false);
+        let (fatal, fatal_rx) = mpsc::unbounded_channel();
+        Arc::new(Supervisor {
+            shutdown,
+            fatal,
+            fatal_rx: Mutex::new(fatal_rx),
+            in_flight: Arc::new(AtomicUsize::new(0)),
+            max_restarts,
+            drain_timeout,
+        })
+    }
+
+    pub fn shutdown(&self) -> Shutdown {
+        Shutdown(self.shutdown.subscribe())
+    }
+
+    pub fn track(&self) -> InFlight {
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+        InFlight(self.in_flight.clone())
+    }
+
+    // Run a one-off task, logging it if it fails or panics.
+    pub fn spawn<F>(&self, name: &'static str, task: F)
+    where
+        F: Future<Output = Result<(), TaskError>> + Send + 'static,
+    {
+        let fatal = self.fatal.clone();
+        tokio::spawn(async move {
+            match tokio::spawn(task).await {
+                Ok(Ok(())) => {}
+                Ok(Err(TaskError::Fatal(e))) => {
+                    let _ = fatal.send(format!("{}: {}", name, e));
+                }
+                Ok(Err(TaskError::Retry(e))) => warn!(task = name, "Task failed: {}", e),
+                Err(e) => error!(task = name, "Task panicked: {}", e),
+            }
+        });
+    }
+
+    // Run a task until shutdown, starting it again whenever it stops.
+    pub fn supervise<F, Fut>(self: &Arc<Self>, name: &'static str, task: F)
+    where
+        F: Fn(Shutdown) -> Fut + Send + 'static,
+        Fut: Future<Output = Result<(), TaskError>> + Send + 'static,
+    {
+        let supervisor = self.clone();
+        tokio::spawn(async move {
+            let mut shutdown = supervisor.shutdown();
+            let mut backoff = MIN_BACKOFF;
+            let mut failures = 0;
+            loop {
+                let started = Instant::now();
+                let result = tokio::spawn(task(shutdown.clone())).await;
+                if shutdown.is_shutdown() {
+                    return;
+                }
+
+                let reason = match result {
+                    Ok(Ok(())) => "exited".to_string(),
+                    Ok(Err(TaskError::Retry(e))) => e,
+                    Ok(Err(TaskError::Fatal(e))) => {
+                        let _ = supervisor.fatal.send(format!("{}: {}", name, e));
+                        return;
+                    }
+                    Err(e) => format!("panicked: {}", e),
+                };
+
+                if started.elapsed() >= HEALTHY_AFTER {
+                    backoff = MIN_BACKOFF;
+                    failures = 0;
+                }
+                failures += 1;
+                if failures > supervisor.max_restarts {
+                    let _ = supervisor.fatal.send(format!(
+                        "{} failed {} times in a row, last: {}",
+                        name, failures, reason
+                    ));
+                    return;
+                }
+
+                warn!(
+                    task = name,
+                    %reason,
+                    backoff_ms = backoff.as_millis() as u64,
+                    "Task stopped, restarting"
+                );
+                tokio::select! {
+                    _ = tokio::time::sleep(backoff) => {}
+                    _ = shutdown.wait() => return,
+                }
+                backoff = (backoff * 2).min(MAX_BACKOFF);
+            }
+        });
+    }
+
+    // Wait for a signal or a fatal error, then shut down: tell every task to
+    // stop taking new work and give in-flight snipes a chance to finish.
+    pub async fn wait(&self) -> Result<(), Box<dyn std::error::Error>> {
+        let mut fatal = self.fatal_rx.lock().await;
+        let result = tokio::select! {
+            _ = signal() => Ok(()),
+            Some(e) = fatal.recv() => Err(e),
+        };
+
+        match &result {
+            Ok(()) => info!("Shutting down"),
+            Err(e) => error!("Fatal error, shutting down: {}", e),
+        }
+        let _ = self.shutdown.send(true);
+
+        let started = Instant::now();
+        loop {
+            let in_flight = self.in_flight.load(Ordering::SeqCst);
+            if in_flight == 0 {
+                break;
+            }
+            if started.elapsed() >= self.drain_timeout {
+                warn!(in_flight, "Gave up waiting for snipes in flight");
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+
+        info!("Stopped");
+        result.map_err(|e| e.into())
+    }
+}
+
+#[cfg(unix)]
+async fn signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    match signal(SignalKind::terminate()) {
+        Ok(mut term) => {
+            tokio::select! {
+                _ = tokio::signal::ctrl_c() => {}
+                _ = term.recv() => {}
+            }
+        }
+        Err(_) => {
+            let _ = tokio::signal::ctrl_c().await;
+        }
+    }
+}
+
+#[cfg(not(unix))]
+async fn signal() {
+    let _ = tokio::signal::ctrl_c().await;
+}
@@ -0,0 +1,286 @@
+use crate::{config::MAX_SUPPLY_LIMIT, math, prod_kosetto::TwitterInfo};
+use ethers::{types::Address, types::U256, utils::parse_ether};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use tokio::sync::RwLock;
+use tracing::{error, info};
+
+// One subject we want to snipe regardless of followers. At least one of
+// handle, twitter_user_id or address identifies it, the rest are optional
+// overrides of the global strategy.
+#[derive(Clone, Debug, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct WatchEntry {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub handle: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub twitter_user_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub address: Option<Address>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amount: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub supply_limit: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub max_eth: Option<f64>,
+}
+
+impl WatchEntry {
+    // Checked when adding an entry and on every reload of the file.
+    fn check(&self) -> Result<(), String> {
+        if self.handle.is_none() && self.twitter_user_id.is_none() && self.address.is_none() {
+            return Err("an entry needs a handle, twitter_user_id or address".to_string());
+        }
+        if self.amount == Some(0) {
+            return Err("amount must be at least 1".to_string());
+        }
+        if matches!(self.supply_limit, Some(limit) if !(1..=MAX_SUPPLY_LIMIT).contains(&limit)) {
+            return Err(format!(
+                "supply_limit must be from 1 to {}",
+                MAX_SUPPLY_LIMIT
+            ));
+        }
+        if matches!(self.max_eth, Some(eth) if !eth.is_finite() || eth <= 0.0 || parse_ether(eth).is_err())
+        {
+            return Err("max_eth must be a positive amount".to_string());
+        }
+        Ok(())
+    }
+
+    fn matches(&self, handle: &str, twitter_user_id: &str) -> bool {
+        let handle_match = self
+            .handle
+            .as_ref()
+            .map(|h| h.trim_start_matches('@').eq_ignore_ascii_case(handle))
+            .unwrap_or(false);
+        let id_match = self
+            .twitter_user_id
+            .as_ref()
+            .map(|id| id == twitter_user_id)
+            .unwrap_or(false);
+
+        handle_match || id_match
+    }
+
+    // Whether `key` is this entry's handle, twitter user id or address.
+    fn is(&self, key: &str) -> bool {
+        let address = key.parse::<Address>().ok();
+        self.matches(key.trim_start_matches('@'), key)
+            || (address.is_some() && self.address == address)
+    }
+
+    pub fn label(&self) -> String {
+        if let Some(handle) = &self.handle {
+            return handle.clone();
+        }
+        if let Some(id) = &self.twitter_user_id {
+            return id.clone();
+        }
+        format!("{:?}", self.address.unwrap_or_default())
+    }
+
+    // Twitter info for a watched subject we never looked up. No followers
+    // are known, the limits come from the entry.
+    pub fn info(&self) -> TwitterInfo {
+        TwitterInfo {
+            twitter_username: self.label(),
+            twitter_user_id: self.twitter_user_id.clone().unwrap_or_default(),
+            followers: 0,
+        }
+    }
+
+    // Amount and supply limit to snipe with, after applying the overrides
+    // and fitting them into max_eth. None if nothing fits the budget.
+    pub fn limits(&self, default_amount: U256, default_supply_limit: u64) -> Option<(U256, u64)> {
+        let amount = self.amount.map(U256::from).unwrap_or(default_amount);
+        let supply_limit = self.supply_limit.unwrap_or(default_supply_limit);
+
+        match self.max_eth {
+            Some(max_eth) => {
+                let max_spend = parse_ether(max_eth).ok()?;
+                math::fit_to_budget(amount, supply_limit, max_spend)
+            }
+            None => Some((amount, supply_limit)),
+        }
+    }
+}
+
+struct Inner {
+    entries: Vec<WatchEntry>,
+    modified: Option<SystemTime>,
+    // (handle, twitter user id) of addresses that matched an entry
+    resolved: HashMap<Address, (String, String)>,
+}
+
+// Subjects from the watchlist file, reloaded whenever the file changes.
+pub struct Watchlist {
+    path: PathBuf,
+    inner: RwLock<Inner>,
+}
+
+impl Watchlist {
+    pub async fn load(path: PathBuf) -> Arc<Self> {
+        let watchlist = Arc::new(Watchlist {
+            path,
+            inner: RwLock::new(Inner {
+                entries: Vec::new(),
+                modified: None,
+                resolved: HashMap::new(),
+            }),
+        });
+        watchlist.reload().await;
+        watchlist
+    }
+
+    // A watchlist that never has any entries.
+    pub fn empty() -> Arc<Self> {
+        Arc::new(Watchlist {
+            path: PathBuf::new(),
+            inner: RwLock::new(Inner {
+                entries: Vec::new(),
+                modified: None,
+                resolved: HashMap::new(),
+            }),
+        })
+    }
+
+    // Poll the file for changes every `interval`.
+    pub fn watch(self: &Arc<Self>, interval: Duration) {
+        let watchlist = self.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::time::sleep(interval).await;
+                watchlist.reload().await;
+            }
+        });
+    }
+
+    pub async fn entries(&self) -> Vec<WatchEntry> {
+        self.inner.read().await.entries.clone()
+    }
+
+    // Add an entry to the watchlist file and load it straight away.
+    pub async fn add(&self, entry: WatchEntry) -> Result<(), Box<dyn std::error::Error>> {
+        entry.check()?;
+
+        let mut entries = self.read_file()?;
+        info!(entry = %entry.label(), "Adding watchlist entry");
+        entries.push(entry);
+        self.write_file(&entries).await
+    }
+
+    // Remove the entries with this handle, twitter user id or address from
+    // the watchlist file. Returns how many were removed.
+    pub async fn remove(&self, key: &str) -> Result<usize, Box<dyn std::error::Error>> {
+        let mut entries = self.read_file()?;
+        let before = entries.len();
+        entries.retain(|e| !e.is(key));
+        let removed = before - entries.len();
+        if removed > 0 {
+            info!(key, removed, "Removing watchlist entries");
+            self.write_file(&entries).await?;
+        }
+        Ok(removed)
+    }
+
+    fn read_file(&self) -> Result<Vec<WatchEntry>, Box<dyn std::error::Error>> {
+        if self.path.as_os_str().is_empty() {
+            return Err("the watchlist is disabled".into());
+        }
+        match std::fs::read_to_string(&self.path) {
+            Ok(data) => Ok(serde_json::from_str(&data)?),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(Vec::new()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn write_file(&self, entries: &[WatchEntry]) -> Result<(), Box<dyn std::error::Error>> {
+        std::fs::write(&self.path, serde_json::to_string_pretty(entries)?)?;
+        // don't wait for the next poll
+        self.inner.write().await.modified = None;
+        self.reload().await;
+        Ok(())
+    }
+
+    async fn reload(&self) {
+        let modified = std::fs::metadata(&self.path)
+            .and_then(|m| m.modified())
+            .ok();
+        if modified == self.inner.read().await.modified {
+            return;
+        }
+
+        let entries = match std::fs::read_to_string(&self.path) {
+            Ok(data) => match serde_json::from_str::<Vec<WatchEntry>>(&data) {
+                Ok(entries) => entries,
+                Err(e) => {
+                    // keep what we had, the file is probably mid-edit
+                    error!(path = ?self.path, "Failed to parse watchlist: {}", e);
+                    return;
+                }
+            },
+            Err(_) => Vec::new(),
+        };
+        // same as a file that doesn't parse, rather than sniping without
+        // the limits meant for the entry
+        for entry in &entries {
+            if let Err(e) = entry.check() {
+                error!(path = ?self.path, entry = %entry.label(), "Invalid watchlist entry: {}", e);
+                return;
+            }
+        }
+
+        info!(path = ?self.path, entries = entries.len(), "Loaded watchlist");
+        let mut inner = self.inner.write().await;
+        inner.entries = entries;
+        inner.modified = modified;
+    }
+
+    // Entry for an address, either listed directly or resolved earlier by
+    // a Kosetto lookup.
+    pub async fn get(&self, address: &Address) -> Option<WatchEntry> {
+        let inner = self.inner.read().await;
+        let resolved = inner.resolved.get(address);
+        inner
+            .entries
+            .iter()
+            .find(|e| {
+                e.address == Some(*address)
+                    || resolved
+                        .map(|(handle, id)| e.matches(handle, id))
+                        .unwrap_or(false)
+            })
+            .cloned()
+    }
+
+    // Called with every Kosetto lookup. If the user is on the watchlist we
+    // remember their address so later txs skip the lookup altogether.
+    pub async fn observe(
+        &self,
+        address: Address,
+        handle: &str,
+        twitter_user_id: &str,
+    ) -> Option<WatchEntry> {
+        if let Some(entry) = self.get(&address).await {
+            return Some(entry);
+        }
+
+        let mut inner = self.inner.write().await;
+        let entry = inner
+            .entries
+            .iter()
+            .find(|e| e.matches(handle, twitter_user_id))
+            .cloned()?;
+
+        info!(entry = %entry.label(), ?address, "Resolved watchlist entry");
+        inner
+            .resolved
+            .insert(address, (handle.to_string(), twitter_user_id.to_string()));
+        Some(entry)
+    }
+}
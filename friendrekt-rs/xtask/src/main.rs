@@ -0,0 +1,71 @@
+// Repo chores, run from friendrekt-rs as `cargo xtask <task>`. build.rs only
+// checks what these write.
+use ethers_contract_abigen::MultiAbigen;
+use serde::Serialize;
+use serde_json::Value;
+use std::{
+    env,
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+    process::exit,
+};
+
+// relative to friendrekt-rs, like in build.rs
+const SNIPER_ARTIFACT: &str = "../friendrekt-contracts/out/Sniper.sol/Sniper.json";
+const ABI: &str = "abi";
+const BINDINGS: &str = "src/bindings";
+
+const USAGE: &str = "usage: cargo xtask <task>
+
+tasks:
+  bindings  take the Sniper ABI from the compiled contracts and regenerate src/bindings";
+
+fn main() {
+    let result = match env::args().nth(1).as_deref() {
+        Some("bindings") => bindings(),
+        _ => {
+            eprintln!("{}", USAGE);
+            exit(2);
+        }
+    };
+    if let Err(e) = result {
+        eprintln!("{}", e);
+        exit(1);
+    }
+}
+
+// friendrekt-rs, wherever cargo was run from
+fn root() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("..")
+}
+
+// After changing Sniper.sol and running `forge build`: copy its ABI to abi/
+// and regenerate the bindings of everything in abi/, for committing both.
+fn bindings() -> Result<(), Box<dyn Error>> {
+    let root = root();
+    let path = root.join(SNIPER_ARTIFACT);
+    let artifact = fs::read_to_string(&path).map_err(|e| {
+        format!(
+            "can't read {:?}, build the contracts with `forge build` first: {}",
+            path, e
+        )
+    })?;
+    let artifact: Value = serde_json::from_str(&artifact)?;
+    if !artifact["abi"].is_array() {
+        return Err(format!("{:?} has no ABI", path).into());
+    }
+
+    // indented like the rest of abi/
+    let mut abi = Vec::new();
+    let formatter = serde_json::ser::PrettyFormatter::with_indent(b"    ");
+    let mut serializer = serde_json::Serializer::with_formatter(&mut abi, formatter);
+    artifact["abi"].serialize(&mut serializer)?;
+    fs::write(root.join(ABI).join("sniper.json"), abi)?;
+
+    MultiAbigen::from_json_files(root.join(ABI))?
+        .build()?
+        .write_to_module(root.join(BINDINGS), false)?;
+    println!("Updated {}/sniper.json and {}, commit both", ABI, BINDINGS);
+    Ok(())
+}